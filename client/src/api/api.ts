@@ -1,30 +1,70 @@
+// every endpoint answers errors with a stable code, see the Readme
+export class ApiError extends Error {
+    code: string;
+
+    constructor(code: string, message: string) {
+        super(message);
+        this.code = code;
+    }
+}
+
+// the token is passed once in the url, like `/?token=...`, and remembered afterwards
+export function token(): string {
+    const fromUrl = new URLSearchParams(window.location.search).get('token');
+    if (fromUrl) localStorage.setItem('token', fromUrl);
+    return fromUrl ?? localStorage.getItem('token') ?? '';
+}
+
+export function wsUrl(): string {
+    const protocol = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    return import.meta.env.VITE_WS_URL ?? `${protocol}://${window.location.host}/ws/`;
+}
+
+function authHeaders(): Record<string, string> {
+    return { 'Authorization': `Bearer ${token()}` };
+}
+
+async function json(response: Response) {
+    const body = await response.json();
+    if (!response.ok) throw new ApiError(body.code ?? 'internal', body.message ?? response.statusText);
+    return body;
+}
+
 export default class Api {
-    static #apiUrl: string = 'http://localhost:8080/api';
+    // served by the server itself the api has the same origin
+    static #apiUrl: string = import.meta.env.VITE_API_URL ?? '/api';
 
     static async getNotebook() {
-        const response = await fetch(`${this.#apiUrl}/`);
-        return await response.json();
+        const response = await fetch(`${this.#apiUrl}/`, { headers: authHeaders() });
+        return await json(response);
     }
 
     static async evalCell(notebookUuid: string, cellUuid: string, content: string) {
         const response = await fetch(`${this.#apiUrl}/eval`, {
             method: 'POST',
             headers: {
+                ...authHeaders(),
                 'Content-Type': 'application/json'
             },
             body: JSON.stringify({ notebookUuid, cellUuid, content })
         });
-        return await response.json();
+        return await json(response);
+    }
+
+    static async getVariables(notebookUuid: string) {
+        const response = await fetch(`${this.#apiUrl}/notebooks/${notebookUuid}/variables`, { headers: authHeaders() });
+        return await json(response);
     }
 
     static async reorderCells(notebookUuid: string, newOrder: string[]) {
         const response = await fetch(`${this.#apiUrl}/reorder`, {
             method: 'POST',
             headers: {
+                ...authHeaders(),
                 'Content-Type': 'application/json'
             },
             body: JSON.stringify({ notebookUuid, newOrder })
         });
-        return await response.json();
+        return await json(response);
     }
 }
\ No newline at end of file
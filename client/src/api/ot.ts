@@ -0,0 +1,180 @@
+import { WsCmds, WsMessage, nextRequestId } from "./ws";
+
+// lengths are counted in characters (code points), like on the server
+export type OpT = { retain: number } | { insert: string } | { delete: number };
+
+function chars(text: string): string[] {
+    return Array.from(text);
+}
+
+function push(ops: OpT[], op: OpT) {
+    const last = ops[ops.length - 1];
+    if ("retain" in op && op.retain === 0) return;
+    if ("delete" in op && op.delete === 0) return;
+    if ("insert" in op && op.insert === "") return;
+
+    if (last && "retain" in last && "retain" in op) last.retain += op.retain;
+    else if (last && "delete" in last && "delete" in op) last.delete += op.delete;
+    else if (last && "insert" in last && "insert" in op) last.insert += op.insert;
+    else ops.push({ ...op });
+}
+
+function length(op: OpT): number {
+    if ("retain" in op) return op.retain;
+    if ("delete" in op) return op.delete;
+    return chars(op.insert).length;
+}
+
+export function apply(content: string, ops: OpT[]): string {
+    const old = chars(content);
+    let result = "";
+    let pos = 0;
+    for (const op of ops) {
+        if ("retain" in op) {
+            result += old.slice(pos, pos + op.retain).join("");
+            pos += op.retain;
+        } else if ("delete" in op) {
+            pos += op.delete;
+        } else {
+            result += op.insert;
+        }
+    }
+    return result;
+}
+
+// the smallest operation turning `oldText` into `newText`
+export function replace(oldText: string, newText: string): OpT[] {
+    const old = chars(oldText);
+    const next = chars(newText);
+
+    let prefix = 0;
+    while (prefix < old.length && prefix < next.length && old[prefix] === next[prefix]) prefix++;
+    let suffix = 0;
+    while (
+        suffix < old.length - prefix &&
+        suffix < next.length - prefix &&
+        old[old.length - 1 - suffix] === next[next.length - 1 - suffix]
+    ) suffix++;
+
+    const ops: OpT[] = [];
+    push(ops, { retain: prefix });
+    push(ops, { insert: next.slice(prefix, next.length - suffix).join("") });
+    push(ops, { delete: old.length - prefix - suffix });
+    push(ops, { retain: suffix });
+    return ops;
+}
+
+// same as the server: inserts of `a` go first
+export function transform(a: OpT[], b: OpT[]): [OpT[], OpT[]] {
+    const aPrime: OpT[] = [];
+    const bPrime: OpT[] = [];
+    let i = 1, j = 1;
+    let opA: OpT | undefined = a[0] && { ...a[0] };
+    let opB: OpT | undefined = b[0] && { ...b[0] };
+
+    while (opA || opB) {
+        if (opA && "insert" in opA) {
+            push(aPrime, opA);
+            push(bPrime, { retain: length(opA) });
+            opA = a[i] && { ...a[i++] };
+            continue;
+        }
+        if (opB && "insert" in opB) {
+            push(aPrime, { retain: length(opB) });
+            push(bPrime, opB);
+            opB = b[j] && { ...b[j++] };
+            continue;
+        }
+        if (!opA || !opB) throw new Error("operations do not match");
+
+        const len = Math.min(length(opA), length(opB));
+        if ("retain" in opA && "retain" in opB) {
+            push(aPrime, { retain: len });
+            push(bPrime, { retain: len });
+        } else if ("delete" in opA && "retain" in opB) {
+            push(aPrime, { delete: len });
+        } else if ("retain" in opA && "delete" in opB) {
+            push(bPrime, { delete: len });
+        }
+
+        if (length(opA) > len) opA = "retain" in opA ? { retain: opA.retain - len } : { delete: length(opA) - len };
+        else opA = a[i] && { ...a[i++] };
+        if (length(opB) > len) opB = "retain" in opB ? { retain: opB.retain - len } : { delete: length(opB) - len };
+        else opB = b[j] && { ...b[j++] };
+    }
+
+    return [aPrime, bPrime];
+}
+
+// keeps the content of one cell in sync with the server, only one edit is in flight at a time
+export class CellSync {
+    cellUuid: string;
+    revision: number;
+    // content of the cell at `revision` on the server
+    shadow: string;
+    // edit which was sent but not acknowledged yet, with the content it leads to
+    pending?: { requestId: string; content: string };
+
+    constructor(cellUuid: string, content: string, revision: number) {
+        this.cellUuid = cellUuid;
+        this.shadow = content;
+        this.revision = revision;
+    }
+
+    // the editor content changed, returns the edit to send
+    local(content: string): WsMessage | undefined {
+        if (this.pending || content === this.shadow) return;
+
+        const requestId = nextRequestId();
+        this.pending = { requestId, content };
+        return {
+            cmd: WsCmds.Edit,
+            requestId,
+            cellUuid: this.cellUuid,
+            revision: this.revision,
+            operation: replace(this.shadow, content),
+        };
+    }
+
+    isOwn(msg: WsMessage): boolean {
+        return !!this.pending && msg.requestId === this.pending.requestId;
+    }
+
+    // the server applied our edit, returns the edit with everything typed in the meantime
+    acked(revision: number, content: string): WsMessage | undefined {
+        this.shadow = this.pending?.content ?? this.shadow;
+        this.revision = revision;
+        this.pending = undefined;
+        return this.local(content);
+    }
+
+    // someone else edited the cell, returns the new editor content
+    remote(revision: number, operation: OpT[], content: string): string {
+        const base = this.pending?.content ?? this.shadow;
+        let onBase = operation;
+        if (this.pending) {
+            [onBase] = transform(operation, replace(this.shadow, this.pending.content));
+            this.pending.content = apply(this.pending.content, onBase);
+        }
+        this.shadow = apply(this.shadow, operation);
+        this.revision = revision;
+
+        const [onContent] = transform(onBase, replace(base, content));
+        return apply(content, onContent);
+    }
+}
+
+const syncs = new Map<string, CellSync>();
+
+export function syncOf(cellUuid: string, content: string, revision: number): CellSync {
+    let sync = syncs.get(cellUuid);
+    if (!sync) {
+        sync = new CellSync(cellUuid, content, revision);
+        syncs.set(cellUuid, sync);
+    }
+    return sync;
+}
+
+export function dropSync(cellUuid: string) {
+    syncs.delete(cellUuid);
+}
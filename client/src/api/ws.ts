@@ -1,25 +1,125 @@
+import { CellT, DisplayDataT, TracebackT, VariableT } from "../types";
+import type { OpT } from "./ot";
+
 export type WsClientT = {
     ws: WebSocket;
     send: (data: WsMessage) => void;
 };
 
+// has to match PROTOCOL_VERSION of the server
+export const PROTOCOL_VERSION = 1;
+
 export enum WsCmds {
+    Hello = 'Hello',
+    Welcome = 'Welcome',
+    Ack = 'Ack',
+    Error = 'Error',
     Run = 'Run',
+    RunAll = 'RunAll',
+    RunStale = 'RunStale',
+    RunCells = 'RunCells',
+    Edit = 'Edit',
+    Edited = 'Edited',
+    InsertCell = 'InsertCell',
+    CellInserted = 'CellInserted',
+    DeleteCell = 'DeleteCell',
+    CellDeleted = 'CellDeleted',
+    Reorder = 'Reorder',
+    Reordered = 'Reordered',
+    SetDisabled = 'SetDisabled',
+    CellDisabled = 'CellDisabled',
+    MarkParameters = 'MarkParameters',
+    ParametersMarked = 'ParametersMarked',
+    SetInput = 'SetInput',
+    Disabled = 'Disabled',
+    SetExecutionMode = 'SetExecutionMode',
+    ExecutionModeChanged = 'ExecutionModeChanged',
+    Stale = 'Stale',
+    Status = 'Status',
     Res = 'Res',
     Err = 'Err',
     Ping = 'Ping',
     Pong = 'Pong',
+    Inspect = 'Inspect',
+    Inspected = 'Inspected',
+    Variables = 'Variables',
+    Peers = 'Peers',
+}
+
+export enum ExecutionMode {
+    Reactive = 'Reactive',
+    Lazy = 'Lazy',
+}
+
+export enum CellStatus {
+    Queued = 'Queued',
+    Idle = 'Idle',
+    Running = 'Running',
+    Finished = 'Finished',
+    Errored = 'Errored',
+    Blocked = 'Blocked',
+}
+
+export type InspectT = {
+    handle: string;
+    path?: (string | number)[];
+    start?: number;
+    end?: number;
+};
+
+export enum Permission {
+    View = 'view',
+    Read = 'read',
+    Edit = 'edit',
 }
 
 export type WsMessage = {
     cmd: WsCmds;
-    data?: string;
+    version?: number;
+    requestId?: string;
+    code?: string;
+    message?: string;
     content?: string;
-    cellUuid: string;
+    revision?: number;
+    operation?: OpT[];
+    index?: number;
+    cell?: CellT;
+    newOrder?: string[];
+    mode?: ExecutionMode;
+    disabled?: boolean;
+    parameters?: boolean;
+    status?: CellStatus;
+    timestamp?: number;
+    duration?: number;
+    blockedBy?: string;
+    traceback?: TracebackT;
+    cellUuid?: string;
+    cellUuids?: string[];
     locals?: any;
-    bindings?: string[];
+    outputs?: DisplayDataT[];
+    handle?: string;
+    path?: (string | number)[];
+    start?: number;
+    end?: number;
+    children?: any[];
+    summary?: any;
+    variables?: VariableT[];
+    count?: number;
+    sessionId?: string;
+    resumeSecret?: string;
+    lastSeq?: number;
+    seq?: number;
+    resumed?: boolean;
+    permission?: Permission;
+    value?: number | string | boolean;
 };
 
+let lastRequestId = 0;
+export function nextRequestId(): string {
+    lastRequestId += 1;
+    return lastRequestId.toString();
+}
+
 export type WsMessageEvent = {
     data: string;
 };
@@ -1,38 +1,85 @@
-import { CellT, LocalsT, LocalType } from "../types";
+import { CellT, DisplayDataT, InputT, LocalsT, LocalType, TracebackT } from "../types";
 import { RxTriangleRight } from "react-icons/rx";
 import { unsyncCell, updateCellContent } from "../store/cellSlice";
 import { useAppSelector, useAppDispatch } from "../store/hooks";
 import { KeyboardEvent, ReactNode, useEffect, useState } from "react";
-import CodeMirror from "@uiw/react-codemirror";
+import CodeMirror, { Decoration, EditorView } from "@uiw/react-codemirror";
 import { python } from "@codemirror/lang-python";
 import { atomone } from "@uiw/codemirror-themes-all";
-import { WsMessage, WsCmds } from "../api/ws";
+import { WsMessage, WsCmds, ExecutionMode, CellStatus, nextRequestId } from "../api/ws";
+import { syncOf } from "../api/ot";
 import "./Cell.css"
 
 type CellProps = {
     cellUuid: string;
     ws: WebSocket;
+    readOnly: boolean;
+    // viewers of a published notebook only see outputs and inputs
+    viewer: boolean;
 }
 
 function Cell(props: CellProps) {
-    const { cellUuid, ws } = props;
+    const { cellUuid, ws, readOnly, viewer } = props;
 
     const dispatch = useAppDispatch();
 
-    async function handleEval(data: string) {
-        console.log("Evaluating cell", data);
+    function handleChange(content: string) {
+        dispatch(updateCellContent({ uuid: cellUuid, content }));
+        const edit = syncOf(cellUuid, cell.content, cell.revision).local(content);
+        if (edit) ws.send(JSON.stringify(edit));
+    }
+
+    // runs the content on the server, which has every edit sent before
+    async function handleEval() {
+        handleChange(cell.content);
         let wsMessage: WsMessage = {
             cmd: WsCmds.Run,
+            requestId: nextRequestId(),
+            cellUuid,
+        }
+        ws.send(JSON.stringify(wsMessage));
+    }
+
+    // lazy cells do not run when a cell they depend on runs, they become stale instead
+    function handleToggleLazy() {
+        let wsMessage: WsMessage = {
+            cmd: WsCmds.SetExecutionMode,
+            requestId: nextRequestId(),
+            cellUuid,
+            mode: cell.cell_type === "NonReactiveCode" ? ExecutionMode.Reactive : ExecutionMode.Lazy,
+        }
+        ws.send(JSON.stringify(wsMessage));
+    }
+
+    // the cell and its dependents run with the new value, for viewers in their own namespace
+    function handleInput(value: number | string | boolean) {
+        let wsMessage: WsMessage = {
+            cmd: WsCmds.SetInput,
+            requestId: nextRequestId(),
+            cellUuid,
+            value,
+        }
+        ws.send(JSON.stringify(wsMessage));
+    }
+
+    function handleToggleDisabled() {
+        let wsMessage: WsMessage = {
+            cmd: WsCmds.SetDisabled,
+            requestId: nextRequestId(),
             cellUuid,
-            data: data,
+            disabled: !cell.metadata.disabled,
         }
         ws.send(JSON.stringify(wsMessage));
+    }
 
-        const updateMsg = {
-            uuid: cell.uuid,
-            content: data,
+    function handleToggleParameters() {
+        let wsMessage: WsMessage = {
+            cmd: WsCmds.MarkParameters,
+            requestId: nextRequestId(),
+            cellUuid,
+            parameters: !cell.metadata.parameters,
         }
-        dispatch(updateCellContent(updateMsg))
+        ws.send(JSON.stringify(wsMessage));
     }
 
     const cell = useAppSelector((state) => state.cells.mappings[cellUuid]);
@@ -41,42 +88,117 @@ function Cell(props: CellProps) {
     }
 
     const out = useAppSelector((state) => state.cells.output[cellUuid]);
+    const status = useAppSelector((state) => state.cells.status[cellUuid]);
     console.log("out", out);
 
+    // outputs of cells blocked by a failed upstream cell are outdated
+    const blocked = status?.status === CellStatus.Blocked;
     const hasError = out && out.cmd === WsCmds.Err;
     const hasOutput = out && out.cmd === WsCmds.Res;
+    // the innermost line of this cell in the traceback
+    const errorLine = hasError ? out.traceback?.frames.filter((frame) => frame.cell_uuid === cellUuid).pop()?.line : undefined;
+    const outputs = (
+        <>
+            {blocked && <div className="text-xs text-red-500">blocked by an error in an upstream cell</div>}
+            <div className={blocked ? "opacity-30" : ""}>
+                {hasError && !out.traceback && <div className="text-red-500">{out.message}</div>}
+                {hasError && out.traceback && <CellTraceback traceback={out.traceback} cellUuid={cellUuid} />}
+                {hasOutput && <CellOutput locals={out.locals} cell={cell} />}
+                {out?.outputs?.map((displayData, i) => <DisplayOutput key={i} displayData={displayData} />)}
+            </div>
+        </>
+    );
+
+    if (viewer) {
+        return (
+            <div>
+                {cell.input && <CellInput input={cell.input} handleInput={handleInput} />}
+                {outputs}
+            </div>
+        )
+    }
+
     return (
-        <div>
-            <CellEditor cell={cell} handleEval={handleEval} />
-            {hasError && <div className="text-red-500">{out.data}</div>}
-            {hasOutput && <CellOutput locals={out.locals} cell={cell} />}
+        <div className={cell.isDisabled ? "opacity-50" : ""}>
+            <CellEditor cell={cell} errorLine={errorLine} readOnly={readOnly} handleEval={handleEval} handleChange={handleChange} />
+            <div className="flex text-xs justify-end gap-2 text-gray-500">
+                {status?.status && <span>{status.status.toLowerCase()}{status.duration !== undefined ? ` in ${status.duration}ms` : ""}</span>}
+                {cell.stale && <span className="text-yellow-500">stale</span>}
+                {cell.isDisabled && !cell.metadata.disabled && <span>disabled by a dependency</span>}
+                {!readOnly && <button onClick={handleToggleDisabled}>{cell.metadata.disabled ? "enable" : "disable"}</button>}
+                {!readOnly && <button onClick={handleToggleParameters}>{cell.metadata.parameters ? "parameters" : "code"}</button>}
+                {!readOnly && <button onClick={handleToggleLazy}>{cell.cell_type === "NonReactiveCode" ? "lazy" : "reactive"}</button>}
+            </div>
+            {outputs}
         </div >
     )
 }
 
+export type CellInputProps = {
+    input: InputT;
+    handleInput: (value: number | string | boolean) => void;
+}
+
+function CellInput(props: CellInputProps) {
+    const { input, handleInput } = props;
+    const className = "text-xs bg-transparent border border-gray-600 rounded px-1";
+
+    let widget;
+    if (typeof input.value === "boolean") {
+        widget = <input type="checkbox" defaultChecked={input.value} onChange={(e) => handleInput(e.target.checked)} />;
+    } else if (typeof input.value === "number") {
+        widget = <input type="number" className={className} defaultValue={input.value} onChange={(e) => {
+            const value = Number(e.target.value);
+            if (e.target.value !== "" && !isNaN(value)) handleInput(value);
+        }} />;
+    } else {
+        widget = <input type="text" className={className} defaultValue={input.value} onChange={(e) => handleInput(e.target.value)} />;
+    }
+
+    return (
+        <label className="flex items-center gap-2 my-1.5 text-sm">
+            <span>{input.name}</span>
+            {widget}
+        </label>
+    )
+}
+
 export type CellEditorProps = {
     cell: CellT;
-    handleEval: (content: string) => void;
+    errorLine?: number;
+    readOnly: boolean;
+    handleEval: () => void;
+    handleChange: (content: string) => void;
 }
 
 function CellEditor(props: CellEditorProps) {
     const { cell } = props;
-    const [localCode, setLocalCode] = useState<string>(cell.content);
     const [showCellToolbar, setShowCellToolbar] = useState<boolean>(false);
 
     function handleKeyDown(event: KeyboardEvent) {
-        if (event.key === "Enter" && event.shiftKey) {
+        if (event.key === "Enter" && event.shiftKey && !props.readOnly) {
             event.preventDefault();
-            props.handleEval(localCode);
+            props.handleEval();
         }
     }
 
+    // highlights the line which raised the error
+    const extensions = [python()];
+    if (props.errorLine) {
+        const errorLine = props.errorLine;
+        extensions.push(EditorView.decorations.of((view) => {
+            if (errorLine > view.state.doc.lines) return Decoration.none;
+            const line = view.state.doc.line(errorLine);
+            return Decoration.set([Decoration.line({ class: "cm-error-line" }).range(line.from)]);
+        }));
+    }
+
     const dispatch = useAppDispatch();
     function handleKeyChange(code: string) {
-        if (code !== localCode) {
+        if (code !== cell.content) {
             dispatch(unsyncCell(cell.uuid));
+            props.handleChange(code);
         }
-        setLocalCode(code);
     }
 
     return (
@@ -84,19 +206,20 @@ function CellEditor(props: CellEditorProps) {
             onMouseOver={() => setShowCellToolbar(true)}
             onMouseOut={() => setShowCellToolbar(false)}>
             <div className="flex items-end my-1.5 relative">
-                <div className="absolute right-0 top-0 z-10">
+                {!props.readOnly && <div className="absolute right-0 top-0 z-10">
                     <RxTriangleRight
                         className={"mr-1 w-6 h-6 hover:cursor-pointer" + (cell.isSynced ? " text-green-500" : " text-gray-600")}
-                        onClick={() => props.handleEval(localCode)} />
-                </div>
+                        onClick={() => props.handleEval()} />
+                </div>}
 
                 <div className="w-full">
                     <CodeMirror
-                        value={localCode}
+                        value={cell.content}
+                        editable={!props.readOnly}
                         onChange={handleKeyChange}
                         onKeyDown={handleKeyDown}
                         theme={atomone}
-                        extensions={[python()]}
+                        extensions={extensions}
                         style={{
                             fontSize: "0.8rem",
                             borderRadius: "0.25rem",
@@ -127,11 +250,12 @@ function CellOutput(props: CellOutputProps) {
     const { locals, cell } = props;
 
     function formatOutput(key: string): ReactNode {
-        let { value, local_type } = locals[key as any];
-        if (!value || local_type === LocalType.Defintion) return
+        let { summary, local_type } = locals[key as any];
+        if (!summary || local_type === LocalType.Defintion) return
         return (<div key={key} className="text-xs max-h-96 overflow-scroll scrollbar-hide">
             <span className="pr-1">{key === "" ? "" : key + ":"}</span>
-            <span className="">{value}</span>
+            <span className="">{summary.repr}</span>
+            <span className="pl-1 text-gray-500">{summary.type_name}{summary.shape ? ` ${summary.shape.join("x")}` : ""}</span>
         </div>)
     }
 
@@ -142,4 +266,57 @@ function CellOutput(props: CellOutputProps) {
     )
 }
 
+export type CellTracebackProps = {
+    traceback: TracebackT;
+    cellUuid: string;
+}
+
+function CellTraceback(props: CellTracebackProps) {
+    const { traceback, cellUuid } = props;
+
+    function location(frame: TracebackT["frames"][number]): string {
+        if (!frame.cell_uuid) return `${frame.filename}, line ${frame.line}`;
+        if (frame.cell_uuid === cellUuid) return `line ${frame.line}`;
+        return `cell ${frame.cell_uuid.slice(0, 6)}, line ${frame.line}`;
+    }
+
+    return (
+        <div className="text-xs border-2 border-red-900 px-3 py-1 mb-2.5 rounded-md">
+            <div className="text-red-500">{traceback.ename}: {traceback.evalue}</div>
+            {traceback.frames.map((frame, i) => (
+                <div key={i} className={frame.cell_uuid === cellUuid ? "text-red-300" : "text-gray-500"}>
+                    <span>in {frame.name}, {location(frame)}</span>
+                    {frame.source && <pre className="pl-4">{frame.source}</pre>}
+                </div>
+            ))}
+        </div>
+    )
+}
+
+export type DisplayOutputProps = {
+    displayData: DisplayDataT;
+}
+
+function DisplayOutput(props: DisplayOutputProps) {
+    const { data } = props.displayData;
+    const className = "text-xs max-h-96 overflow-scroll scrollbar-hide mb-2.5";
+
+    if (data["image/svg+xml"]) {
+        return <div className={className} dangerouslySetInnerHTML={{ __html: data["image/svg+xml"] }} />
+    }
+    if (data["image/png"]) {
+        return <img className="mb-2.5" src={`data:image/png;base64,${data["image/png"]}`} />
+    }
+    if (data["image/jpeg"]) {
+        return <img className="mb-2.5" src={`data:image/jpeg;base64,${data["image/jpeg"]}`} />
+    }
+    if (data["text/html"]) {
+        return <div className={className} dangerouslySetInnerHTML={{ __html: data["text/html"] }} />
+    }
+    if (data["application/json"]) {
+        return <pre className={className}>{JSON.stringify(data["application/json"], null, 2)}</pre>
+    }
+    return <pre className={className}>{data["text/plain"]}</pre>
+}
+
 export default Cell;
\ No newline at end of file
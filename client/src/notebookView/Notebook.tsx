@@ -1,11 +1,13 @@
 import Cell from "./Cell";
-import { initCell, addOutput } from "../store/cellSlice";
+import { initCell, addOutput, setStatus, updateCellContent, removeCell, setStale, setCellType, setCellDisabled, setCellParameters, setDisabled } from "../store/cellSlice";
+import store from "../store/store";
+import { syncOf, dropSync } from "../api/ot";
 import { CellT } from "../types"
 import { useAppDispatch } from "../store/hooks";
 import { useEffect, useState } from "react";
 import { DragDropContext, Droppable, Draggable } from "react-beautiful-dnd";
-import Api from "../api/api";
-import { WsCmds, WsMessage } from "../api/ws";
+import { WsCmds, WsMessage, PROTOCOL_VERSION, ExecutionMode, Permission, nextRequestId } from "../api/ws";
+import { token, wsUrl } from "../api/api";
 
 export type NotebookProps = {
     notebook: any;
@@ -15,41 +17,177 @@ function Notebook(props: NotebookProps) {
     const { notebook } = props;
     const [order, setOrder] = useState<string[]>(notebook.topology.display_order);
     const [ws, setWs] = useState<WebSocket>();
+    const [peers, setPeers] = useState<number>(1);
+    const [mode, setMode] = useState<ExecutionMode>(notebook.execution_mode ?? ExecutionMode.Reactive);
+    const [permission, setPermission] = useState<Permission>(Permission.Edit);
+    const readOnly = permission !== Permission.Edit;
 
     const dispatch = useAppDispatch()
 
     useEffect(() => {
-
         const notebookUuid = notebook?.uuid;
-        let wsUrl = `${import.meta.env.VITE_WS_URL}?notebookUuid=${notebookUuid}`;
-        let ws = new WebSocket(wsUrl);
-        ws.onopen = () => {
-            console.log("Connected to websocket");
-            // setInterval(() => {
-            //   let wsMessage = {
-            //     cmd: "Ping",
-            //     out: Date.now().toString(),
-            //   } as WsMessage;
-            //   ws.send(JSON.stringify(wsMessage));
-            // }, 1000);
-            setWs(ws);
+        const url = `${wsUrl()}?notebookUuid=${notebookUuid}&token=${encodeURIComponent(token())}`;
+        // resume the same session after the connection dropped
+        let sessionId: string | undefined;
+        let resumeSecret: string | undefined;
+        let lastSeq: number | undefined;
+        let retries = 0;
+        let closed = false;
+        let ws: WebSocket;
+
+        function connect() {
+            ws = new WebSocket(url);
+            ws.onopen = () => {
+                console.log("Connected to websocket");
+                let hello: WsMessage = {
+                    cmd: WsCmds.Hello,
+                    version: PROTOCOL_VERSION,
+                    sessionId,
+                    resumeSecret,
+                    lastSeq,
+                };
+                ws.send(JSON.stringify(hello));
+            }
+            ws.onmessage = handleMessage;
+            ws.onclose = () => {
+                setWs(undefined);
+                if (closed) return;
+                const delay = Math.min(1000 * 2 ** retries, 30000);
+                retries += 1;
+                console.log(`Websocket closed, reconnecting in ${delay}ms`);
+                setTimeout(connect, delay);
+            }
         }
 
-        ws.onmessage = (event) => {
+        function handleMessage(event: MessageEvent) {
             let wsMessage = JSON.parse(event.data) as WsMessage;
-            dispatch(addOutput(wsMessage));
+            if (wsMessage.seq !== undefined) {
+                lastSeq = Math.max(lastSeq ?? 0, wsMessage.seq);
+            }
+            switch (wsMessage.cmd) {
+                case WsCmds.Welcome:
+                    // results we missed are gone, only a reload shows the current state
+                    if (sessionId && !wsMessage.resumed) {
+                        window.location.reload();
+                        return;
+                    }
+                    sessionId = wsMessage.sessionId;
+                    resumeSecret = wsMessage.resumeSecret;
+                    setPermission(wsMessage.permission ?? Permission.Edit);
+                    lastSeq = Math.max(lastSeq ?? 0, wsMessage.seq ?? 0);
+                    retries = 0;
+                    setWs(ws);
+                    break;
+                case WsCmds.Peers:
+                    setPeers(wsMessage.count ?? 1);
+                    break;
+                case WsCmds.Error:
+                    console.error(`Request ${wsMessage.requestId} failed (${wsMessage.code}): ${wsMessage.message}`);
+                    break;
+                case WsCmds.Status:
+                    dispatch(setStatus(wsMessage));
+                    break;
+                case WsCmds.Res:
+                case WsCmds.Err:
+                    dispatch(addOutput(wsMessage));
+                    break;
+                case WsCmds.Edited:
+                    handleEdited(ws, wsMessage);
+                    break;
+                case WsCmds.CellInserted: {
+                    const { cell, index } = wsMessage;
+                    if (!cell || index === undefined) break;
+                    syncOf(cell.uuid, cell.content, cell.revision);
+                    dispatch(initCell([cell]));
+                    setOrder((order) => [...order.slice(0, index), cell.uuid, ...order.slice(index)]);
+                    break;
+                }
+                case WsCmds.CellDeleted: {
+                    const { cellUuid } = wsMessage;
+                    if (!cellUuid) break;
+                    dropSync(cellUuid);
+                    setOrder((order) => order.filter((uuid) => uuid !== cellUuid));
+                    dispatch(removeCell(cellUuid));
+                    break;
+                }
+                case WsCmds.Reordered:
+                    if (wsMessage.newOrder) setOrder(wsMessage.newOrder);
+                    break;
+                case WsCmds.CellDisabled:
+                    if (wsMessage.cellUuid) dispatch(setCellDisabled({
+                        uuid: wsMessage.cellUuid,
+                        disabled: !!wsMessage.disabled,
+                    }));
+                    break;
+                case WsCmds.ParametersMarked:
+                    if (wsMessage.cellUuid) dispatch(setCellParameters({
+                        uuid: wsMessage.cellUuid,
+                        parameters: !!wsMessage.parameters,
+                    }));
+                    break;
+                case WsCmds.Disabled:
+                    dispatch(setDisabled(wsMessage.cellUuids ?? []));
+                    break;
+                case WsCmds.Stale:
+                    dispatch(setStale(wsMessage.cellUuids ?? []));
+                    break;
+                case WsCmds.ExecutionModeChanged: {
+                    const { cellUuid, mode } = wsMessage;
+                    if (!mode) break;
+                    if (!cellUuid) setMode(mode);
+                    else dispatch(setCellType({
+                        uuid: cellUuid,
+                        cellType: mode === ExecutionMode.Lazy ? "NonReactiveCode" : "ReactiveCode",
+                    }));
+                    break;
+                }
+            }
         }
 
+        connect();
+
         return () => {
+            closed = true;
             ws.close();
         }
     }, []);
 
     useEffect(() => {
         let cells: CellT[] = notebook.topology.display_order.map((uuid: string) => notebook.topology.cells[uuid]);
+        for (const cell of cells) {
+            syncOf(cell.uuid, cell.content, cell.revision);
+        }
         dispatch(initCell(cells));
     }, [notebook.topology.display_order]);
 
+    // edits of every session, including our own which acknowledge the edit in flight
+    function handleEdited(ws: WebSocket, msg: WsMessage) {
+        const { cellUuid, revision, operation } = msg;
+        const cell = cellUuid && store.getState().cells.mappings[cellUuid];
+        if (!cell || revision === undefined || !operation) return;
+
+        const sync = syncOf(cell.uuid, cell.content, cell.revision);
+        if (sync.isOwn(msg)) {
+            const edit = sync.acked(revision, cell.content);
+            if (edit) ws.send(JSON.stringify(edit));
+            return;
+        }
+        const content = sync.remote(revision, operation, cell.content);
+        dispatch(updateCellContent({ uuid: cell.uuid, content }));
+    }
+
+    function sendMessage(msg: WsMessage) {
+        ws?.send(JSON.stringify({ ...msg, requestId: nextRequestId() }));
+    }
+
+    function handleInsertCell(index: number) {
+        sendMessage({ cmd: WsCmds.InsertCell, index, content: "" });
+    }
+
+    function handleDeleteCell(cellUuid: string) {
+        sendMessage({ cmd: WsCmds.DeleteCell, cellUuid });
+    }
+
     async function handleDragEnd(result: any) {
         if (!result.destination) {
             return;
@@ -59,13 +197,8 @@ function Notebook(props: NotebookProps) {
         const [removed] = newOrder.splice(result.source.index, 1);
         newOrder.splice(result.destination.index, 0, removed);
 
-        try {
-            const res = await Api.reorderCells(notebook.uuid, newOrder);
-            console.log(res);
-        } catch (error: any) {
-            console.log(error);
-        }
-
+        // every session, including this one, gets the new order as Reordered
+        sendMessage({ cmd: WsCmds.Reorder, newOrder });
         setOrder(newOrder);
     }
 
@@ -79,6 +212,16 @@ function Notebook(props: NotebookProps) {
                 <div className="text-xs">
                     <span className="mr-0.5">{notebook.language_info.name}</span>
                     <span>{notebook.language_info.version}</span>
+                    {peers > 1 && <span className="ml-2">{peers} sessions connected</span>}
+                    {permission === Permission.Read && <span className="ml-2">read only</span>}
+                    {!readOnly && <>
+                    <button className="ml-2" onClick={() => sendMessage({ cmd: WsCmds.RunAll })}>run all</button>
+                    <button className="ml-2" onClick={() => sendMessage({ cmd: WsCmds.RunStale })}>run stale</button>
+                    <button className="ml-2" onClick={() => sendMessage({
+                        cmd: WsCmds.SetExecutionMode,
+                        mode: mode === ExecutionMode.Lazy ? ExecutionMode.Reactive : ExecutionMode.Lazy,
+                    })}>{mode === ExecutionMode.Lazy ? "lazy" : "reactive"}</button>
+                    </>}
                 </div>
             </div>
             <DragDropContext onDragEnd={handleDragEnd}>
@@ -97,7 +240,13 @@ function Notebook(props: NotebookProps) {
                                                 key={cellUuid}
                                                 cellUuid={cellUuid}
                                                 ws={ws}
+                                                readOnly={readOnly}
+                                                viewer={permission === Permission.View}
                                             />
+                                            {!readOnly && <div className="flex text-xs justify-center gap-2 mb-1 text-gray-500">
+                                                <button onClick={() => handleInsertCell(i + 1)}>+ cell</button>
+                                                <button onClick={() => handleDeleteCell(cellUuid)}>delete</button>
+                                            </div>}
                                         </div>
                                     )}
                                 </Draggable>
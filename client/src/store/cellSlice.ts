@@ -9,11 +9,16 @@ interface cellsState {
     output: {
         [key: string]: WsMessage
     },
+    // latest Status message of every cell
+    status: {
+        [key: string]: WsMessage
+    },
 }
 
 const initialState: cellsState = {
     mappings: {},
     output: {},
+    status: {},
 }
 
 export const cellsSlice = createSlice({
@@ -30,14 +35,47 @@ export const cellsSlice = createSlice({
         },
         addOutput: (state, action: PayloadAction<WsMessage>) => {
             let msg = action.payload;
+            if (!msg.cellUuid) return;
             state.output[msg.cellUuid] = msg;
         },
+        setStatus: (state, action: PayloadAction<WsMessage>) => {
+            let msg = action.payload;
+            if (!msg.cellUuid) return;
+            state.status[msg.cellUuid] = msg;
+        },
         updateCellContent: (state, action: PayloadAction<{ uuid: string, content: string }>) => {
             state.mappings[action.payload.uuid].content = action.payload.content;
-        }
+        },
+        setStale: (state, action: PayloadAction<string[]>) => {
+            for (let cell of Object.values(state.mappings)) {
+                cell.stale = action.payload.includes(cell.uuid);
+            }
+        },
+        setCellDisabled: (state, action: PayloadAction<{ uuid: string, disabled: boolean }>) => {
+            let cell = state.mappings[action.payload.uuid];
+            if (cell) cell.metadata.disabled = action.payload.disabled;
+        },
+        setCellParameters: (state, action: PayloadAction<{ uuid: string, parameters: boolean }>) => {
+            let cell = state.mappings[action.payload.uuid];
+            if (cell) cell.metadata.parameters = action.payload.parameters;
+        },
+        setDisabled: (state, action: PayloadAction<string[]>) => {
+            for (let cell of Object.values(state.mappings)) {
+                cell.isDisabled = action.payload.includes(cell.uuid);
+            }
+        },
+        setCellType: (state, action: PayloadAction<{ uuid: string, cellType: string }>) => {
+            let cell = state.mappings[action.payload.uuid];
+            if (cell) cell.cell_type = action.payload.cellType;
+        },
+        removeCell: (state, action: PayloadAction<string>) => {
+            delete state.mappings[action.payload];
+            delete state.output[action.payload];
+            delete state.status[action.payload];
+        },
     },
 });
 
-export const { initCell, unsyncCell, updateCellContent, addOutput } = cellsSlice.actions;
+export const { initCell, unsyncCell, updateCellContent, addOutput, setStatus, setStale, setCellType, setCellDisabled, setCellParameters, setDisabled, removeCell } = cellsSlice.actions;
 
 export default cellsSlice.reducer;
@@ -1,23 +1,80 @@
 export type CellT = {
     metadata: {
         collapsed: boolean;
+        disabled: boolean;
+        parameters?: boolean;
     };
     uuid: string;
     cell_type: string;
     content: string;
+    revision: number;
+    stale: boolean;
     pos: number;
     dependencies: string[];
     isSynced?: boolean;
+    // disabled itself or depending on a disabled cell
+    isDisabled?: boolean;
     bindings?: string[];
+    // set if the cell only assigns a literal, viewers of a published notebook change it with a widget
+    input?: InputT;
+};
+
+export type InputT = {
+    name: string;
+    value: number | string | boolean;
+};
+
+export type ValueSummaryT = {
+    type_name: string;
+    repr: string;
+    truncated: boolean;
+    length?: number;
+    shape?: number[];
+    expandable: boolean;
+};
+
+export type VariableT = {
+    name: string;
+    cell_uuid: string;
+    handle?: string;
+    summary?: ValueSummaryT;
+    dependents: string[];
 };
 
 export type LocalsT = {
     [key: string]: {
-        value: string;
+        handle: string;
+        summary: ValueSummaryT;
         local_type: LocalType;
     };
 };
 
+export type MimeBundleT = {
+    [mimeType: string]: any;
+};
+
+export type DisplayDataT = {
+    data: MimeBundleT;
+    metadata: { [key: string]: any };
+};
+
+export type FrameT = {
+    cell_uuid?: string;
+    filename: string;
+    name: string;
+    line: number;
+    source?: string;
+};
+
+// line numbers are lines of the cells
+export type TracebackT = {
+    ename: string;
+    evalue: string;
+    frames: FrameT[];
+    start_line: number;
+    end_line: number;
+};
+
 export enum LocalType {
     Defintion = 'Definition',
     Eval = 'Eval',
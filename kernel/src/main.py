@@ -1,94 +1,307 @@
+import builtins
+import json
+import linecache
+import os
+import re
+import reprlib
+import signal
+import sys
 import zmq
-from io import StringIO
+from collections import OrderedDict
+from io import StringIO, BytesIO
 from contextlib import redirect_stdout
 import dill
 import base64
 import subprocess
+import time
+import traceback
+import uuid
+
+# render matplotlib figures off screen, they are sent to the client as images
+os.environ.setdefault("MPLBACKEND", "Agg")
+
+# (method, mime type) pairs of the IPython rich display protocol
+REPR_METHODS = [
+    ("_repr_html_", "text/html"),
+    ("_repr_markdown_", "text/markdown"),
+    ("_repr_svg_", "image/svg+xml"),
+    ("_repr_png_", "image/png"),
+    ("_repr_jpeg_", "image/jpeg"),
+    ("_repr_json_", "application/json"),
+    ("_repr_latex_", "text/latex"),
+]
+
+# statements are compiled with this file name, tracebacks are mapped back to cell lines with it
+CELL_FILENAME = re.compile(r"^<cell (\S+) line (\d+)>$")
+
+# max length of value reprs sent to the client, and default number of children per inspection
+REPR_LIMIT = 200
+INSPECT_LIMIT = 100
+
+# orderings of the last inspected sets, their children are addressed by the index into them
+SET_ORDERS = OrderedDict()
+SET_ORDERS_LIMIT = 16
 
 context = zmq.Context()
 pub_socket = context.socket(zmq.PUB)
-pub_socket.bind("tcp://*:8081")
+pub_socket.bind(f"tcp://*:{os.environ.get('ZMQ_PORT_PUB', '8081')}")
 
 rep_socket = context.socket(zmq.REP)
-rep_socket.bind("tcp://*:8082")
+rep_socket.bind(f"tcp://*:{os.environ.get('ZMQ_PORT_REQ', '8082')}")
 
 print("Connected to server")
 
+# set while cells run, the server interrupts superseded runs with SIGINT
+running = False
+
+
+def handle_interrupt(signum, frame):
+    if running:
+        raise KeyboardInterrupt
+
+
+signal.signal(signal.SIGINT, handle_interrupt)
+
+
+class HandleStore:
+    """values of a notebook stay in the kernel, the server and client only see their handles"""
+
+    def __init__(self):
+        self.objects = {}  # handle -> value
+        self.names = {}  # (cell_uuid, binding name) -> handle
+
+    def bind(self, cell_uuid, name, value):
+        key = (cell_uuid, name)
+        handle = self.names.get(key)
+        if handle is not None and self.objects[handle] is value:
+            return handle
+
+        if handle is not None:
+            del self.objects[handle]
+        handle = uuid.uuid4().hex
+        self.objects[handle] = value
+        self.names[key] = handle
+        return handle
+
+    def get(self, handle):
+        return self.objects[handle]
+
+    def drop_cell(self, cell_uuid):
+        for key in [key for key in self.names if key[0] == cell_uuid]:
+            del self.objects[self.names.pop(key)]
+
+
+class Namespace:
+    """state of a notebook, every cell owns the bindings it created"""
+
+    def __init__(self):
+        self.cells = {}  # cell_uuid -> {name: value}
+        self.types = {}  # cell_uuid -> {name: execution type of the binding statement}
+        self.store = HandleStore()
+
+    def invalidate(self, cell_uuid):
+        self.cells.pop(cell_uuid, None)
+        self.types.pop(cell_uuid, None)
+        self.store.drop_cell(cell_uuid)
+
+    def exposed(self, exposed_names, shared=None):
+        """values of exposed_names (name -> uuid of the defining cell) from the other cells,
+        cells which never ran in this namespace are taken from the shared one if given"""
+        res = {}
+        for name, cell_uuid in exposed_names.items():
+            bindings = self.bindings_of(cell_uuid, shared)
+            if name in bindings:
+                res[name] = bindings[name]
+        return res
+
+    def bindings_of(self, cell_uuid, shared=None):
+        if cell_uuid not in self.cells and shared is not None:
+            return shared.cells.get(cell_uuid, {})
+        return self.cells.get(cell_uuid, {})
+
+    def update(self, cell_uuid, env, exposed, execution_type):
+        """takes over everything the cell bound in env, values it only read from other cells are skipped"""
+        bindings = self.cells.setdefault(cell_uuid, {})
+        types = self.types.setdefault(cell_uuid, {})
+        for name, value in env.items():
+            if name in RESERVED_NAMES:
+                continue
+            if name in exposed and exposed[name] is value:
+                continue
+            if name not in bindings or bindings[name] is not value:
+                types[name] = execution_type
+            bindings[name] = value
+
+    def locals_of(self, cell_uuid):
+        bindings = self.cells.get(cell_uuid, {})
+        types = self.types.get(cell_uuid, {})
+        return {
+            name: {
+                "local_type": types.get(name, "Exec"),
+                "handle": self.store.bind(cell_uuid, name, value),
+                "summary": summarize(value),
+            }
+            for name, value in bindings.items()
+        }
+
+
+# names every cell environment provides, they are never part of a cell namespace
+RESERVED_NAMES = {"__builtins__", "display"}
+
+# notebook_uuid (or private namespace of a viewer) -> Namespace
+namespaces = {}
+
+
+def namespace_of(notebook_uuid):
+    if notebook_uuid not in namespaces:
+        namespaces[notebook_uuid] = Namespace()
+    return namespaces[notebook_uuid]
+
 
 def main():
+    global running
 
     while True:
         message = rep_socket.recv()
-        rep_socket.send(b"OK")
-
         msg = dill.loads(message)
 
+        if msg.get("kind") == "Inspect":
+            rep_socket.send(dill.dumps(handle_inspect(msg)))
+            continue
+        if msg.get("kind") == "Namespace":
+            rep_socket.send(dill.dumps(handle_namespace(msg)))
+            continue
+        if msg.get("kind") == "Values":
+            rep_socket.send(json.dumps(handle_values(msg)).encode())
+            continue
+        if msg.get("kind") == "DropNamespace":
+            namespaces.pop(msg["namespace"], None)
+            rep_socket.send(b"OK")
+            continue
+        if msg.get("kind") == "InvalidateCells":
+            namespace = namespace_of(msg["notebook_uuid"])
+            for cell_uuid in msg["cell_uuids"]:
+                namespace.invalidate(cell_uuid)
+            rep_socket.send(b"OK")
+            continue
+        if msg.get("kind") == "Ping":
+            rep_socket.send(b"OK")
+            # answered on the pub socket too, once the server gets it its subscription is
+            # connected. Results of runs have a run id, a ping has none
+            pub_socket.send(dill.dumps({
+                "notebook_uuid": "",
+                "cell_uuid": "",
+                "locals": {},
+                "ended": False,
+                "run_id": 0,
+            }))
+            continue
+        rep_socket.send(b"OK")
+
         notebook_uuid = msg["notebook_uuid"]
+        # copied into every message belonging to this request
+        header = {
+            "notebook_uuid": notebook_uuid,
+            # session and request id, the server routes the results with it
+            "origin": msg.get("origin", {}),
+            # results of superseded runs are dropped by the server
+            "run_id": msg.get("run_id", 0),
+        }
         execution_cells = msg["execution_cells"]
-        locals_of_deps = msg["locals_of_deps"]
-
-        acc_locals = {}
-
-        for i in range(len(execution_cells)):
-            try:
+        exposed_names = msg["exposed_names"]
+        # per execution cell, the cells blocked if it fails
+        dependents = msg.get("dependents", [])
+
+        # viewers of a published notebook run it in their own namespace
+        namespace = namespace_of(msg.get("namespace") or notebook_uuid)
+        # calls reuse what ran in the namespace of the notebook
+        shared = namespace_of(notebook_uuid) if msg.get("reuse_shared") else None
+        for cell_uuid in msg.get("invalidate", []):
+            namespace.invalidate(cell_uuid)
+
+        cell_uuid = msg["cell_uuid"]
+        blocked = set()
+        try:
+            # inside the try, an interrupt right after it is set cannot stop the kernel
+            running = True
+            for i in range(len(execution_cells)):
                 cell = execution_cells[i]
-                cell_locals = locals_of_deps[i]
-
-                for key, value in cell_locals.items():
-                    acc_locals[key] = value
-
                 statements = cell["statements"]
                 cell_uuid = cell["uuid"]
-
-                for statement in statements:
-                    print(f"Executing statement: {statement}")
-                    try:
-                        run_statement(statement, acc_locals,
-                                      notebook_uuid, cell_uuid)
-                    except Exception as e:
-                        print(f"Error: {e}")
-                        raise e
-            except Exception as e:
-                break
-
-        handle_send(notebook_uuid, cell_uuid, acc_locals, ended=True)
+                if cell_uuid in blocked:
+                    continue
+                started = time.perf_counter()
+                handle_status(header, cell_uuid, "Running")
+                try:
+                    # a cell starts from scratch, with only the names it requires from other cells
+                    namespace.invalidate(cell_uuid)
+                    exposed = namespace.exposed(exposed_names[i], shared)
+                    # display data of all statements of the cell so far
+                    outputs = []
+                    env = cell_env(exposed, outputs)
+
+                    for statement in statements:
+                        print(f"Executing statement: {statement}")
+                        try:
+                            run_statement(statement, env, exposed, namespace,
+                                          header, cell_uuid, outputs)
+                        except Exception as e:
+                            print(f"Error: {e}")
+                            raise e
+                except Exception as e:
+                    handle_status(header, cell_uuid, "Errored", started)
+                    # cells not depending on the failed one still run
+                    for dependent in dependents[i] if i < len(dependents) else []:
+                        if dependent not in blocked:
+                            blocked.add(dependent)
+                            handle_status(header, dependent, "Blocked",
+                                          blocked_by=cell_uuid)
+                    continue
+                handle_status(header, cell_uuid, "Finished", started)
+        except KeyboardInterrupt:
+            print("Interrupted")
+        finally:
+            running = False
+
+        handle_send(header, cell_uuid, {}, ended=True)
         print("Ended")
 
 
-def run_statement(statement, acc_locals, notebook_uuid, cell_uuid):
+def run_statement(statement, env, exposed, namespace, header, cell_uuid, outputs):
     execution_type = statement["execution_type"]
     content = statement["content"]
+    filename = f"<cell {cell_uuid} line {statement.get('start_line', 1)}>"
 
-    locals_decoded = locals_decode(acc_locals)
     try:
         if execution_type == "Eval":
-            res = eval_code(content, locals_decoded)
+            res = eval_code(content, env, outputs, filename)
         else:
-            res = exec_code(content, locals_decoded)
+            res = exec_code(content, env, filename)
         if res != "" and res is not None:
-            locals_decoded["<stdout>"] = res
+            outputs.append(stream_bundle(res))
     except Exception as e:
-        locals = locals_encode(locals_decoded, acc_locals, execution_type)
-        handle_err(notebook_uuid, cell_uuid,
-                   str(e), locals)
+        outputs.extend(figure_bundles())
+        namespace.update(cell_uuid, env, exposed, execution_type)
+        handle_err(header, cell_uuid,
+                   str(e), namespace.locals_of(cell_uuid), outputs,
+                   traceback_of(e, statement))
         raise e
+    outputs.extend(figure_bundles())
 
-    print(f"Locals: {acc_locals}")
-    locals = locals_encode(locals_decoded, acc_locals, execution_type)
-    print(f"Locals encoded: {locals}")
-    for key, value in locals.items():
-        acc_locals[key] = value
+    namespace.update(cell_uuid, env, exposed, execution_type)
+    handle_send(header, cell_uuid,
+                namespace.locals_of(cell_uuid), outputs)
 
-    handle_send(notebook_uuid, cell_uuid, acc_locals)
 
-
-def handle_err(notebook_uuid, cell_uuid, err, locals):
+def handle_err(header, cell_uuid, err, locals, outputs, tb=None):
     error_msg = {
-        "notebook_uuid": notebook_uuid,
+        **header,
         "cell_uuid": cell_uuid,
         "locals": locals,
         "error": err,
         "ended": False,
+        "outputs": outputs,
+        "traceback": tb,
     }
     print(f"Sending error: {error_msg}")
     # pub_socket.send_multipart([
@@ -98,13 +311,31 @@ def handle_err(notebook_uuid, cell_uuid, err, locals):
     pub_socket.send(dill.dumps(error_msg))
 
 
-def handle_send(notebook_uuid, cell_uuid, locals, ended=False):
+def handle_status(header, cell_uuid, status, started=None, blocked_by=None):
+    """lifecycle event of a cell, with the milliseconds it ran if it was started"""
+    status_msg = {
+        **header,
+        "cell_uuid": cell_uuid,
+        "locals": {},
+        "ended": False,
+        "status": status,
+        "timestamp": int(time.time() * 1000),
+    }
+    if started is not None:
+        status_msg["duration"] = int((time.perf_counter() - started) * 1000)
+    if blocked_by is not None:
+        status_msg["blocked_by"] = blocked_by
+    pub_socket.send(dill.dumps(status_msg))
+
+
+def handle_send(header, cell_uuid, locals, outputs=None, ended=False):
     res_msg = {
-        "notebook_uuid": notebook_uuid,
+        **header,
         "cell_uuid": cell_uuid,
         "locals": locals,
         # "error": None,
         "ended": ended,
+        "outputs": outputs or [],
     }
     # print(f"Sending response: {res_msg}")
     print(f"Sending response")
@@ -115,69 +346,366 @@ def handle_send(notebook_uuid, cell_uuid, locals, ended=False):
     pub_socket.send(dill.dumps(res_msg))
 
 
-def eval_code(code, locals):
+def traceback_of(e, statement):
+    """type, message and frames of the exception, with the line numbers of the cells"""
+    frames = []
+    for frame in traceback.extract_tb(e.__traceback__):
+        # frames of the kernel itself, before the cell was entered, are left out
+        if not frames and not CELL_FILENAME.match(frame.filename):
+            continue
+        frames.append(frame_of(frame.filename, frame.name,
+                      frame.lineno, frame.line))
+    if isinstance(e, SyntaxError) and e.filename and CELL_FILENAME.match(e.filename):
+        frames.append(frame_of(e.filename, "<module>",
+                      e.lineno, (e.text or "").strip()))
+
+    start_line = statement.get("start_line", 0)
+    return {
+        "ename": type(e).__name__,
+        "evalue": str(e),
+        "frames": frames,
+        "start_line": start_line,
+        "end_line": statement.get("end_line", start_line),
+    }
+
+
+def frame_of(filename, name, lineno, source):
+    cell_uuid = None
+    line = lineno or 0
+    match = CELL_FILENAME.match(filename)
+    if match:
+        cell_uuid = match.group(1)
+        line += int(match.group(2)) - 1
+    return {
+        "cell_uuid": cell_uuid,
+        "filename": filename,
+        "name": name,
+        "line": line,
+        "source": source or None,
+    }
+
+
+def compile_statement(code, filename, mode):
+    # linecache provides the source lines of the frames in tracebacks
+    linecache.cache[filename] = (
+        len(code), None, code.splitlines(True), filename)
+    return compile(code, filename, mode)
+
+
+def eval_code(code, env, outputs, filename="<cell>"):
     f = StringIO()
     with redirect_stdout(f):
         try:
-            res = eval(code, {}, locals)
+            res = eval(compile_statement(code, filename, "eval"), env)
             if res is not None and res != "":
-                locals["<stdout>"] = res
+                outputs.append(mime_bundle(res))
         except Exception as e:
             raise e
     return f.getvalue()
 
 
-def exec_code(code, locals):
+def exec_code(code, env, filename="<cell>"):
     f = StringIO()
     with redirect_stdout(f):
         try:
-            exec(code, {}, locals)
+            exec(compile_statement(code, filename, "exec"), env)
         except Exception as e:
             raise e
     return f.getvalue()
 
 
-def locals_encode(locals, full_locals, new_type):
-    res = {}
+def cell_env(exposed, outputs):
+    """globals of a cell, the exposed values of other cells and display(*objs),
+    which collects mime bundles into outputs"""
+    def display(*objs):
+        for obj in objs:
+            outputs.append(mime_bundle(obj))
 
-    for key, value in locals.items():
-        if key in full_locals:
-            execution_type = full_locals[key]["local_type"]
-            if execution_type == "Definition":
-                dumped = dill.dumps(value)
-                res[key] = {
-                    "local_type": execution_type,
-                    "value": base64.b64encode(dumped).decode("utf-8")
-                }
-            else:
-                res[key] = {
-                    "local_type": execution_type,
-                    "value": value,
-                }
+    env = {"__builtins__": builtins, "display": display}
+    env.update(exposed)
+    return env
+
+
+def mime_bundle(obj):
+    """display data of obj, a mapping of mime type -> payload as in IPython's display formatter"""
+    data = {"text/plain": repr(obj)}
+    for method, mime_type in REPR_METHODS:
+        repr_method = getattr(obj, method, None)
+        if not callable(repr_method):
+            continue
+        try:
+            payload = repr_method()
+        except Exception:
+            continue
+        # _repr_*_ methods may return (data, metadata)
+        if isinstance(payload, tuple):
+            payload = payload[0]
+        if payload is None:
+            continue
+        if isinstance(payload, bytes):
+            payload = base64.b64encode(payload).decode("utf-8")
+        data[mime_type] = payload
+
+    if "image/png" not in data and is_figure(obj):
+        data["image/png"] = render_figure(obj)
+        # the figure is shown as the result, do not render it again in figure_bundles
+        sys.modules["matplotlib.pyplot"].close(obj)
+
+    return {"data": data, "metadata": {}}
+
+
+def stream_bundle(text, name="stdout"):
+    return {"data": {"text/plain": text}, "metadata": {"stream": name}}
+
+
+def is_figure(obj):
+    figure = sys.modules.get("matplotlib.figure")
+    return figure is not None and isinstance(obj, figure.Figure)
+
+
+def render_figure(fig):
+    buffer = BytesIO()
+    fig.savefig(buffer, format="png", bbox_inches="tight")
+    return base64.b64encode(buffer.getvalue()).decode("utf-8")
+
+
+def figure_bundles():
+    """renders and closes all open matplotlib figures, so plt.plot(...) shows up inline"""
+    plt = sys.modules.get("matplotlib.pyplot")
+    if plt is None:
+        return []
+
+    bundles = []
+    for num in plt.get_fignums():
+        fig = plt.figure(num)
+        bundles.append({
+            "data": {
+                "text/plain": repr(fig),
+                "image/png": render_figure(fig),
+            },
+            "metadata": {},
+        })
+        plt.close(fig)
+    return bundles
+
+
+def type_name(value):
+    value_type = type(value)
+    if value_type.__module__ == "builtins":
+        return value_type.__qualname__
+    return f"{value_type.__module__}.{value_type.__qualname__}"
+
+
+class SummaryRepr(reprlib.Repr):
+    """repr bounded in length and depth, remembers if it left something out"""
+
+    def __init__(self):
+        super().__init__()
+        self.maxstring = self.maxlong = self.maxother = REPR_LIMIT
+        # the repr is cut at REPR_LIMIT anyway, fewer items would hide short lists
+        self.maxlist = self.maxtuple = self.maxset = self.maxfrozenset = self.maxdict = 50
+        self.truncated = False
+
+    def repr(self, x):
+        self.truncated = False
+        return super().repr(x)
+
+    def repr1(self, x, level):
+        res = super().repr1(x, level)
+        if isinstance(x, (tuple, list, set, frozenset, dict)):
+            limit = getattr(self, "max" + type(x).__name__, self.maxlist)
+            self.truncated |= len(x) > (limit if level > 0 else 0)
         else:
-            if new_type == "Definition":
-                dumped = dill.dumps(value)
-                res[key] = {
-                    "local_type": new_type,
-                    "value": base64.b64encode(dumped).decode("utf-8")
-                }
-            else:
-                res[key] = {
-                    "local_type": new_type,
-                    "value": value,
-                }
+            # strings, numbers and other values are cut in the middle
+            self.truncated |= len(res) >= REPR_LIMIT and "..." in res
+        return res
 
-    return res
+
+def summarize(value):
+    """typed summary of a value, the value itself never leaves the kernel"""
+    summary_repr = SummaryRepr()
+    try:
+        value_repr = summary_repr.repr(value)
+    except Exception as e:
+        value_repr = f"<repr failed: {e}>"
+    truncated = summary_repr.truncated or len(value_repr) > REPR_LIMIT
+
+    length = None
+    try:
+        length = len(value)
+    except Exception:
+        pass
+
+    shape = None
+    try:
+        shape = [int(dim) for dim in value.shape]
+    except Exception:
+        pass
+
+    return {
+        "type_name": type_name(value),
+        "repr": value_repr[:REPR_LIMIT] + ("..." if len(value_repr) > REPR_LIMIT else ""),
+        "truncated": truncated,
+        "length": length,
+        "shape": shape,
+        "expandable": expandable(value),
+    }
+
+
+def expandable(value):
+    """if value has children, like children_keys but without listing them"""
+    if isinstance(value, (str, bytes, bytearray, int, float, complex, bool, type(None))):
+        return False
+    if isinstance(value, (dict, list, tuple, range, set, frozenset)):
+        return len(value) > 0
+    if hasattr(value, "shape") and hasattr(value, "__getitem__"):
+        try:
+            if hasattr(value, "columns"):
+                return len(value.columns) > 0
+            return len(value) > 0
+        except Exception:
+            return False
+    if callable(value) or isinstance(value, type(sys)):
+        return False
+    if hasattr(value, "__dict__"):
+        return any(not key.startswith("_") for key in vars(value))
+    return False
+
+
+def children_keys(value):
+    """keys to walk into value with child(value, key)"""
+    if isinstance(value, (str, bytes, bytearray, int, float, complex, bool, type(None))):
+        return []
+    if isinstance(value, dict):
+        return list(value.keys())
+    if isinstance(value, (list, tuple, range)):
+        return list(range(len(value)))
+    if isinstance(value, (set, frozenset)):
+        return list(range(len(value)))
+    # numpy arrays, pandas dataframes (columns) and series
+    if hasattr(value, "shape") and hasattr(value, "__getitem__"):
+        if hasattr(value, "columns"):
+            return list(value.columns)
+        if hasattr(value, "index") and not callable(value.index):
+            return list(value.index)
+        try:
+            return list(range(len(value)))
+        except Exception:
+            return []
+    if callable(value) or isinstance(value, type(sys)):
+        return []
+    if hasattr(value, "__dict__"):
+        return [key for key in vars(value) if not key.startswith("_")]
+    return []
+
+
+def set_order(value):
+    """sets have no order of their own, it is kept while the set stays the same size so the
+    indices of its children stay stable between inspections"""
+    entry = SET_ORDERS.get(id(value))
+    if entry is None or entry[0] is not value or len(entry[1]) != len(value):
+        entry = (value, sorted(value, key=repr))
+        SET_ORDERS[id(value)] = entry
+        if len(SET_ORDERS) > SET_ORDERS_LIMIT:
+            SET_ORDERS.popitem(last=False)
+    SET_ORDERS.move_to_end(id(value))
+    return entry[1]
+
+
+def child(value, key):
+    if isinstance(value, (set, frozenset)):
+        return set_order(value)[key]
+    if isinstance(value, (dict, list, tuple, range)) or hasattr(value, "shape"):
+        return value[key]
+    return getattr(value, key)
+
+
+def to_key(key):
+    """keys are sent to the client, they need to survive serialization"""
+    if isinstance(key, (str, int, float, bool)) or key is None:
+        return key
+    return repr(key)
+
+
+def handle_namespace(msg):
+    namespace = namespace_of(msg["notebook_uuid"])
+    return {
+        "notebook_uuid": msg["notebook_uuid"],
+        "cells": {
+            cell_uuid: namespace.locals_of(cell_uuid)
+            for cell_uuid in namespace.cells
+        },
+    }
 
 
-def locals_decode(locals):
+def to_json(value):
+    """values of calls leave the kernel as json, numpy and pandas values are converted and
+    everything else is sent as its repr"""
+    if hasattr(value, "columns") and hasattr(value, "to_dict"):
+        return value.to_dict(orient="records")
+    if hasattr(value, "to_dict"):
+        return value.to_dict()
+    if hasattr(value, "tolist"):
+        return value.tolist()
+    if isinstance(value, (set, frozenset, tuple)):
+        return list(value)
+    return repr(value)
+
+
+def handle_values(msg):
+    """values of names (name -> uuid of the defining cell) in a private namespace, falling
+    back to the namespace of the notebook like the call did"""
+    namespace = namespace_of(msg["namespace"])
+    shared = namespace_of(msg["notebook_uuid"])
     res = {}
-    for key, value in locals.items():
-        if value["local_type"] == "Definition":
-            decoded_bytes = base64.b64decode(value["value"])
-            res[key] = dill.loads(decoded_bytes)
-        else:
-            res[key] = value["value"]
+    for name, cell_uuid in msg["names"].items():
+        bindings = namespace.bindings_of(cell_uuid, shared)
+        if name not in bindings:
+            continue
+        try:
+            res[name] = json.loads(json.dumps(bindings[name], default=to_json, allow_nan=False))
+        except (TypeError, ValueError):
+            res[name] = repr(bindings[name])
+    return res
+
+
+def handle_inspect(msg):
+    res = {
+        "notebook_uuid": msg["notebook_uuid"],
+        "handle": msg["handle"],
+        "path": msg.get("path", []),
+        "summary": None,
+        "children": [],
+        "error": None,
+    }
+
+    store = namespace_of(msg["notebook_uuid"]).store
+    if msg["handle"] not in store.objects:
+        res["error"] = f"Unknown handle {msg['handle']}"
+        return res
+
+    try:
+        value = store.get(msg["handle"])
+        for key in msg.get("path", []):
+            keys = children_keys(value)
+            # keys from the client lose their type, e.g. tuples become strings
+            key = next((k for k in keys if to_key(k) == key), key)
+            value = child(value, key)
+
+        start = msg.get("start") or 0
+        end = msg.get("end")
+        if end is None:
+            end = start + INSPECT_LIMIT
+
+        res["summary"] = summarize(value)
+        for key in children_keys(value)[start:end]:
+            res["children"].append({
+                "key": to_key(key),
+                "summary": summarize(child(value, key)),
+            })
+    except Exception as e:
+        res["error"] = str(e)
+
     return res
 
 
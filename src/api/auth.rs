@@ -0,0 +1,257 @@
+use crate::{api::state::State, core::errors::NotebookError};
+use actix_web::{
+    dev::{Payload, Service, ServiceRequest, ServiceResponse},
+    http::header,
+    web::{self, Data},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    future::{ready, Future, Ready},
+    path::Path,
+    pin::Pin,
+};
+use tracing::info;
+
+// grants of a user which apply to every notebook
+const ANY_NOTEBOOK: &str = "*";
+
+/// what a user may do with a notebook, editing includes reading. Viewers of a published notebook
+/// do not see its code and run it with their own inputs, apart from everyone else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    View,
+    Read,
+    Edit,
+}
+
+/// a configured user, `notebooks` maps notebook uuids (or `*`) to its permission
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub token: String,
+
+    #[serde(default)]
+    pub notebooks: HashMap<String, Permission>,
+}
+
+/// who sent a request, set by the auth middleware
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    notebooks: HashMap<String, Permission>,
+}
+
+impl Identity {
+    pub fn permission(&self, notebook_uuid: &str) -> Option<Permission> {
+        self.notebooks
+            .get(notebook_uuid)
+            .or_else(|| self.notebooks.get(ANY_NOTEBOOK))
+            .copied()
+    }
+
+    pub fn require(
+        &self,
+        notebook_uuid: &str,
+        permission: Permission,
+    ) -> Result<(), NotebookError> {
+        match self.permission(notebook_uuid) {
+            Some(granted) if granted >= permission => Ok(()),
+            _ => Err(NotebookError::Forbidden),
+        }
+    }
+}
+
+impl FromRequest for Identity {
+    type Error = NotebookError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let identity = req.extensions().get::<Identity>().cloned();
+        ready(identity.ok_or(NotebookError::Unauthorized))
+    }
+}
+
+/// the token generated on startup, like the one of jupyter, can edit every notebook. More users
+/// with restricted permissions are read from a json file. Published notebooks can be viewed by
+/// anyone, without a token. Every notebook runs in the same kernel, so `Edit` on any notebook
+/// lets a user run code reaching every other one, permissions only isolate read only users
+#[derive(Debug, Clone)]
+pub struct Auth {
+    pub token: String,
+    users: Vec<User>,
+
+    /// uuids of the published notebooks
+    pub published: HashSet<String>,
+}
+
+impl Auth {
+    pub fn new(token: String, users: Vec<User>) -> Self {
+        Self {
+            token,
+            users,
+            published: HashSet::new(),
+        }
+    }
+
+    /// a given token replaces the generated one, `users` is a json file of `User`s
+    pub fn load(token: Option<String>, users: Option<&Path>) -> Result<Self, NotebookError> {
+        let token = token.unwrap_or_else(|| nanoid!(48));
+        let users = match users {
+            Some(path) => {
+                let json = std::fs::read_to_string(path)
+                    .map_err(|e| NotebookError::Config(format!("{}: {}", path.display(), e)))?;
+                serde_json::from_str(&json)?
+            }
+            None => Vec::new(),
+        };
+        info!("{} users configured", users.len());
+
+        Ok(Self::new(token, users))
+    }
+
+    /// requests without a valid token view published notebooks, and only those
+    pub fn viewer(&self) -> Option<Identity> {
+        if self.published.is_empty() {
+            return None;
+        }
+
+        Some(Identity {
+            name: String::from("viewer"),
+            notebooks: self
+                .published
+                .iter()
+                .map(|notebook_uuid| (notebook_uuid.clone(), Permission::View))
+                .collect(),
+        })
+    }
+
+    pub fn identify(&self, token: &str) -> Option<Identity> {
+        if constant_time_eq(token, &self.token) {
+            return Some(Identity {
+                name: String::from("owner"),
+                notebooks: HashMap::from([(ANY_NOTEBOOK.to_string(), Permission::Edit)]),
+            });
+        }
+
+        self.users
+            .iter()
+            .find(|user| constant_time_eq(token, &user.token))
+            .map(|user| Identity {
+                name: user.name.clone(),
+                notebooks: user.notebooks.clone(),
+            })
+    }
+}
+
+// does not stop at the first difference, so the time taken does not leak the token
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// the token of a request, browsers cannot set headers on websocket upgrades, so it is also
+/// accepted as `token` query parameter
+fn token_of(req: &ServiceRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.to_string());
+
+    bearer.or_else(|| {
+        web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.into_inner().remove("token"))
+    })
+}
+
+type ResponseFuture<B> = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>>;
+
+/// rejects requests without a known token, the others get their `Identity`
+pub fn authenticate<S, B: 'static>(req: ServiceRequest, srv: &S) -> ResponseFuture<B>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    let identity = req.app_data::<Data<State>>().and_then(|state| {
+        token_of(&req)
+            .and_then(|token| state.auth.identify(&token))
+            .or_else(|| state.auth.viewer())
+    });
+
+    match identity {
+        Some(identity) => {
+            req.extensions_mut().insert(identity);
+            Box::pin(srv.call(req))
+        }
+        None => Box::pin(ready(Err(NotebookError::Unauthorized.into()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions() {
+        let auth = Auth::new(
+            String::from("secret"),
+            vec![User {
+                name: String::from("ana"),
+                token: String::from("ana-token"),
+                notebooks: HashMap::from([
+                    (String::from("*"), Permission::Read),
+                    (String::from("nb"), Permission::Edit),
+                ]),
+            }],
+        );
+
+        let owner = auth.identify("secret").unwrap();
+        assert!(owner.require("any", Permission::Edit).is_ok());
+
+        let ana = auth.identify("ana-token").unwrap();
+        assert!(ana.require("nb", Permission::Edit).is_ok());
+        assert!(ana.require("other", Permission::Read).is_ok());
+        assert!(ana.require("other", Permission::Edit).is_err());
+
+        assert!(auth.identify("secre").is_none());
+        assert!(auth.identify("").is_none());
+        assert!(auth.viewer().is_none());
+    }
+
+    #[test]
+    fn test_token_of() {
+        let req = actix_web::test::TestRequest::with_uri("/ws/?notebookUuid=nb&token=a%2Bb%3D%26c")
+            .to_srv_request();
+        assert_eq!(token_of(&req).as_deref(), Some("a+b=&c"));
+
+        let req = actix_web::test::TestRequest::with_uri("/ws/?token=query")
+            .insert_header((header::AUTHORIZATION, "Bearer header"))
+            .to_srv_request();
+        assert_eq!(token_of(&req).as_deref(), Some("header"));
+
+        let req = actix_web::test::TestRequest::with_uri("/api/").to_srv_request();
+        assert_eq!(token_of(&req), None);
+    }
+
+    #[test]
+    fn test_viewers_of_published_notebooks() {
+        let auth = Auth {
+            published: HashSet::from([String::from("nb")]),
+            ..Auth::new(String::from("secret"), Vec::new())
+        };
+
+        let viewer = auth.viewer().unwrap();
+        assert_eq!(viewer.permission("nb"), Some(Permission::View));
+        assert!(viewer.require("nb", Permission::Read).is_err());
+        assert_eq!(viewer.permission("other"), None);
+    }
+}
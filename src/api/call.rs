@@ -0,0 +1,128 @@
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        state::State,
+    },
+    core::{
+        errors::{KernelErrors, NotebookError},
+        kernel_client::{MsgFromKernel, Origin},
+    },
+};
+use actix_web::{post, web, HttpResponse};
+use nanoid::nanoid;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+#[derive(Deserialize)]
+struct CallRequest {
+    /// parameter name -> value, see `Parameter`
+    #[serde(default)]
+    inputs: HashMap<String, Value>,
+
+    /// names of the variables to answer with
+    outputs: Vec<String>,
+}
+
+/// runs the notebook like a function: the parameters are set to the inputs and what the
+/// outputs depend on runs in a namespace of its own, answers with the values of the outputs
+#[post("/notebooks/{notebook_uuid}/call")]
+async fn call(
+    path: web::Path<String>,
+    req: web::Json<CallRequest>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let notebook_uuid = path.into_inner();
+    // a call runs the code of the notebook in its kernel, which read only users may not
+    identity.require(&notebook_uuid, Permission::Edit)?;
+
+    // calls do not see each other or change the notebook
+    let namespace = format!("{}/call-{}", notebook_uuid, nanoid!(12));
+    let started = Instant::now();
+    let res = run(&state, &notebook_uuid, &namespace, req.into_inner()).await;
+
+    state.sessions.lock()?.unlisten(&namespace);
+    if let Some(notebook) = state.open_notebooks.lock()?.get(&notebook_uuid) {
+        notebook.drop_namespace(&namespace)?;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "outputs": res?,
+        "duration": started.elapsed().as_millis() as u64,
+    })))
+}
+
+async fn run(
+    state: &State,
+    notebook_uuid: &str,
+    namespace: &str,
+    req: CallRequest,
+) -> Result<HashMap<String, Value>, NotebookError> {
+    let results = state.sessions.lock()?.listen(namespace);
+    let names = state
+        .open_notebooks
+        .lock()?
+        .get(notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?
+        .call(namespace, &req.inputs, &req.outputs, Origin::default())?;
+
+    let timeout = state.timeouts.call();
+    match web::block(move || wait_for_end(&results, timeout)).await {
+        Ok(res) => res?,
+        Err(e) => return Err(NotebookError::Internal(e.to_string())),
+    }
+
+    let (tx, rx) = mpsc::channel();
+    state
+        .open_notebooks
+        .lock()?
+        .get(notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?
+        .request_values(namespace, names, tx)?;
+    let timeout = state.timeouts.kernel_request();
+    let mut values = match web::block(move || rx.recv_timeout(timeout)).await {
+        Ok(Ok(values)) => values,
+        _ => return Err(KernelErrors::Timeout.into()),
+    };
+
+    req.outputs
+        .iter()
+        .map(|name| match values.remove(name) {
+            Some(value) => Ok((name.clone(), value)),
+            None => Err(NotebookError::Execution(format!(
+                "{} was not bound by its cell",
+                name
+            ))),
+        })
+        .collect()
+}
+
+/// waits until the plan of the call ended, fails with the first error of a cell
+fn wait_for_end(
+    results: &mpsc::Receiver<MsgFromKernel>,
+    timeout: Duration,
+) -> Result<(), NotebookError> {
+    let deadline = Instant::now() + timeout;
+    let mut failed = None;
+    loop {
+        let msg = results
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|_| KernelErrors::Timeout)?;
+        if msg.ended {
+            break;
+        }
+        if let (None, Some(error)) = (&failed, &msg.error) {
+            failed = Some(format!("{} in cell {}", error, msg.cell_uuid));
+        }
+    }
+
+    match failed {
+        Some(error) => Err(NotebookError::Execution(error)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::core::errors::{ErrorCode, NotebookError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::json;
+
+/// every endpoint answers errors with `{ "status": "error", "code": ..., "message": ... }`
+impl ResponseError for NotebookError {
+    fn status_code(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotebookNotFound | ErrorCode::CellNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MalformedMessage
+            | ErrorCode::UnknownCommand
+            | ErrorCode::HandshakeRequired
+            | ErrorCode::UnsupportedVersion
+            | ErrorCode::ParseError
+            | ErrorCode::NotACodeCell
+            | ErrorCode::InvalidInput
+            | ErrorCode::CycleDetected
+            | ErrorCode::InvalidOrder
+            | ErrorCode::EditMismatch
+            | ErrorCode::UnknownRevision => StatusCode::BAD_REQUEST,
+            ErrorCode::KernelUnavailable | ErrorCode::KernelError => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ErrorCode::KernelTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::ExecutionFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Persistence | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({
+            "status": "error",
+            "code": self.code(),
+            "message": self.to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::errors::TopologyErrors;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let res = NotebookError::from(TopologyErrors::CellNotFound).error_response();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let body = to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "status": "error", "code": "cell_not_found", "message": "Cell not found" })
+        );
+    }
+}
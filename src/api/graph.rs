@@ -0,0 +1,42 @@
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        state::State,
+    },
+    core::{errors::NotebookError, graph::GraphFormat},
+};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct GraphQuery {
+    #[serde(default)]
+    format: GraphFormat,
+}
+
+#[get("/notebooks/{notebook_uuid}/graph")]
+async fn graph(
+    path: web::Path<String>,
+    query: web::Query<GraphQuery>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let notebook_uuid = path.into_inner();
+    identity.require(&notebook_uuid, Permission::Read)?;
+
+    let notebooks = state.open_notebooks.lock()?;
+    let notebook = notebooks
+        .get(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+
+    let graph = notebook.dependency_graph();
+    let content_type = match query.format {
+        GraphFormat::Json => "application/json",
+        GraphFormat::Dot => "text/vnd.graphviz; charset=utf-8",
+        GraphFormat::Mermaid => "text/plain; charset=utf-8",
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .body(graph.render(query.format)))
+}
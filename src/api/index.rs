@@ -1,34 +1,35 @@
-use crate::{api::state::State, core::notebook::Notebook};
-use actix_web::{get, web, HttpResponse, Responder};
-use serde_json::json;
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        state::State,
+    },
+    core::{errors::NotebookError, notebook::Notebook},
+};
+use actix_web::{get, web, HttpResponse};
 
 #[get("/")]
-pub async fn index(state: web::Data<State>) -> impl Responder {
-    let open_notebooks = state.open_notebooks.lock();
-    if open_notebooks.is_err() {
-        return HttpResponse::InternalServerError()
-            .json(json!({ "status": "error", "message": "Could not lock notebooks" }));
-    }
-
-    let mut open_notebooks = open_notebooks.unwrap();
+pub async fn index(
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let mut open_notebooks = state.open_notebooks.lock()?;
     if !open_notebooks.is_empty() {
-        let notebook = open_notebooks
-            .get(&open_notebooks.keys().next().unwrap().clone())
-            .unwrap();
-        return HttpResponse::Ok().json(notebook);
+        // the first notebook the user may see, for viewers one which was published
+        let (notebook, permission) = open_notebooks
+            .values()
+            .find_map(|notebook| Some((notebook, identity.permission(&notebook.uuid)?)))
+            .ok_or(NotebookError::Forbidden)?;
+        return match permission {
+            Permission::View => Ok(HttpResponse::Ok().json(notebook.published())),
+            _ => Ok(HttpResponse::Ok().json(notebook)),
+        };
     }
 
-    let kernel_sender = match state.kernel_sender.lock() {
-        Ok(kernel_sender) => kernel_sender,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(json!({ "status": "error", "message": "Could not lock kernel sender" }));
-        }
-    };
-
+    let kernel_sender = state.kernel_sender.lock()?;
     let notebook = Notebook::new(kernel_sender.clone());
+    identity.require(&notebook.uuid, Permission::Edit)?;
     let notebook_uuid = notebook.uuid.clone();
     open_notebooks.insert(notebook_uuid, notebook.clone());
 
-    HttpResponse::Ok().json(notebook)
+    Ok(HttpResponse::Ok().json(notebook))
 }
@@ -1,6 +1,14 @@
+pub mod auth;
+mod call;
+mod errors;
+mod graph;
 mod index;
+mod parameters;
+mod protocol;
 mod reorder;
 pub mod routes;
+pub mod sessions;
 pub mod state;
+mod variables;
 mod ws;
 pub mod ws_client;
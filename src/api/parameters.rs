@@ -0,0 +1,73 @@
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        protocol::ServerMsg,
+        sessions::Broadcast,
+        state::State,
+    },
+    core::{errors::NotebookError, kernel_client::Origin},
+};
+use actix_web::{get, post, web, HttpResponse};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// the parameters of the notebook, see `Parameter`
+#[get("/notebooks/{notebook_uuid}/parameters")]
+async fn parameters(
+    path: web::Path<String>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let notebook_uuid = path.into_inner();
+    identity.require(&notebook_uuid, Permission::Read)?;
+
+    let notebooks = state.open_notebooks.lock()?;
+    let notebook = notebooks
+        .get(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+
+    Ok(HttpResponse::Ok().json(notebook.parameters()))
+}
+
+/// overrides the values of parameters (name -> value) and runs their cells and every cell
+/// depending on them, answers with the parameters
+#[post("/notebooks/{notebook_uuid}/parameters")]
+async fn set_parameters(
+    path: web::Path<String>,
+    values: web::Json<HashMap<String, Value>>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let notebook_uuid = path.into_inner();
+    identity.require(&notebook_uuid, Permission::Edit)?;
+
+    let mut notebooks = state.open_notebooks.lock()?;
+    let notebook = notebooks
+        .get_mut(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+    let edits = notebook.set_parameters(&values)?;
+    let changed = edits
+        .iter()
+        .map(|(cell_uuid, _, _)| cell_uuid.clone())
+        .collect::<Vec<_>>();
+
+    // the notebook stays locked, so no session sees a later change first
+    if let Ok(sessions) = state.sessions.lock() {
+        for (cell_uuid, revision, operation) in edits {
+            let msg = Broadcast {
+                origin: Origin::default(),
+                msg: ServerMsg::Edited {
+                    cell_uuid,
+                    revision,
+                    operation,
+                },
+            };
+            sessions.broadcast(&notebook_uuid, msg);
+        }
+    }
+    if !changed.is_empty() {
+        notebook.run_changed(&changed, Origin::default())?;
+    }
+
+    Ok(HttpResponse::Ok().json(notebook.parameters()))
+}
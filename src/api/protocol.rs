@@ -0,0 +1,605 @@
+use crate::{
+    api::auth::Permission,
+    core::{
+        cell::{Cell, LocalValue},
+        display::DisplayData,
+        errors::{ErrorCode, NotebookError},
+        kernel_client::{CellStatus, MsgFromKernel},
+        notebook::ExecutionMode,
+        ot::TextOperation,
+        traceback::Traceback,
+        value::{InspectRequest, InspectResponse, Variable},
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// bumped on every incompatible change of the messages below
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// messages from the client, every message may carry a `requestId` which is echoed on
+/// every response belonging to it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "cmd")]
+pub enum ClientMsg {
+    /// has to be the first message of a session. A reconnecting client resumes its previous
+    /// session with its id and the sequence number of the last cell result it has seen
+    Hello {
+        version: u32,
+
+        #[serde(rename = "sessionId", default)]
+        session_id: Option<String>,
+
+        #[serde(rename = "lastSeq", default)]
+        last_seq: Option<u64>,
+
+        /// the secret of the last `Welcome`, only with it the session is resumed
+        #[serde(rename = "resumeSecret", default)]
+        resume_secret: Option<String>,
+    },
+    /// runs the cell, with `content` replacing the current content if given
+    Run {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+
+        #[serde(default)]
+        content: Option<String>,
+    },
+    /// runs every cell in topological order
+    RunAll,
+    /// runs the cells which did not run with their current content or inputs, and every cell
+    /// depending on them
+    RunStale,
+    /// runs the selected cells and every cell they transitively depend on
+    RunCells {
+        #[serde(rename = "cellUuids")]
+        cell_uuids: Vec<String>,
+    },
+    /// edits the content of a cell, `revision` is the last revision the client has seen
+    Edit {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        revision: usize,
+        operation: TextOperation,
+    },
+    InsertCell {
+        index: usize,
+
+        #[serde(default)]
+        content: String,
+    },
+    DeleteCell {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+    },
+    Reorder {
+        #[serde(rename = "newOrder")]
+        new_order: Vec<String>,
+    },
+    /// disabled cells and every cell depending on them do not run
+    SetDisabled {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        disabled: bool,
+    },
+    /// marks the literal assignments of the cell as parameters, see `Parameter`
+    MarkParameters {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        parameters: bool,
+    },
+    /// sets the execution mode of the notebook or, with a `cellUuid`, of one cell
+    SetExecutionMode {
+        #[serde(rename = "cellUuid", default)]
+        cell_uuid: Option<String>,
+        mode: ExecutionMode,
+    },
+    /// sets the value of an input cell and runs it, see `Input`
+    SetInput {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        value: Value,
+    },
+    Inspect(InspectRequest),
+    Variables,
+    Ping,
+}
+
+impl ClientMsg {
+    /// commands changing or running the notebook, read only sessions may not send them
+    pub fn edits(&self) -> bool {
+        !matches!(
+            self,
+            ClientMsg::Hello { .. }
+                | ClientMsg::Inspect(_)
+                | ClientMsg::Variables
+                | ClientMsg::Ping
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "cmd")]
+pub enum ServerMsg {
+    /// `seq` is the latest sequence number of the notebook, `resumed` is false if results
+    /// the client missed are no longer available and it has to reload the notebook
+    Welcome {
+        version: u32,
+
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        permission: Permission,
+        seq: u64,
+        resumed: bool,
+
+        /// resumes this session after the connection dropped, see `Hello`
+        #[serde(rename = "resumeSecret", skip_serializing_if = "Option::is_none")]
+        resume_secret: Option<String>,
+    },
+    /// the command with the same request id was accepted
+    Ack,
+    /// the command with the same request id was rejected or failed
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+    /// a cell was (partially) executed
+    Res {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        locals: HashMap<String, LocalValue>,
+        outputs: Vec<DisplayData>,
+    },
+    /// the execution of a cell failed
+    Err {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        message: String,
+        locals: HashMap<String, LocalValue>,
+        outputs: Vec<DisplayData>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        traceback: Option<Traceback>,
+    },
+    /// a cell of an execution plan was queued, started running, finished, failed or was
+    /// blocked by a failed cell it depends on
+    Status {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        status: CellStatus,
+        timestamp: u64,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<u64>,
+
+        /// the failed cell a blocked cell depends on
+        #[serde(rename = "blockedBy", skip_serializing_if = "Option::is_none")]
+        blocked_by: Option<String>,
+    },
+    /// the content of a cell was edited, `operation` turns the previous revision into `revision`
+    Edited {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        revision: usize,
+        operation: TextOperation,
+    },
+    CellInserted {
+        index: usize,
+        cell: Box<Cell>,
+    },
+    CellDeleted {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+    },
+    Reordered {
+        #[serde(rename = "newOrder")]
+        new_order: Vec<String>,
+    },
+    ExecutionModeChanged {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: Option<String>,
+        mode: ExecutionMode,
+    },
+    CellDisabled {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        disabled: bool,
+    },
+    ParametersMarked {
+        #[serde(rename = "cellUuid")]
+        cell_uuid: String,
+        parameters: bool,
+    },
+    /// every cell of the notebook which is disabled or depends on a disabled cell, sent
+    /// whenever they change
+    Disabled {
+        #[serde(rename = "cellUuids")]
+        cell_uuids: Vec<String>,
+    },
+    /// every stale cell of the notebook, sent whenever they change
+    Stale {
+        #[serde(rename = "cellUuids")]
+        cell_uuids: Vec<String>,
+    },
+    Inspected(InspectResponse),
+    Variables {
+        variables: Vec<Variable>,
+    },
+    Pong,
+    /// number of sessions which have the notebook open, sent whenever it changes
+    Peers {
+        count: usize,
+    },
+}
+
+/// what is actually sent over the socket
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerEnvelope {
+    pub version: u32,
+
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+
+    /// set on cell results, see `Hello`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+
+    #[serde(flatten)]
+    pub msg: ServerMsg,
+}
+
+impl ServerEnvelope {
+    pub fn new(request_id: Option<String>, msg: ServerMsg) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            request_id,
+            seq: None,
+            msg,
+        }
+    }
+
+    pub fn error(request_id: Option<String>, code: ErrorCode, message: &str) -> Self {
+        Self::new(
+            request_id,
+            ServerMsg::Error {
+                code,
+                message: message.to_string(),
+            },
+        )
+    }
+}
+
+impl From<MsgFromKernel> for ServerEnvelope {
+    fn from(msg: MsgFromKernel) -> Self {
+        let server_msg = match (msg.status, msg.error) {
+            (Some(status), _) => ServerMsg::Status {
+                cell_uuid: msg.cell_uuid,
+                status,
+                timestamp: msg.timestamp.unwrap_or_default(),
+                duration: msg.duration,
+                blocked_by: msg.blocked_by,
+            },
+            (None, Some(message)) => ServerMsg::Err {
+                cell_uuid: msg.cell_uuid,
+                message,
+                locals: msg.locals,
+                outputs: msg.outputs,
+                traceback: msg.traceback,
+            },
+            (None, None) => ServerMsg::Res {
+                cell_uuid: msg.cell_uuid,
+                locals: msg.locals,
+                outputs: msg.outputs,
+            },
+        };
+
+        Self {
+            seq: msg.seq,
+            ..Self::new(msg.origin.request_id, server_msg)
+        }
+    }
+}
+
+impl From<&NotebookError> for ServerMsg {
+    fn from(err: &NotebookError) -> Self {
+        ServerMsg::Error {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// a client message which could not be parsed, keeps the request id if the message had one
+#[derive(Debug)]
+pub struct ProtocolError {
+    pub request_id: Option<String>,
+    pub error: NotebookError,
+}
+
+impl ProtocolError {
+    fn new(request_id: Option<String>, code: ErrorCode, message: &str) -> Self {
+        Self {
+            request_id,
+            error: NotebookError::Protocol(code, message.to_string()),
+        }
+    }
+}
+
+impl From<ProtocolError> for ServerEnvelope {
+    fn from(err: ProtocolError) -> Self {
+        Self::new(err.request_id, ServerMsg::from(&err.error))
+    }
+}
+
+pub fn parse_client_msg(text: &str) -> Result<(Option<String>, ClientMsg), ProtocolError> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| ProtocolError::new(None, ErrorCode::MalformedMessage, &e.to_string()))?;
+
+    let request_id = match value.get("requestId") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(request_id)) => Some(request_id.clone()),
+        Some(request_id) => Some(request_id.to_string()),
+    };
+
+    let cmd = match value.get("cmd") {
+        Some(Value::String(cmd)) => cmd.clone(),
+        _ => {
+            return Err(ProtocolError::new(
+                request_id,
+                ErrorCode::MalformedMessage,
+                "missing field `cmd`",
+            ))
+        }
+    };
+    // without any fields, an unknown variant can only be the command itself
+    if let Err(e) = serde_json::from_value::<ClientMsg>(serde_json::json!({ "cmd": cmd })) {
+        if e.to_string()
+            .starts_with(&format!("unknown variant `{}`", cmd))
+        {
+            return Err(ProtocolError::new(
+                request_id,
+                ErrorCode::UnknownCommand,
+                &e.to_string(),
+            ));
+        }
+    }
+
+    // the command is known, anything wrong now is in its fields
+    match serde_json::from_value::<ClientMsg>(value) {
+        Ok(msg) => Ok((request_id, msg)),
+        Err(e) => Err(ProtocolError::new(
+            request_id,
+            ErrorCode::MalformedMessage,
+            &e.to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::kernel_client::Origin;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_run() {
+        let (request_id, msg) = parse_client_msg(
+            r#"{ "requestId": "1", "cmd": "Run", "cellUuid": "abc", "content": "a = 1" }"#,
+        )
+        .unwrap();
+
+        assert_eq!(request_id, Some(String::from("1")));
+        match msg {
+            ClientMsg::Run { cell_uuid, content } => {
+                assert_eq!(cell_uuid, "abc");
+                assert_eq!(content, Some(String::from("a = 1")));
+            }
+            msg => panic!("expected run, got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_parse_hello_resume() {
+        let (_, msg) = parse_client_msg(
+            r#"{ "cmd": "Hello", "version": 1, "sessionId": "s1", "lastSeq": 12, "resumeSecret": "x" }"#,
+        )
+        .unwrap();
+
+        match msg {
+            ClientMsg::Hello {
+                version,
+                session_id,
+                last_seq,
+                resume_secret,
+            } => {
+                assert_eq!(version, PROTOCOL_VERSION);
+                assert_eq!(session_id, Some(String::from("s1")));
+                assert_eq!(last_seq, Some(12));
+                assert_eq!(resume_secret, Some(String::from("x")));
+            }
+            msg => panic!("expected hello, got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_parse_run_cells() {
+        let (_, msg) =
+            parse_client_msg(r#"{ "cmd": "RunCells", "cellUuids": ["abc", "def"] }"#).unwrap();
+        match msg {
+            ClientMsg::RunCells { cell_uuids } => assert_eq!(cell_uuids, vec!["abc", "def"]),
+            msg => panic!("expected run cells, got {:?}", msg),
+        }
+
+        let (_, msg) = parse_client_msg(r#"{ "cmd": "RunStale" }"#).unwrap();
+        assert!(matches!(msg, ClientMsg::RunStale));
+    }
+
+    #[test]
+    fn test_parse_edit() {
+        let (_, msg) = parse_client_msg(
+            r#"{ "cmd": "Edit", "cellUuid": "abc", "revision": 3, "operation": [{ "retain": 5 }, { "insert": "2" }] }"#,
+        )
+        .unwrap();
+
+        match msg {
+            ClientMsg::Edit {
+                revision,
+                operation,
+                ..
+            } => {
+                assert_eq!(revision, 3);
+                assert_eq!(operation.apply("a = 1").unwrap(), "a = 12");
+            }
+            msg => panic!("expected edit, got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_parse_inspect() {
+        let (request_id, msg) =
+            parse_client_msg(r#"{ "cmd": "Inspect", "handle": "h", "path": ["a", 1] }"#).unwrap();
+
+        assert_eq!(request_id, None);
+        match msg {
+            ClientMsg::Inspect(inspect) => {
+                assert_eq!(inspect.handle, "h");
+                assert_eq!(inspect.path, vec![json!("a"), json!(1)]);
+            }
+            msg => panic!("expected inspect, got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_parse_every_command() {
+        let msgs = [
+            json!({ "cmd": "Hello", "version": 1 }),
+            json!({ "cmd": "Run", "cellUuid": "abc" }),
+            json!({ "cmd": "RunAll" }),
+            json!({ "cmd": "RunStale" }),
+            json!({ "cmd": "RunCells", "cellUuids": ["abc"] }),
+            json!({ "cmd": "Edit", "cellUuid": "abc", "revision": 0, "operation": [] }),
+            json!({ "cmd": "InsertCell", "index": 0 }),
+            json!({ "cmd": "DeleteCell", "cellUuid": "abc" }),
+            json!({ "cmd": "Reorder", "newOrder": ["abc"] }),
+            json!({ "cmd": "SetDisabled", "cellUuid": "abc", "disabled": true }),
+            json!({ "cmd": "MarkParameters", "cellUuid": "abc", "parameters": true }),
+            json!({ "cmd": "SetExecutionMode", "mode": "Lazy" }),
+            json!({ "cmd": "SetInput", "cellUuid": "abc", "value": 1 }),
+            json!({ "cmd": "Inspect", "handle": "h", "path": [] }),
+            json!({ "cmd": "Variables" }),
+            json!({ "cmd": "Ping" }),
+        ];
+
+        for msg in msgs {
+            let (_, parsed) = parse_client_msg(&msg.to_string()).unwrap();
+            // a new command fails to compile here until it is added above
+            let cmd = match parsed {
+                ClientMsg::Hello { .. } => "Hello",
+                ClientMsg::Run { .. } => "Run",
+                ClientMsg::RunAll => "RunAll",
+                ClientMsg::RunStale => "RunStale",
+                ClientMsg::RunCells { .. } => "RunCells",
+                ClientMsg::Edit { .. } => "Edit",
+                ClientMsg::InsertCell { .. } => "InsertCell",
+                ClientMsg::DeleteCell { .. } => "DeleteCell",
+                ClientMsg::Reorder { .. } => "Reorder",
+                ClientMsg::SetDisabled { .. } => "SetDisabled",
+                ClientMsg::MarkParameters { .. } => "MarkParameters",
+                ClientMsg::SetExecutionMode { .. } => "SetExecutionMode",
+                ClientMsg::SetInput { .. } => "SetInput",
+                ClientMsg::Inspect(_) => "Inspect",
+                ClientMsg::Variables => "Variables",
+                ClientMsg::Ping => "Ping",
+            };
+            assert_eq!(msg["cmd"], cmd);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_keep_request_id() {
+        let err = parse_client_msg(r#"{ "requestId": "2", "cmd": "Run" }"#).unwrap_err();
+        assert_eq!(err.request_id, Some(String::from("2")));
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+
+        let err = parse_client_msg(r#"{ "requestId": 3, "cmd": "Explode" }"#).unwrap_err();
+        assert_eq!(err.request_id, Some(String::from("3")));
+        assert_eq!(err.error.code(), ErrorCode::UnknownCommand);
+
+        let err = parse_client_msg(r#"{ "requestId": "4" }"#).unwrap_err();
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+
+        let err = parse_client_msg(r#"{ "cmd": 5 }"#).unwrap_err();
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+
+        // a known command with an unknown value in its fields
+        let err =
+            parse_client_msg(r#"{ "cmd": "SetExecutionMode", "mode": "Eager" }"#).unwrap_err();
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+        let err = parse_client_msg(r#"{ "cmd": "SetExecutionMode", "mode": "SetExecutionMode" }"#)
+            .unwrap_err();
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+
+        let err = parse_client_msg("not json").unwrap_err();
+        assert_eq!(err.request_id, None);
+        assert_eq!(err.error.code(), ErrorCode::MalformedMessage);
+    }
+
+    #[test]
+    fn test_serialize_envelope() {
+        let envelope = ServerEnvelope::new(Some(String::from("5")), ServerMsg::Ack);
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            json!({ "version": PROTOCOL_VERSION, "requestId": "5", "cmd": "Ack" })
+        );
+
+        let envelope = ServerEnvelope::error(None, ErrorCode::HandshakeRequired, "say hello");
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            json!({
+                "version": PROTOCOL_VERSION,
+                "cmd": "Error",
+                "code": "handshake_required",
+                "message": "say hello"
+            })
+        );
+    }
+
+    #[test]
+    fn test_kernel_error_to_envelope() {
+        let msg = MsgFromKernel {
+            cell_uuid: String::from("abc"),
+            error: Some(String::from("division by zero")),
+            origin: Origin {
+                session_id: None,
+                request_id: Some(String::from("6")),
+            },
+            seq: Some(7),
+            ..Default::default()
+        };
+
+        let value = serde_json::to_value(ServerEnvelope::from(msg)).unwrap();
+        assert_eq!(value["cmd"], "Err");
+        assert_eq!(value["cellUuid"], "abc");
+        assert_eq!(value["message"], "division by zero");
+        assert_eq!(value["requestId"], "6");
+        assert_eq!(value["seq"], 7);
+    }
+
+    #[test]
+    fn test_status_to_envelope() {
+        let msg = MsgFromKernel {
+            cell_uuid: String::from("abc"),
+            status: Some(CellStatus::Finished),
+            timestamp: Some(1_700_000_000_000),
+            duration: Some(42),
+            ..Default::default()
+        };
+
+        let value = serde_json::to_value(ServerEnvelope::from(msg)).unwrap();
+        assert_eq!(value["cmd"], "Status");
+        assert_eq!(value["status"], "Finished");
+        assert_eq!(value["timestamp"], 1_700_000_000_000u64);
+        assert_eq!(value["duration"], 42);
+    }
+}
@@ -1,5 +1,13 @@
-use crate::api::state::State;
-use actix_web::{post, web, HttpResponse, Responder};
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        protocol::ServerMsg,
+        sessions::Broadcast,
+        state::State,
+    },
+    core::{errors::NotebookError, kernel_client::Origin},
+};
+use actix_web::{post, web, HttpResponse};
 use serde::Deserialize;
 use serde_json::json;
 
@@ -13,21 +21,29 @@ struct ReorderRequest {
 }
 
 #[post("/reorder")]
-async fn reorder_cells(req: web::Json<ReorderRequest>, state: web::Data<State>) -> impl Responder {
+async fn reorder_cells(
+    req: web::Json<ReorderRequest>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
     let notebook_uuid = req.notebook_uuid.clone();
+    identity.require(&notebook_uuid, Permission::Edit)?;
     let new_order = req.new_order.clone();
 
-    let notebooks = state.open_notebooks.lock();
-    if notebooks.is_err() {
-        return HttpResponse::InternalServerError()
-            .json(json!({ "status": "error", "message": "Could not lock notebooks" }));
+    let mut notebooks = state.open_notebooks.lock()?;
+    let notebook = notebooks
+        .get_mut(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+    notebook.reorder_cells(&new_order)?;
+
+    // the notebook stays locked, so no session sees a later change first
+    if let Ok(sessions) = state.sessions.lock() {
+        let msg = Broadcast {
+            origin: Origin::default(),
+            msg: ServerMsg::Reordered { new_order },
+        };
+        sessions.broadcast(&notebook_uuid, msg);
     }
-    let mut notebooks = notebooks.unwrap();
-    let notebook = match notebooks.get_mut(&notebook_uuid) {
-        Some(notebook) => notebook,
-        None => return HttpResponse::NotFound().json(json!({ "status": "Notebook not found" })),
-    };
-    notebook.reorder_cells(&new_order);
 
-    HttpResponse::Ok().json(json!({ "status": "ok" }))
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
 }
@@ -1,11 +1,78 @@
-use super::{index::index, reorder::reorder_cells, ws::ws_route};
-use actix_web::web;
+use super::{
+    call::call,
+    graph::graph,
+    index::index,
+    parameters::{parameters, set_parameters},
+    reorder::reorder_cells,
+    variables::variables,
+    ws::ws_route,
+};
+use actix_files::{Files, NamedFile};
+use actix_web::{
+    dev::{fn_service, ServiceRequest, ServiceResponse},
+    web,
+};
+use std::path::Path;
 
 pub fn notebook_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(index);
     cfg.service(reorder_cells);
+    cfg.service(variables);
+    cfg.service(graph);
+    cfg.service(parameters);
+    cfg.service(set_parameters);
+    cfg.service(call);
 }
 
 pub fn ws_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(ws_route);
 }
+
+/// the built client, paths which are no files are routes of the client and get its index.html
+pub fn client_files(dir: &Path) -> Files {
+    let index_html = dir.join("index.html");
+    Files::new("/", dir)
+        .index_file("index.html")
+        .default_handler(fn_service(move |req: ServiceRequest| {
+            let index_html = index_html.clone();
+            async move {
+                let (req, _) = req.into_parts();
+                let res = NamedFile::open_async(index_html).await?.into_response(&req);
+                Ok(ServiceResponse::new(req, res))
+            }
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_web::test]
+    async fn test_client_files() {
+        let dir = std::env::temp_dir().join(format!("notebook-client-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<div id=\"root\"></div>").unwrap();
+        std::fs::write(dir.join("assets/index.js"), "render()").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .service(web::scope("/api").route("/", web::get().to(|| async { "api" })))
+                .service(client_files(&dir)),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        for (uri, body) in [
+            ("/?token=abc", "<div id=\"root\"></div>"),
+            ("/assets/index.js", "render()"),
+            ("/notebooks/abc", "<div id=\"root\"></div>"),
+            ("/api/", "api"),
+        ] {
+            let res = test::call_service(&app, get(uri)).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(test::read_body(res).await, body, "{}", uri);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{
+    api::{
+        auth::constant_time_eq,
+        protocol::{ServerEnvelope, ServerMsg},
+        ws_client::WsClient,
+    },
+    core::kernel_client::{MsgFromKernel, Origin},
+};
+use actix::{Addr, Handler, Message};
+use nanoid::nanoid;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// cell results kept per notebook for reconnecting sessions, at most this many and this many
+// bytes of them
+const REPLAY_CAPACITY: usize = 512;
+const REPLAY_BYTES: usize = 16 * 1024 * 1024;
+
+// how long a session which lost its connection can be resumed
+const RESUME_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// tells a session how many sessions (including itself) have its notebook open
+#[derive(Debug, Clone)]
+pub struct Peers {
+    pub count: usize,
+}
+
+impl Message for Peers {
+    type Result = ();
+}
+
+/// a change of the notebook every session has to see, the request id of `origin` is only
+/// passed on to the session which sent the request
+#[derive(Debug, Clone)]
+pub struct Broadcast {
+    pub origin: Origin,
+    pub msg: ServerMsg,
+}
+
+impl Message for Broadcast {
+    type Result = ();
+}
+
+/// the latest cell results of a notebook, numbered by a sequence number
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    max_bytes: usize,
+    last_seq: u64,
+    results: VecDeque<MsgFromKernel>,
+
+    /// serialized size of each result, and of all of them
+    sizes: VecDeque<usize>,
+    bytes: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        Self {
+            capacity,
+            max_bytes,
+            last_seq: 0,
+            results: VecDeque::with_capacity(capacity),
+            sizes: VecDeque::with_capacity(capacity),
+            bytes: 0,
+        }
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// assigns the next sequence number to the result and keeps it, the oldest results are
+    /// dropped to stay within the capacity and the bytes
+    pub fn push(&mut self, mut msg: MsgFromKernel) -> MsgFromKernel {
+        self.last_seq += 1;
+        msg.seq = Some(self.last_seq);
+
+        let size = serde_json::to_vec(&msg)
+            .map(|json| json.len())
+            .unwrap_or_default();
+        self.results.push_back(msg.clone());
+        self.sizes.push_back(size);
+        self.bytes += size;
+        while self.results.len() > self.capacity || self.bytes > self.max_bytes {
+            self.results.pop_front();
+            self.bytes -= self.sizes.pop_front().unwrap_or_default();
+        }
+
+        msg
+    }
+
+    /// every result after `last_seq`, `None` if some of them were already dropped
+    pub fn since(&self, last_seq: u64) -> Option<Vec<MsgFromKernel>> {
+        let dropped = self.last_seq - self.results.len() as u64;
+        if last_seq < dropped || last_seq > self.last_seq {
+            return None;
+        }
+
+        let missed = (self.last_seq - last_seq) as usize;
+        Some(
+            self.results
+                .iter()
+                .skip(self.results.len() - missed)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// what a later connection has to show to take over a session
+#[derive(Debug)]
+struct Resumable {
+    notebook_uuid: String,
+    user: String,
+    secret: String,
+
+    /// set once the session has no connection anymore
+    left: Option<Instant>,
+}
+
+impl Resumable {
+    fn resumable(&self) -> bool {
+        match self.left {
+            Some(left) => left.elapsed() < RESUME_WINDOW,
+            None => true,
+        }
+    }
+}
+
+/// every connected websocket session, grouped by notebook
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    notebooks: HashMap<String, HashMap<String, Addr<WsClient>>>, // notebook_uuid -> session_id -> ws
+    replay: HashMap<String, ReplayBuffer>,                       // notebook_uuid -> results
+    listeners: HashMap<String, Vec<mpsc::Sender<MsgFromKernel>>>, // notebook_uuid -> listeners
+    resumable: HashMap<String, Resumable>,                       // session_id -> secret
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, notebook_uuid: &str, session_id: &str, addr: Addr<WsClient>) {
+        self.notebooks
+            .entry(notebook_uuid.to_string())
+            .or_default()
+            .insert(session_id.to_string(), addr);
+        self.announce_peers(notebook_uuid);
+    }
+
+    /// only removes the session if it is still registered with `addr`, a resumed session
+    /// takes over the id of its previous connection
+    pub fn unregister(&mut self, notebook_uuid: &str, session_id: &str, addr: &Addr<WsClient>) {
+        if let Some(sessions) = self.notebooks.get_mut(notebook_uuid) {
+            if sessions.get(session_id) == Some(addr) {
+                sessions.remove(session_id);
+                if let Some(resumable) = self.resumable.get_mut(session_id) {
+                    resumable.left = Some(Instant::now());
+                }
+            }
+            if sessions.is_empty() {
+                self.notebooks.remove(notebook_uuid);
+            }
+        }
+        self.announce_peers(notebook_uuid);
+    }
+
+    /// a new secret for resuming the session, it replaces the previous one
+    pub fn resume_secret(&mut self, notebook_uuid: &str, session_id: &str, user: &str) -> String {
+        self.resumable.retain(|_, resumable| resumable.resumable());
+
+        let secret = nanoid!(32);
+        let resumable = Resumable {
+            notebook_uuid: notebook_uuid.to_string(),
+            user: user.to_string(),
+            secret: secret.clone(),
+            left: None,
+        };
+        self.resumable.insert(session_id.to_string(), resumable);
+
+        secret
+    }
+
+    /// only the same user may resume a session of the notebook, with the latest secret issued
+    /// for it. The secret is used up
+    pub fn resume(
+        &mut self,
+        notebook_uuid: &str,
+        session_id: &str,
+        user: &str,
+        secret: &str,
+    ) -> bool {
+        let valid = self.resumable.get(session_id).is_some_and(|resumable| {
+            resumable.notebook_uuid == notebook_uuid
+                && resumable.user == user
+                && resumable.resumable()
+                && constant_time_eq(&resumable.secret, secret)
+        });
+        if valid {
+            self.resumable.remove(session_id);
+        }
+
+        valid
+    }
+
+    pub fn count(&self, notebook_uuid: &str) -> usize {
+        self.notebooks
+            .get(notebook_uuid)
+            .map(|sessions| sessions.len())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, notebook_uuid: &str, session_id: &str) -> Option<&Addr<WsClient>> {
+        self.notebooks
+            .get(notebook_uuid)
+            .and_then(|sessions| sessions.get(session_id))
+    }
+
+    /// sends `msg` to every session of the notebook, returns how many received it
+    pub fn broadcast<M>(&self, notebook_uuid: &str, msg: M) -> usize
+    where
+        M: Message<Result = ()> + Send + Clone + 'static,
+        WsClient: Handler<M>,
+    {
+        let sessions = match self.notebooks.get(notebook_uuid) {
+            Some(sessions) => sessions,
+            None => return 0,
+        };
+        for addr in sessions.values() {
+            addr.do_send(msg.clone());
+        }
+
+        sessions.len()
+    }
+
+    /// tells every session of the notebook which cells are stale
+    pub fn broadcast_stale(&self, notebook_uuid: &str, cell_uuids: Vec<String>) -> usize {
+        let msg = Broadcast {
+            origin: Origin::default(),
+            msg: ServerMsg::Stale { cell_uuids },
+        };
+
+        self.broadcast(notebook_uuid, msg)
+    }
+
+    /// numbers the result, keeps it for replays and sends it to every session and listener.
+    /// Statuses are not kept, only the latest one of a cell matters and they would push results
+    /// out of the replay buffer
+    pub fn broadcast_result(&mut self, msg: MsgFromKernel) -> usize {
+        let notebook_uuid = msg.notebook_uuid.clone();
+        if msg.status.is_some() {
+            let listeners = self.notify(&notebook_uuid, &msg);
+            let status = Broadcast {
+                origin: msg.origin.clone(),
+                msg: ServerEnvelope::from(msg).msg,
+            };
+            return listeners + self.broadcast(&notebook_uuid, status);
+        }
+        let msg = self
+            .replay
+            .entry(notebook_uuid.clone())
+            .or_insert_with(|| ReplayBuffer::new(REPLAY_CAPACITY, REPLAY_BYTES))
+            .push(msg);
+
+        self.notify(&notebook_uuid, &msg) + self.broadcast(&notebook_uuid, msg)
+    }
+
+    /// results of the notebook, or of a private namespace, without a websocket session, e.g.
+    /// of headless runs. Listeners also get the message ending each run, see `MsgFromKernel::ended`
+    pub fn listen(&mut self, key: &str) -> mpsc::Receiver<MsgFromKernel> {
+        let (tx, rx) = mpsc::channel();
+        self.listeners.entry(key.to_string()).or_default().push(tx);
+
+        rx
+    }
+
+    /// drops the listeners of the notebook or namespace
+    pub fn unlisten(&mut self, key: &str) {
+        self.listeners.remove(key);
+    }
+
+    /// sends `msg` to the listeners of the notebook or namespace which are still there, returns
+    /// how many
+    pub fn notify(&mut self, key: &str, msg: &MsgFromKernel) -> usize {
+        let listeners = match self.listeners.get_mut(key) {
+            Some(listeners) => listeners,
+            None => return 0,
+        };
+        listeners.retain(|listener| listener.send(msg.clone()).is_ok());
+
+        let count = listeners.len();
+        if count == 0 {
+            self.listeners.remove(key);
+        }
+        count
+    }
+
+    pub fn last_seq(&self, notebook_uuid: &str) -> u64 {
+        self.replay
+            .get(notebook_uuid)
+            .map(|replay| replay.last_seq())
+            .unwrap_or_default()
+    }
+
+    pub fn replay(&self, notebook_uuid: &str, last_seq: u64) -> Option<Vec<MsgFromKernel>> {
+        match self.replay.get(notebook_uuid) {
+            Some(replay) => replay.since(last_seq),
+            None if last_seq == 0 => Some(Vec::new()),
+            None => None,
+        }
+    }
+
+    fn announce_peers(&self, notebook_uuid: &str) {
+        let count = self.count(notebook_uuid);
+        self.broadcast(notebook_uuid, Peers { count });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::kernel_client::CellStatus;
+
+    fn result(cell_uuid: &str) -> MsgFromKernel {
+        MsgFromKernel {
+            cell_uuid: cell_uuid.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_since() {
+        let mut replay = ReplayBuffer::new(3, REPLAY_BYTES);
+        assert_eq!(replay.since(0).unwrap().len(), 0);
+
+        for cell_uuid in ["a", "b", "c"] {
+            replay.push(result(cell_uuid));
+        }
+        let missed = replay.since(1).unwrap();
+        let missed = missed
+            .iter()
+            .map(|msg| (msg.seq.unwrap(), msg.cell_uuid.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(missed, vec![(2, "b"), (3, "c")]);
+        assert!(replay.since(3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_listeners() {
+        let mut sessions = SessionRegistry::new();
+        let listener = sessions.listen("nb");
+        let other = sessions.listen("other");
+        drop(other);
+
+        let msg = MsgFromKernel {
+            notebook_uuid: String::from("nb"),
+            ..result("a")
+        };
+        assert_eq!(sessions.broadcast_result(msg), 1);
+        assert_eq!(listener.try_recv().unwrap().seq, Some(1));
+
+        let msg = MsgFromKernel {
+            notebook_uuid: String::from("other"),
+            ..result("a")
+        };
+        assert_eq!(sessions.broadcast_result(msg), 0);
+        assert!(!sessions.listeners.contains_key("other"));
+
+        let call = sessions.listen("nb/call");
+        assert_eq!(sessions.notify("nb/call", &result("a")), 1);
+        assert!(call.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_statuses_are_not_replayed() {
+        let mut sessions = SessionRegistry::new();
+        let listener = sessions.listen("nb");
+
+        let status = MsgFromKernel {
+            notebook_uuid: String::from("nb"),
+            status: Some(CellStatus::Queued),
+            ..result("a")
+        };
+        assert_eq!(sessions.broadcast_result(status), 1);
+        assert_eq!(listener.try_recv().unwrap().seq, None);
+        assert_eq!(sessions.last_seq("nb"), 0);
+        assert!(sessions.replay("nb", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replay_drops_oldest() {
+        let mut replay = ReplayBuffer::new(2, REPLAY_BYTES);
+        for cell_uuid in ["a", "b", "c"] {
+            replay.push(result(cell_uuid));
+        }
+
+        assert_eq!(replay.last_seq(), 3);
+        // result 1 is gone
+        assert!(replay.since(0).is_none());
+        assert_eq!(replay.since(1).unwrap().len(), 2);
+        // sequence numbers from the future are not trusted either
+        assert!(replay.since(4).is_none());
+    }
+
+    #[test]
+    fn test_replay_drops_beyond_bytes() {
+        let size = serde_json::to_vec(&ReplayBuffer::new(1, 0).push(result("a")))
+            .unwrap()
+            .len();
+        let mut replay = ReplayBuffer::new(512, 2 * size);
+        for cell_uuid in ["a", "b", "c"] {
+            replay.push(result(cell_uuid));
+        }
+        assert!(replay.since(0).is_none());
+        assert_eq!(replay.since(1).unwrap().len(), 2);
+
+        // a result larger than the buffer is not kept at all
+        let large = MsgFromKernel {
+            error: Some("x".repeat(4 * size)),
+            ..result("d")
+        };
+        replay.push(large);
+        assert!(replay.since(3).is_none());
+        assert!(replay.since(4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut sessions = SessionRegistry::new();
+        let secret = sessions.resume_secret("nb", "s1", "ana");
+
+        assert!(!sessions.resume("nb", "s1", "ana", "guessed"));
+        assert!(!sessions.resume("nb", "s1", "bob", &secret));
+        assert!(!sessions.resume("other", "s1", "ana", &secret));
+        assert!(!sessions.resume("nb", "s2", "ana", &secret));
+        assert!(sessions.resume("nb", "s1", "ana", &secret));
+        // a secret is only good once
+        assert!(!sessions.resume("nb", "s1", "ana", &secret));
+    }
+}
@@ -1,30 +1,77 @@
-use crate::core::{
-    kernel_client::{KernelClient, KernelClientMsg},
-    notebook::Notebook,
+use crate::{
+    api::{auth::Auth, sessions::SessionRegistry},
+    config::{Config, Timeouts},
+    core::{
+        errors::NotebookError,
+        kernel_client::{KernelClient, KernelClientMsg},
+        notebook::Notebook,
+    },
 };
 use std::{
     collections::HashMap,
     sync::{mpsc::Sender, Arc, Mutex},
-    thread,
+    thread::{self, JoinHandle},
 };
+use tracing::warn;
+
+/// notebook_uuid -> notebook, shared by every session and request
+pub type OpenNotebooks = Arc<Mutex<HashMap<String, Notebook>>>;
 
 pub struct State {
-    pub open_notebooks: Arc<Mutex<HashMap<String, Notebook>>>,
+    pub open_notebooks: OpenNotebooks,
     pub kernel_sender: Arc<Mutex<Sender<KernelClientMsg>>>,
+    pub sessions: Arc<Mutex<SessionRegistry>>,
+    pub auth: Auth,
+    pub timeouts: Timeouts,
+    kernel_client: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl State {
-    pub fn new() -> Self {
-        let mut kernel_client = KernelClient::new().expect("Could not create kernel client");
+    /// starts the kernel client, fails if the kernel can not be started
+    pub fn new(auth: Auth, config: &Config) -> Result<Self, NotebookError> {
+        let sessions = Arc::new(Mutex::new(SessionRegistry::new()));
+        let open_notebooks = Arc::new(Mutex::new(HashMap::new()));
+        let mut kernel_client = KernelClient::new(
+            Arc::clone(&sessions),
+            Arc::clone(&open_notebooks),
+            &config.kernel,
+        )?;
         let sender = kernel_client.tx.clone();
 
-        thread::spawn(move || {
+        let kernel_client = thread::spawn(move || {
             kernel_client.start();
         });
 
-        Self {
-            open_notebooks: Arc::new(Mutex::new(HashMap::new())),
+        Ok(Self {
+            open_notebooks,
             kernel_sender: Arc::new(Mutex::new(sender)),
+            sessions,
+            auth,
+            timeouts: config.timeouts,
+            kernel_client: Mutex::new(Some(kernel_client)),
+        })
+    }
+
+    /// connects the notebook to the kernel and opens it, returns its uuid
+    pub fn open(&self, mut notebook: Notebook) -> Result<String, NotebookError> {
+        notebook.kernel_sender = Some(self.kernel_sender.lock()?.clone());
+        let notebook_uuid = notebook.uuid.clone();
+        self.open_notebooks
+            .lock()?
+            .insert(notebook_uuid.clone(), notebook);
+
+        Ok(notebook_uuid)
+    }
+
+    /// stops the kernel and waits for the kernel client to finish
+    pub fn shutdown(&self) -> Result<(), NotebookError> {
+        self.kernel_sender.lock()?.send(KernelClientMsg::Shutdown)?;
+        if let Some(kernel_client) = self.kernel_client.lock()?.take() {
+            if kernel_client.join().is_err() {
+                warn!("Kernel client panicked");
+            }
         }
+
+        Ok(())
     }
 }
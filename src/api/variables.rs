@@ -0,0 +1,44 @@
+use crate::{
+    api::{
+        auth::{Identity, Permission},
+        state::State,
+    },
+    core::{
+        errors::{KernelErrors, NotebookError},
+        kernel_client::Origin,
+    },
+};
+use actix_web::{get, web, HttpResponse};
+use std::sync::mpsc;
+
+#[get("/notebooks/{notebook_uuid}/variables")]
+async fn variables(
+    path: web::Path<String>,
+    state: web::Data<State>,
+    identity: Identity,
+) -> Result<HttpResponse, NotebookError> {
+    let notebook_uuid = path.into_inner();
+    identity.require(&notebook_uuid, Permission::Read)?;
+
+    let (tx, rx) = mpsc::channel();
+    state
+        .open_notebooks
+        .lock()?
+        .get(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?
+        .request_namespace(Origin::default(), Some(tx))?;
+
+    // the kernel answers after the plan it is currently executing
+    let timeout = state.timeouts.kernel_request();
+    let namespace = match web::block(move || rx.recv_timeout(timeout)).await {
+        Ok(Ok(namespace)) => namespace,
+        _ => return Err(KernelErrors::Timeout.into()),
+    };
+
+    let notebooks = state.open_notebooks.lock()?;
+    let notebook = notebooks
+        .get(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+
+    Ok(HttpResponse::Ok().json(notebook.variables(&namespace)))
+}
@@ -1,11 +1,11 @@
 use crate::{
-    api::{state::State, ws_client::WsClient},
-    core::kernel_client::KernelClientMsg,
+    api::{auth::Identity, state::State, ws_client::WsClient},
+    core::errors::NotebookError,
 };
 use actix_web::{get, web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use serde::Deserialize;
-use serde_json::json;
+use std::sync::Arc;
 use tracing::info;
 
 #[derive(Deserialize)]
@@ -20,35 +20,33 @@ async fn ws_route(
     state: web::Data<State>,
     query: web::Query<WsQuery>,
     stream: web::Payload,
+    identity: Identity,
 ) -> Result<HttpResponse, actix_web::Error> {
     let notebook_uuid = query.notebook_uuid.clone();
-    info!("Opening websocket for notebook {}", notebook_uuid);
+    info!(
+        "Opening websocket for notebook {} for {}",
+        notebook_uuid, identity.name
+    );
+    let permission = identity
+        .permission(&notebook_uuid)
+        .ok_or(NotebookError::Forbidden)?;
 
-    let notebooks = state.open_notebooks.lock();
-    if notebooks.is_err() {
-        return Ok(HttpResponse::InternalServerError()
-            .json(json!({ "status": "error", "message": "Could not lock notebooks" })));
+    if !state
+        .open_notebooks
+        .lock()
+        .map_err(NotebookError::from)?
+        .contains_key(&notebook_uuid)
+    {
+        return Err(NotebookError::NotebookNotFound.into());
     }
-    let notebooks = notebooks.unwrap();
-    let notebook = match notebooks.get(&notebook_uuid) {
-        Some(notebook) => notebook,
-        None => return Ok(HttpResponse::NotFound().json(json!({ "status": "Notebook not found" }))),
-    };
 
-    let kernel_sender = match state.kernel_sender.lock() {
-        Ok(kernel_sender) => kernel_sender,
-        Err(_) => {
-            return Ok(HttpResponse::InternalServerError()
-                .json(json!({ "status": "error", "message": "Could not lock kernel sender" })));
-        }
-    };
-    let kernel_sender = kernel_sender.clone();
-
-    let ws_socket = WsClient::new(notebook);
-    let (addr, res) = ws::WsResponseBuilder::new(ws_socket, &req, stream).start_with_addr()?;
-
-    let kernel_init_msg = KernelClientMsg::InitWs(notebook.uuid.clone(), addr.clone());
-    kernel_sender.send(kernel_init_msg).unwrap();
-
-    Ok(res)
+    // the session registers itself once started
+    let ws_socket = WsClient::new(
+        &notebook_uuid,
+        Arc::clone(&state.open_notebooks),
+        Arc::clone(&state.sessions),
+        &identity.name,
+        permission,
+    );
+    ws::start(ws_socket, &req, stream)
 }
@@ -1,40 +1,139 @@
-use std::collections::HashMap;
-
-use crate::core::{cell::LocalValue, kernel_client::MsgFromKernel, notebook::Notebook};
-use actix::{Actor, Handler, StreamHandler};
+use crate::{
+    api::{
+        auth::Permission,
+        protocol::{parse_client_msg, ClientMsg, ServerEnvelope, ServerMsg, PROTOCOL_VERSION},
+        sessions::{Broadcast, Peers, SessionRegistry},
+        state::OpenNotebooks,
+    },
+    core::{
+        errors::{ErrorCode, NotebookError},
+        kernel_client::{MsgFromKernel, Origin},
+        notebook::Notebook,
+        value::{InspectResponse, KernelNamespace},
+    },
+};
+use actix::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web_actors::ws;
-use serde::{Deserialize, Serialize};
+use nanoid::nanoid;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tracing::{info, log::warn};
 
+// the server pings every session this often, sessions not answering within the timeout are dropped
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
 pub struct WsClient {
-    pub notebook: Notebook,
+    notebook_uuid: String,
+    notebooks: OpenNotebooks,
+    session_id: String,
+    sessions: Arc<Mutex<SessionRegistry>>,
+
+    /// name of the user, only they may resume the session
+    user: String,
+
+    /// read only sessions only get results and may inspect values
+    permission: Permission,
+
+    /// values a viewer of a published notebook chose for its inputs, cell uuid -> value
+    inputs: HashMap<String, Value>,
+
+    /// set once the client said hello with a supported protocol version
+    handshake_done: bool,
+
+    /// last time anything was received from the client
+    heartbeat: Instant,
+
+    /// sequence number of the last cell result before the connection was opened, later ones
+    /// are sent as they come in
+    connected_seq: u64,
 }
 
 impl Actor for WsClient {
     type Context = ws::WebsocketContext<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
-        info!("WS session started");
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("WS session {} started", self.session_id);
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.heartbeat) > CLIENT_TIMEOUT {
+                warn!("WS session {} timed out", act.session_id);
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+
+        match self.sessions.lock() {
+            Ok(mut sessions) => {
+                self.connected_seq = sessions.last_seq(&self.notebook_uuid);
+                sessions.register(&self.notebook_uuid, &self.session_id, ctx.address());
+            }
+            Err(_) => {
+                warn!("Could not lock sessions");
+                ctx.stop();
+            }
+        }
+        if self.permission == Permission::Edit {
+            if let Err(e) = self.with_notebook(|notebook| {
+                notebook.join(&self.session_id);
+                Ok(())
+            }) {
+                warn!("Could not join notebook {}: {}", self.notebook_uuid, e);
+            }
+        }
+    }
 
-        // ctx.run_interval(Duration::from_secs(1), |act, ctx| {
-        //     act.tx.send(KernelMsg::Ping).unwrap();
-        //     ctx.text(serde_json::to_string(&KernelMsg::Ping).unwrap());
-        // });
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        info!("WS session {} stopped", self.session_id);
+        if self.permission == Permission::View {
+            let namespace = self.namespace();
+            if let Err(e) = self.with_notebook(|notebook| notebook.drop_namespace(&namespace)) {
+                warn!("Could not drop namespace {}: {}", namespace, e);
+            }
+        }
+        if self.permission == Permission::Edit {
+            if let Err(e) = self.with_notebook(|notebook| {
+                notebook.leave(&self.session_id);
+                Ok(())
+            }) {
+                warn!("Could not leave notebook {}: {}", self.notebook_uuid, e);
+            }
+        }
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.unregister(&self.notebook_uuid, &self.session_id, &ctx.address());
+        }
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsClient {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.heartbeat = Instant::now();
         match msg {
             Ok(ws::Message::Text(text)) => {
-                self.handle_text(text.to_string(), ctx);
+                self.handle_text(&text, ctx);
             }
-
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(ws::Message::Binary(_)) => {
+                let msg = ServerEnvelope::error(
+                    None,
+                    ErrorCode::MalformedMessage,
+                    "binary messages are not supported",
+                );
+                Self::send(ctx, &msg);
+            }
+            Ok(_) => {}
             Err(e) => {
-                println!("Error: {:?}", e);
+                warn!("Websocket protocol error: {:?}", e);
+                ctx.stop();
             }
-
-            _ => warn!("Unhandled message {:?}", msg),
         }
     }
 }
@@ -42,88 +141,451 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsClient {
 impl Handler<MsgFromKernel> for WsClient {
     type Result = ();
 
-    fn handle(&mut self, msg: MsgFromKernel, ctx: &mut Self::Context) {
+    fn handle(&mut self, mut msg: MsgFromKernel, ctx: &mut Self::Context) {
+        // viewers only get the results of their own runs, which are not numbered
+        if self.permission == Permission::View && msg.seq.is_some() {
+            return;
+        }
+        if self.permission == Permission::View {
+            msg.redact();
+        }
         info!("Sending message to Client: {:#?}", msg);
-        let msg: WsMessage = msg.into();
-        ctx.text(serde_json::to_string(&msg).unwrap());
+        // request ids only mean something to the session which sent the request
+        if !self.is_origin(&msg.origin) {
+            msg.origin.request_id = None;
+        }
+        Self::send(ctx, &msg.into());
+    }
+}
+
+impl Handler<InspectResponse> for WsClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: InspectResponse, ctx: &mut Self::Context) {
+        let msg = ServerEnvelope::new(msg.origin.request_id.clone(), ServerMsg::Inspected(msg));
+        Self::send(ctx, &msg);
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct WsMessage {
-    cmd: WsCmds,
-    data: Option<String>,
-    locals: Option<HashMap<String, LocalValue>>,
+impl Handler<KernelNamespace> for WsClient {
+    type Result = ();
 
-    #[serde(rename = "cellUuid")]
-    cell_uuid: Option<String>,
+    fn handle(&mut self, msg: KernelNamespace, ctx: &mut Self::Context) {
+        let variables = match self.with_notebook(|notebook| Ok(notebook.variables(&msg))) {
+            Ok(variables) => ServerMsg::Variables { variables },
+            Err(e) => ServerMsg::from(&e),
+        };
+        Self::send(ctx, &ServerEnvelope::new(msg.origin.request_id, variables));
+    }
 }
 
-impl From<MsgFromKernel> for WsMessage {
-    fn from(msg: MsgFromKernel) -> Self {
-        if let Some(err) = msg.error {
-            Self {
-                cmd: WsCmds::Err,
-                data: Some(err),
-                locals: None,
-                cell_uuid: Some(msg.cell_uuid),
-            }
-        } else {
-            Self {
-                cmd: WsCmds::Res,
-                data: None,
-                cell_uuid: Some(msg.cell_uuid),
-                locals: Some(msg.locals),
-            }
+impl Handler<Broadcast> for WsClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: Broadcast, ctx: &mut Self::Context) {
+        if self.permission == Permission::View {
+            return;
         }
+        let request_id = match self.is_origin(&msg.origin) {
+            true => msg.origin.request_id,
+            false => None,
+        };
+        Self::send(ctx, &ServerEnvelope::new(request_id, msg.msg));
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum WsCmds {
-    Run,
-    Res,
-    Err,
-    Ping,
-    Pong,
+impl Handler<Peers> for WsClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: Peers, ctx: &mut Self::Context) {
+        let msg = ServerEnvelope::new(None, ServerMsg::Peers { count: msg.count });
+        Self::send(ctx, &msg);
+    }
 }
 
 impl WsClient {
-    pub fn new(notebook: &Notebook) -> Self {
+    pub fn new(
+        notebook_uuid: &str,
+        notebooks: OpenNotebooks,
+        sessions: Arc<Mutex<SessionRegistry>>,
+        user: &str,
+        permission: Permission,
+    ) -> Self {
         Self {
-            notebook: notebook.clone(),
+            notebook_uuid: notebook_uuid.to_string(),
+            notebooks,
+            session_id: nanoid!(30),
+            sessions,
+            user: user.to_string(),
+            permission,
+            inputs: HashMap::new(),
+            handshake_done: false,
+            heartbeat: Instant::now(),
+            connected_seq: 0,
         }
     }
 
-    pub fn handle_text(&mut self, text: String, ctx: &mut ws::WebsocketContext<Self>) {
-        let msg: WsMessage = match serde_json::from_str(&text) {
-            Ok(msg) => msg,
-            Err(e) => {
-                warn!("Could not parse message: {}", e);
+    fn origin(&self, request_id: &Option<String>) -> Origin {
+        Origin {
+            session_id: Some(self.session_id.clone()),
+            request_id: request_id.clone(),
+        }
+    }
+
+    fn is_origin(&self, origin: &Origin) -> bool {
+        origin.session_id.as_ref() == Some(&self.session_id)
+    }
+
+    /// every session works on the same notebook, the one in the state
+    fn with_notebook<T>(
+        &self,
+        f: impl FnOnce(&mut Notebook) -> Result<T, NotebookError>,
+    ) -> Result<T, NotebookError> {
+        let mut notebooks = self.notebooks.lock()?;
+        let notebook = notebooks
+            .get_mut(&self.notebook_uuid)
+            .ok_or(NotebookError::NotebookNotFound)?;
+
+        f(notebook)
+    }
+
+    /// called while the notebook is locked, so every session sees changes in the same order
+    fn broadcast(&self, origin: &Origin, msg: ServerMsg) {
+        match self.sessions.lock() {
+            Ok(sessions) => {
+                let msg = Broadcast {
+                    origin: origin.clone(),
+                    msg,
+                };
+                sessions.broadcast(&self.notebook_uuid, msg);
+            }
+            Err(_) => warn!("Could not lock sessions"),
+        }
+    }
+
+    fn send(ctx: &mut ws::WebsocketContext<Self>, msg: &ServerEnvelope) {
+        match serde_json::to_string(msg) {
+            Ok(text) => ctx.text(text),
+            Err(e) => warn!("Could not serialize message: {}", e),
+        }
+    }
+
+    pub fn handle_text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let (request_id, msg) = match parse_client_msg(text) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                warn!("Could not parse message: {:?}", err);
+                Self::send(ctx, &err.into());
                 return;
             }
         };
 
         info!("Received message: {:#?}", msg);
-        match msg.cmd {
-            WsCmds::Run => match self
-                .notebook
-                .eval_cell(&msg.cell_uuid.unwrap(), &msg.data.unwrap())
-            {
-                Ok(_) => info!("Evaluated cell"),
-                Err(e) => warn!("Could not evaluate cell: {}", e),
+        let reply = match msg {
+            ClientMsg::Hello {
+                version,
+                session_id,
+                last_seq,
+                resume_secret,
+            } if version == PROTOCOL_VERSION => {
+                let resume = session_id.zip(resume_secret);
+                self.hello(request_id, resume, last_seq, ctx);
+                return;
+            }
+            ClientMsg::Hello { version, .. } => ServerMsg::Error {
+                code: ErrorCode::UnsupportedVersion,
+                message: format!(
+                    "protocol version {} is not supported, expected {}",
+                    version, PROTOCOL_VERSION
+                ),
+            },
+            _ if !self.handshake_done => ServerMsg::Error {
+                code: ErrorCode::HandshakeRequired,
+                message: String::from("the first message has to be a Hello"),
             },
-            WsCmds::Ping => {
-                // let response = WsMessage {
-                //     cmd: WsCmds::Pong,
-                //     out: None,
-                //     cell_uuid: None,
-                //     locals: None,
-                // };
+            ClientMsg::Ping => ServerMsg::Pong,
+            msg if !self.allows(&msg) => ServerMsg::from(&NotebookError::Forbidden),
+            ClientMsg::SetInput { cell_uuid, value } if self.permission == Permission::View => {
+                Self::ack_or_error(self.set_input(cell_uuid, value, &request_id))
+            }
+            msg => {
+                let origin = self.origin(&request_id);
+                Self::ack_or_error(self.with_notebook(|notebook| {
+                    let stale = notebook.stale_cells();
+                    let disabled = notebook.disabled_cells();
+                    let res = self.run(notebook, msg, origin.clone());
+
+                    let cell_uuids = notebook.disabled_cells();
+                    if cell_uuids != disabled {
+                        self.broadcast(&origin, ServerMsg::Disabled { cell_uuids });
+                    }
+                    let cell_uuids = notebook.stale_cells();
+                    if cell_uuids != stale {
+                        self.broadcast(&origin, ServerMsg::Stale { cell_uuids });
+                    }
+                    res
+                }))
+            }
+        };
+
+        Self::send(ctx, &ServerEnvelope::new(request_id, reply));
+    }
+
+    /// finishes the handshake, resuming a previous session takes over its id, so results
+    /// of requests still running reach this connection, and replays the missed results.
+    /// `resume` is the id of the session and the secret it was welcomed with
+    fn hello(
+        &mut self,
+        request_id: Option<String>,
+        resume: Option<(String, String)>,
+        last_seq: Option<u64>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        if self.permission == Permission::View {
+            self.hello_viewer(request_id, ctx);
+            return;
+        }
+
+        let mut sessions = match self.sessions.lock() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                let msg = ServerMsg::from(&NotebookError::from(e));
+                Self::send(ctx, &ServerEnvelope::new(request_id, msg));
+                return;
+            }
+        };
+
+        // without the secret the client starts over, it may not see what it missed
+        let mut joined_as = None;
+        let resumed = match resume.filter(|(id, _)| id != &self.session_id) {
+            Some((session_id, secret)) => {
+                let resumed =
+                    sessions.resume(&self.notebook_uuid, &session_id, &self.user, &secret);
+                if resumed {
+                    sessions.unregister(&self.notebook_uuid, &self.session_id, &ctx.address());
+                    joined_as = Some(std::mem::replace(&mut self.session_id, session_id));
+                    sessions.register(&self.notebook_uuid, &self.session_id, ctx.address());
+                } else {
+                    warn!("Refused to resume session {}", session_id);
+                }
+                resumed
+            }
+            None => true,
+        };
+        self.handshake_done = true;
+
+        let missed = match (resumed, last_seq) {
+            (false, _) => None,
+            (true, Some(last_seq)) => sessions.replay(&self.notebook_uuid, last_seq),
+            (true, None) => Some(Vec::new()),
+        };
+        let seq = sessions.last_seq(&self.notebook_uuid);
+        let secret = sessions.resume_secret(&self.notebook_uuid, &self.session_id, &self.user);
+        drop(sessions);
+
+        // edits of the session are acknowledged under the resumed id from now on
+        if let (Some(joined_as), Permission::Edit) = (joined_as, self.permission) {
+            if let Err(e) = self.with_notebook(|notebook| {
+                notebook.rejoin(&joined_as, &self.session_id);
+                Ok(())
+            }) {
+                warn!("Could not rejoin notebook {}: {}", self.notebook_uuid, e);
+            }
+        }
+
+        let welcome = ServerMsg::Welcome {
+            version: PROTOCOL_VERSION,
+            session_id: self.session_id.clone(),
+            permission: self.permission,
+            seq,
+            resumed: missed.is_some(),
+            resume_secret: Some(secret),
+        };
+        Self::send(ctx, &ServerEnvelope::new(request_id, welcome));
+
+        // results broadcast since this connection was opened are sent anyway
+        for mut msg in missed.unwrap_or_default() {
+            if msg.seq.unwrap_or_default() > self.connected_seq {
+                break;
+            }
+            if !self.is_origin(&msg.origin) {
+                msg.origin.request_id = None;
+            }
+            Self::send(ctx, &msg.into());
+        }
+    }
+
+    /// viewers never resume, their results are not kept for replays. They start by running the
+    /// whole notebook in their namespace
+    fn hello_viewer(&mut self, request_id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) {
+        self.handshake_done = true;
+        let welcome = ServerMsg::Welcome {
+            version: PROTOCOL_VERSION,
+            session_id: self.session_id.clone(),
+            permission: self.permission,
+            seq: 0,
+            resumed: false,
+            resume_secret: None,
+        };
+        Self::send(ctx, &ServerEnvelope::new(request_id.clone(), welcome));
+
+        let namespace = self.namespace();
+        let origin = self.origin(&request_id);
+        let res = self.with_notebook(|notebook| {
+            notebook.run_published(&namespace, &self.inputs, None, origin)
+        });
+        if let Err(e) = res {
+            Self::send(ctx, &ServerEnvelope::new(request_id, ServerMsg::from(&e)));
+        }
+    }
+
+    /// viewers of a published notebook may only change inputs, readers may not change anything
+    fn allows(&self, msg: &ClientMsg) -> bool {
+        match self.permission {
+            Permission::Edit => true,
+            Permission::Read => !msg.edits(),
+            Permission::View => matches!(msg, ClientMsg::SetInput { .. }),
+        }
+    }
+
+    /// private namespace of a viewer in the kernel
+    fn namespace(&self) -> String {
+        format!("{}/{}", self.notebook_uuid, self.session_id)
+    }
+
+    /// runs the input of a viewer and every cell depending on it in the viewers namespace
+    fn set_input(
+        &mut self,
+        cell_uuid: String,
+        value: Value,
+        request_id: &Option<String>,
+    ) -> Result<(), NotebookError> {
+        self.with_notebook(|notebook| notebook.input(&cell_uuid)?.assignment(&value))?;
+        self.inputs.insert(cell_uuid.clone(), value);
+
+        let namespace = self.namespace();
+        let origin = self.origin(request_id);
+        self.with_notebook(|notebook| {
+            notebook.run_published(&namespace, &self.inputs, Some(&cell_uuid), origin)
+        })
+    }
 
-                // ctx.text(serde_json::to_string(&response).unwrap());
+    fn run(
+        &self,
+        notebook: &mut Notebook,
+        msg: ClientMsg,
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        match msg {
+            ClientMsg::Run { cell_uuid, content } => {
+                if let Some(content) = content {
+                    if let Some((revision, operation)) =
+                        notebook.replace_content(&cell_uuid, &content)?
+                    {
+                        let edited = ServerMsg::Edited {
+                            cell_uuid: cell_uuid.clone(),
+                            revision,
+                            operation,
+                        };
+                        self.broadcast(&origin, edited);
+                    }
+                }
+                notebook.run_cell(&cell_uuid, origin)
+            }
+            ClientMsg::RunAll => notebook.run_all(origin),
+            ClientMsg::RunStale => notebook.run_stale(origin),
+            ClientMsg::RunCells { cell_uuids } => notebook.run_cells(&cell_uuids, origin),
+            ClientMsg::Edit {
+                cell_uuid,
+                revision,
+                operation,
+            } => {
+                notebook.acknowledge(&self.session_id, &cell_uuid, revision);
+                let (revision, operation) = notebook.edit_cell(&cell_uuid, revision, operation)?;
+                let edited = ServerMsg::Edited {
+                    cell_uuid,
+                    revision,
+                    operation,
+                };
+                self.broadcast(&origin, edited);
+                Ok(())
+            }
+            ClientMsg::InsertCell { index, content } => {
+                let (index, cell) = notebook.insert_cell(index, &content)?;
+                self.broadcast(
+                    &origin,
+                    ServerMsg::CellInserted {
+                        index,
+                        cell: Box::new(cell),
+                    },
+                );
+                Ok(())
+            }
+            ClientMsg::DeleteCell { cell_uuid } => {
+                notebook.delete_cell(&cell_uuid)?;
+                self.broadcast(&origin, ServerMsg::CellDeleted { cell_uuid });
+                Ok(())
+            }
+            ClientMsg::Reorder { new_order } => {
+                notebook.reorder_cells(&new_order)?;
+                self.broadcast(&origin, ServerMsg::Reordered { new_order });
+                Ok(())
+            }
+            ClientMsg::SetDisabled {
+                cell_uuid,
+                disabled,
+            } => {
+                notebook.set_disabled(&cell_uuid, disabled)?;
+                let changed = ServerMsg::CellDisabled {
+                    cell_uuid,
+                    disabled,
+                };
+                self.broadcast(&origin, changed);
+                Ok(())
+            }
+            ClientMsg::MarkParameters {
+                cell_uuid,
+                parameters,
+            } => {
+                notebook.mark_parameters(&cell_uuid, parameters)?;
+                let changed = ServerMsg::ParametersMarked {
+                    cell_uuid,
+                    parameters,
+                };
+                self.broadcast(&origin, changed);
+                Ok(())
+            }
+            ClientMsg::SetExecutionMode { cell_uuid, mode } => {
+                notebook.set_execution_mode(cell_uuid.as_deref(), mode)?;
+                let changed = ServerMsg::ExecutionModeChanged { cell_uuid, mode };
+                self.broadcast(&origin, changed);
+                Ok(())
+            }
+            ClientMsg::SetInput { cell_uuid, value } => {
+                let content = notebook.input(&cell_uuid)?.assignment(&value)?;
+                let run = ClientMsg::Run {
+                    cell_uuid,
+                    content: Some(content),
+                };
+                self.run(notebook, run, origin)
+            }
+            ClientMsg::Inspect(mut inspect) => {
+                inspect.notebook_uuid = notebook.uuid.clone();
+                inspect.origin = origin;
+                notebook.inspect(inspect)
+            }
+            ClientMsg::Variables => notebook.request_namespace(origin, None),
+            ClientMsg::Hello { .. } | ClientMsg::Ping => Ok(()),
+        }
+    }
+
+    fn ack_or_error(res: Result<(), NotebookError>) -> ServerMsg {
+        match res {
+            Ok(_) => ServerMsg::Ack,
+            Err(e) => {
+                warn!("Command failed: {}", e);
+                ServerMsg::from(&e)
             }
-            _ => {}
         }
     }
 }
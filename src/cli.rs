@@ -0,0 +1,462 @@
+use crate::{
+    api::{auth::Auth, state::State},
+    config::Config,
+    core::{
+        check::Severity,
+        display::DisplayData,
+        errors::{KernelErrors, NotebookError},
+        format::Format,
+        kernel_client::{CellStatus, KernelClientMsg, MsgFromKernel, Origin},
+        notebook::Notebook,
+    },
+};
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::ExitCode,
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Reactive python notebooks
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    serve: ServeArgs,
+
+    /// Configuration file, notebook.toml if there is one
+    #[arg(long, global = true, env = "NOTEBOOK_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+impl Cli {
+    /// without a command the server is started
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Serve(self.serve))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the server
+    Serve(ServeArgs),
+    /// Run every cell of a notebook without a browser
+    Run(RunArgs),
+    /// Convert a notebook to another format
+    Export(ExportArgs),
+    /// Report cells which do not parse, depend on themselves or use undefined names
+    Check(CheckArgs),
+}
+
+/// override the configuration file, see `Config`
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on [default: 127.0.0.1]
+    #[arg(long, env = "API_HOST")]
+    pub host: Option<String>,
+
+    /// Port to listen on [default: 8080]
+    #[arg(long, env = "API_PORT")]
+    pub port: Option<u16>,
+
+    /// Where the notebook is opened, allowed to call the server as well
+    #[arg(long, env = "CLIENT_URL")]
+    pub client_url: Option<String>,
+
+    /// Origins of more web clients allowed to call the server, * allows every origin
+    #[arg(
+        long = "allowed-origin",
+        env = "ALLOWED_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub allowed_origins: Vec<String>,
+
+    /// Serve the built client (npm run build) from this directory [default: client/dist if built]
+    #[arg(long, env = "CLIENT_DIR")]
+    pub client_dir: Option<PathBuf>,
+
+    /// Port the kernel publishes its results on [default: 8081]
+    #[arg(long, env = "ZMQ_PORT_SUB")]
+    pub kernel_pub_port: Option<u16>,
+
+    /// Port the kernel answers requests on [default: 8082]
+    #[arg(long, env = "ZMQ_PORT_REQ")]
+    pub kernel_req_port: Option<u16>,
+
+    /// Open the notebooks (.json, .py, .ipynb) in this directory
+    #[arg(long, env = "NOTEBOOK_DIR")]
+    pub notebook_dir: Option<PathBuf>,
+
+    /// Token to use instead of a generated one
+    #[arg(long, env = "NOTEBOOK_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Json file with more users and their permissions
+    #[arg(long, env = "NOTEBOOK_USERS")]
+    pub users: Option<PathBuf>,
+
+    /// Open these notebooks and let everyone without a token view them, without any none of
+    /// the configuration file are published
+    #[arg(long, env = "NOTEBOOK_PUBLISH", value_delimiter = ',', num_args = 0..)]
+    pub publish: Option<Vec<PathBuf>>,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Notebook to run (.json, .py, .ipynb)
+    pub path: PathBuf,
+
+    /// Write the notebook with the outputs of the run to this file (.json, .ipynb)
+    #[arg(short, long, conflicts_with = "in_place")]
+    pub output: Option<PathBuf>,
+
+    /// Write the outputs of the run back into the notebook, not with overridden parameters as
+    /// the notebook would keep them
+    #[arg(long, conflicts_with_all = ["parameter", "parameters"])]
+    pub in_place: bool,
+
+    /// Write the status, outputs, errors and timing of every cell to this json file
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+
+    /// Fail if the run takes longer than this many seconds
+    #[arg(long)]
+    pub timeout: Option<u64>,
+
+    /// Override a parameter, the value is json or else a string
+    #[arg(short, long, value_name = "NAME=VALUE", value_parser = parse_parameter)]
+    pub parameter: Vec<(String, String)>,
+
+    /// Json file with an object of parameters to override, before those of --parameter
+    #[arg(long)]
+    pub parameters: Option<PathBuf>,
+}
+
+fn parse_parameter(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("`{}` is not like NAME=VALUE", arg))
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Notebook to convert (.json, .py, .ipynb)
+    pub input: PathBuf,
+
+    /// File to write, the format is taken from its extension
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Format to convert to (json, py, ipynb), printed if there is no output file
+    #[arg(long, required_unless_present = "output")]
+    pub to: Option<Format>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Notebook to check (.json, .py, .ipynb)
+    pub path: PathBuf,
+}
+
+/// runs every cell in topological order with a kernel of its own and prints the outputs. The
+/// results are written back into the notebook or a report, it fails if a cell failed
+pub fn run(args: &RunArgs, config: &Config) -> Result<ExitCode, NotebookError> {
+    let mut notebook = Notebook::load(&args.path)?;
+    notebook.clear_runs();
+    notebook.set_parameters(&parameter_values(&notebook, args)?)?;
+    let numbers = cell_numbers(&notebook);
+    let state = State::new(Auth::load(None, None)?, config)?;
+    let notebook_uuid = state.open(notebook)?;
+    let results = state.sessions.lock()?.listen(&notebook_uuid);
+
+    let started = SystemTime::now();
+    let res = wait_for_kernel(&state)
+        .and_then(|_| {
+            state
+                .open_notebooks
+                .lock()?
+                .get_mut(&notebook_uuid)
+                .ok_or(NotebookError::NotebookNotFound)?
+                .run_all(Origin::default())
+        })
+        .and_then(|_| {
+            let timeout = args.timeout.map(Duration::from_secs);
+            receive(&state, &notebook_uuid, &results, &numbers, timeout)
+        });
+    state.shutdown()?;
+    res?;
+
+    let notebook = state
+        .open_notebooks
+        .lock()?
+        .remove(&notebook_uuid)
+        .ok_or(NotebookError::NotebookNotFound)?;
+    let duration = started.elapsed().unwrap_or_default();
+    let report = notebook.report(millis(started), duration.as_millis() as u64);
+    if let Some(output) = &args.output {
+        notebook.save(output)?;
+    }
+    if args.in_place {
+        notebook.save(&args.path)?;
+    }
+    if let Some(path) = &args.report {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)
+            .map_err(|e| NotebookError::persistence(path, e))?;
+    }
+
+    eprintln!(
+        "{} cells ran in {:.2}s, {} failed",
+        report.cells.len(),
+        duration.as_secs_f64(),
+        report.failed
+    );
+    match report.failed {
+        0 => Ok(ExitCode::SUCCESS),
+        _ => Ok(ExitCode::FAILURE),
+    }
+}
+
+/// the values of the parameter file and the parameter arguments. Arguments of string
+/// parameters are taken as they are, so quotes are not needed
+fn parameter_values(
+    notebook: &Notebook,
+    args: &RunArgs,
+) -> Result<HashMap<String, Value>, NotebookError> {
+    let mut values = match &args.parameters {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| NotebookError::InvalidInput(format!("{}: {}", path.display(), e)))?;
+            serde_json::from_str(&json)?
+        }
+        None => HashMap::new(),
+    };
+
+    let parameters = notebook.parameters();
+    for (name, value) in args.parameter.iter() {
+        let is_string = parameters
+            .iter()
+            .any(|parameter| &parameter.name == name && parameter.value.is_string());
+        let value = match serde_json::from_str(value) {
+            Ok(value) if !is_string => value,
+            _ => Value::String(value.clone()),
+        };
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+/// prints and records the results of the notebook until the run ended
+fn receive(
+    state: &State,
+    notebook_uuid: &str,
+    results: &mpsc::Receiver<MsgFromKernel>,
+    numbers: &HashMap<String, usize>,
+    timeout: Option<Duration>,
+) -> Result<(), NotebookError> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let msg = match deadline {
+            Some(deadline) => results
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|_| KernelErrors::Timeout)?,
+            None => results
+                .recv()
+                .map_err(|_| KernelErrors::Unavailable(String::from("Kernel client stopped")))?,
+        };
+        if msg.ended {
+            return Ok(());
+        }
+
+        print_result(numbers, &msg);
+        if let Some(notebook) = state.open_notebooks.lock()?.get_mut(notebook_uuid) {
+            notebook.record(&msg);
+        }
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// the kernel is up and its results reach the kernel client
+fn wait_for_kernel(state: &State) -> Result<(), NotebookError> {
+    let (tx, rx) = mpsc::channel();
+    state
+        .kernel_sender
+        .lock()?
+        .send(KernelClientMsg::Ping(tx))?;
+    rx.recv_timeout(state.timeouts.kernel_startup())
+        .map_err(|_| KernelErrors::Timeout)?;
+
+    Ok(())
+}
+
+fn print_result(numbers: &HashMap<String, usize>, msg: &MsgFromKernel) {
+    let number = |cell_uuid: &str| numbers.get(cell_uuid).copied().unwrap_or_default();
+    match (msg.status, &msg.blocked_by) {
+        (Some(CellStatus::Blocked), Some(blocked_by)) => eprintln!(
+            "[{}] not run, it depends on [{}] which failed",
+            number(&msg.cell_uuid),
+            number(blocked_by)
+        ),
+        (Some(_), _) => {}
+        (None, _) => {
+            for output in msg.outputs.iter() {
+                print_output(output);
+            }
+            if let Some(error) = &msg.error {
+                eprintln!("[{}] failed: {}", number(&msg.cell_uuid), error);
+            }
+        }
+    }
+}
+
+fn print_output(output: &DisplayData) {
+    match output.data.get("text/plain").and_then(|text| text.as_str()) {
+        // streams bring their own line breaks
+        Some(text) if output.metadata.contains_key("stream") => print!("{}", text),
+        Some(text) => println!("{}", text),
+        None => {
+            let mime_types = output.data.keys().cloned().collect::<Vec<_>>();
+            println!("<{}>", mime_types.join(", "));
+        }
+    }
+}
+
+/// converts the notebook to the format of `--to` or of the output file
+pub fn export(args: &ExportArgs) -> Result<ExitCode, NotebookError> {
+    let notebook = Notebook::load(&args.input)?;
+    match (&args.output, args.to) {
+        (Some(output), None) => notebook.save(output)?,
+        (Some(output), Some(format)) => std::fs::write(output, notebook.render(format)?)
+            .map_err(|e| NotebookError::persistence(output, e))?,
+        (None, Some(format)) => println!("{}", notebook.render(format)?),
+        (None, None) => unreachable!("clap requires one of them"),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// prints the problems of the notebook, fails if one of them is an error
+pub fn check(args: &CheckArgs) -> Result<ExitCode, NotebookError> {
+    let notebook = Notebook::load(&args.path)?;
+    let numbers = cell_numbers(&notebook);
+    let problems = notebook.check();
+    for problem in problems.iter() {
+        let number = numbers.get(&problem.cell_uuid).copied().unwrap_or_default();
+        println!("[{}] {}", number, problem);
+    }
+
+    if problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error)
+    {
+        return Ok(ExitCode::FAILURE);
+    }
+    println!("{} cells, no errors", numbers.len());
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// cells are referred to by their position, starting at 1
+fn cell_numbers(notebook: &Notebook) -> HashMap<String, usize> {
+    notebook
+        .cells()
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| (cell.uuid.clone(), index + 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::{CellMetadata, CellType};
+
+    #[test]
+    fn test_commands() {
+        let cli = Cli::try_parse_from(["notebook", "--port", "9000"]).unwrap();
+        match cli.command() {
+            Command::Serve(args) => {
+                assert_eq!(args.port, Some(9000));
+                assert_eq!(args.host, None);
+                assert_eq!(args.publish, None);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let cli = Cli::try_parse_from(["notebook", "export", "a.json", "--to", "ipynb"]).unwrap();
+        match cli.command() {
+            Command::Export(args) => assert_eq!(args.to, Some(Format::Jupyter)),
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert!(Cli::try_parse_from(["notebook", "export", "a.json"]).is_err());
+        assert!(Cli::try_parse_from(["notebook", "export", "a.json", "--to", "txt"]).is_err());
+        assert!(Cli::try_parse_from(["notebook", "check"]).is_err());
+
+        let cli = Cli::try_parse_from(["notebook", "run", "a.py", "--report", "r.json"]).unwrap();
+        match cli.command() {
+            Command::Run(args) => assert_eq!(args.report, Some(PathBuf::from("r.json"))),
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert!(
+            Cli::try_parse_from(["notebook", "run", "a.py", "-o", "b.ipynb", "--in-place"])
+                .is_err()
+        );
+        assert!(Cli::try_parse_from(["notebook", "run", "a.py", "-p", "samples"]).is_err());
+        assert!(
+            Cli::try_parse_from(["notebook", "run", "a.py", "--in-place", "-p", "samples=1"])
+                .is_err()
+        );
+        assert!(Cli::try_parse_from([
+            "notebook",
+            "run",
+            "a.py",
+            "--in-place",
+            "--parameters",
+            "p.json"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parameter_values() {
+        let cells = vec![(
+            CellType::ReactiveCode,
+            String::from("samples = 10\nlabel = 'a'"),
+            CellMetadata {
+                parameters: true,
+                ..Default::default()
+            },
+        )];
+        let notebook = Notebook::from_cells("Parameters", cells).unwrap();
+
+        let cli = Cli::try_parse_from([
+            "notebook",
+            "run",
+            "a.py",
+            "-p",
+            "samples=20",
+            "-p",
+            "label=42",
+        ])
+        .unwrap();
+        let args = match cli.command() {
+            Command::Run(args) => args,
+            command => panic!("unexpected command {:?}", command),
+        };
+        let values = parameter_values(&notebook, &args).unwrap();
+        assert_eq!(values["samples"], Value::from(20));
+        assert_eq!(values["label"], Value::from("42"));
+    }
+}
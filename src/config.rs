@@ -0,0 +1,356 @@
+use crate::{
+    cli::ServeArgs,
+    core::{errors::NotebookError, format::Format, kernel_client::KernelConfig},
+};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// read from the working directory if no other file is given
+pub const DEFAULT_PATH: &str = "notebook.toml";
+
+/// where `npm run build` puts the client, served if it was built
+pub const DEFAULT_CLIENT_DIR: &str = "client/dist";
+
+/// settings of the server, from a toml file. Environment variables and command line
+/// options override them, see `ServeArgs`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub kernel: KernelConfig,
+    pub timeouts: Timeouts,
+    pub auth: AuthConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+
+    /// origins of web clients which may call the server, `*` allows every origin
+    pub allowed_origins: Vec<String>,
+
+    /// where users open the notebook, defaults to the address of the server
+    pub client_url: Option<String>,
+
+    /// the built client is served from here, so the notebook opens at the address of the server
+    pub client_dir: Option<PathBuf>,
+
+    /// the notebooks (.json, .py, .ipynb) in this directory are opened on startup
+    pub notebook_dir: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from("127.0.0.1"),
+            port: 8080,
+            allowed_origins: Vec::new(),
+            client_url: None,
+            client_dir: None,
+            notebook_dir: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// the configured client or else the built one of the repository, if there is one
+    pub fn client_dir(&self) -> Option<PathBuf> {
+        match &self.client_dir {
+            Some(dir) => Some(dir.clone()),
+            None if Path::new(DEFAULT_CLIENT_DIR).join("index.html").is_file() => {
+                Some(PathBuf::from(DEFAULT_CLIENT_DIR))
+            }
+            None => None,
+        }
+    }
+}
+
+/// in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// until the kernel answers after it was started, for headless runs
+    pub kernel_startup: u64,
+
+    /// until the kernel answers a request, e.g. for the variables
+    pub kernel_request: u64,
+
+    /// until a call of a notebook finished running
+    pub call: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            kernel_startup: 30,
+            kernel_request: 30,
+            call: 300,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn kernel_startup(&self) -> Duration {
+        Duration::from_secs(self.kernel_startup)
+    }
+
+    pub fn kernel_request(&self) -> Duration {
+        Duration::from_secs(self.kernel_request)
+    }
+
+    pub fn call(&self) -> Duration {
+        Duration::from_secs(self.call)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// used instead of a generated token
+    pub token: Option<String>,
+
+    /// json file with more users and their permissions
+    pub users: Option<PathBuf>,
+
+    /// notebooks opened on startup, which everyone without a token may view
+    pub publish: Vec<PathBuf>,
+}
+
+impl Config {
+    /// reads the file, without one `notebook.toml` if there is one or else the defaults
+    pub fn load(path: Option<&Path>) -> Result<Self, NotebookError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).is_file() => Path::new(DEFAULT_PATH),
+            None => return Ok(Self::default()),
+        };
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| NotebookError::Config(format!("could not read {:?}: {}", path, e)))?;
+
+        content.parse()
+    }
+
+    /// environment variables and command line options take precedence over the file
+    pub fn apply(&mut self, args: ServeArgs) {
+        let server = &mut self.server;
+        server.host = args.host.unwrap_or(server.host.clone());
+        server.port = args.port.unwrap_or(server.port);
+        server.client_url = args.client_url.or(server.client_url.take());
+        server.client_dir = args.client_dir.or(server.client_dir.take());
+        server.notebook_dir = args.notebook_dir.or(server.notebook_dir.take());
+        if !args.allowed_origins.is_empty() {
+            server.allowed_origins = args.allowed_origins;
+        }
+
+        self.kernel.pub_port = args.kernel_pub_port.unwrap_or(self.kernel.pub_port);
+        self.kernel.req_port = args.kernel_req_port.unwrap_or(self.kernel.req_port);
+
+        self.auth.token = args.token.or(self.auth.token.take());
+        self.auth.users = args.users.or(self.auth.users.take());
+        if let Some(publish) = args.publish {
+            self.auth.publish = publish;
+        }
+    }
+
+    /// every problem of the configuration at once, so they can be fixed in one go
+    pub fn validate(&self) -> Result<(), NotebookError> {
+        let mut problems = Vec::new();
+
+        if self.server.host.trim().is_empty() {
+            problems.push(String::from("server.host is empty"));
+        }
+        for origin in self.server.allowed_origins.iter() {
+            if origin != "*" && !is_origin(origin) {
+                problems.push(format!(
+                    "server.allowed_origins: {} is no origin like https://example.com",
+                    origin
+                ));
+            }
+        }
+        if let Some(client_url) = &self.server.client_url {
+            if !client_url.starts_with("http://") && !client_url.starts_with("https://") {
+                problems.push(format!("server.client_url: {} is no http url", client_url));
+            }
+        }
+        if let Some(dir) = &self.server.client_dir {
+            if !dir.join("index.html").is_file() {
+                problems.push(format!(
+                    "server.client_dir: {:?} has no index.html, build the client with npm run build",
+                    dir
+                ));
+            }
+        }
+        if let Some(dir) = &self.server.notebook_dir {
+            if !dir.is_dir() {
+                problems.push(format!("server.notebook_dir: {:?} is no directory", dir));
+            }
+        }
+
+        let kernel = &self.kernel;
+        if kernel.command.is_empty() {
+            problems.push(String::from("kernel.command is empty"));
+        }
+        if !kernel.dir.is_dir() {
+            problems.push(format!("kernel.dir: {:?} is no directory", kernel.dir));
+        }
+        let ports = [self.server.port, kernel.pub_port, kernel.req_port];
+        if (0..ports.len()).any(|i| ports[i + 1..].contains(&ports[i])) {
+            problems.push(format!(
+                "server.port, kernel.pub_port and kernel.req_port have to differ, they are {:?}",
+                ports
+            ));
+        }
+
+        let timeouts = [
+            ("kernel_startup", self.timeouts.kernel_startup),
+            ("kernel_request", self.timeouts.kernel_request),
+            ("call", self.timeouts.call),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout == 0 {
+                problems.push(format!("timeouts.{} has to be at least 1 second", name));
+            }
+        }
+
+        if let Some(users) = &self.auth.users {
+            if !users.is_file() {
+                problems.push(format!("auth.users: {:?} does not exist", users));
+            }
+        }
+        for notebook in self.auth.publish.iter() {
+            if !notebook.is_file() || Format::of(notebook).is_err() {
+                problems.push(format!(
+                    "auth.publish: {:?} is no notebook (.json, .py, .ipynb)",
+                    notebook
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(NotebookError::Config(problems.join("\n")))
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = NotebookError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| NotebookError::Config(e.to_string()))
+    }
+}
+
+/// scheme and host, with an optional port, but no path
+fn is_origin(origin: &str) -> bool {
+    let host = match origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    {
+        Some(host) => host,
+        None => return false,
+    };
+
+    !host.is_empty() && !host.contains('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = r#"
+            [server]
+            host = "0.0.0.0"
+            allowed_origins = ["https://notebooks.example.com", "http://localhost:5173"]
+
+            [kernel]
+            command = ["python3.11", "main.py"]
+            req_port = 9082
+
+            [timeouts]
+            call = 60
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.server.allowed_origins.len(), 2);
+        assert_eq!(config.kernel.pub_port, 8081);
+        assert_eq!(config.kernel.req_port, 9082);
+        assert_eq!(config.timeouts.call(), Duration::from_secs(60));
+        assert_eq!(config.timeouts.kernel_request, 30);
+
+        assert!("[server]\nhots = \"0.0.0.0\"".parse::<Config>().is_err());
+        assert!("[server]\nport = \"80\"".parse::<Config>().is_err());
+        assert_eq!("".parse::<Config>().unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config: Config =
+            "[server]\nhost = \"0.0.0.0\"\nport = 8000\n[auth]\npublish = [\"a.py\"]"
+                .parse()
+                .unwrap();
+        let cli = Cli::try_parse_from([
+            "notebook",
+            "--port",
+            "9000",
+            "--allowed-origin",
+            "https://a.example.com,https://b.example.com",
+        ])
+        .unwrap();
+        match cli.command() {
+            Command::Serve(args) => config.apply(args),
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.allowed_origins.len(), 2);
+        assert_eq!(config.auth.publish, vec![PathBuf::from("a.py")]);
+
+        // publishing is turned off with an empty list
+        let cli = Cli::try_parse_from(["notebook", "--publish"]).unwrap();
+        match cli.command() {
+            Command::Serve(args) => config.apply(args),
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert!(config.auth.publish.is_empty());
+
+        let cli = Cli::try_parse_from(["notebook", "run", "a.py", "--config", "ci.toml"]).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("ci.toml")));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        config.kernel.dir = PathBuf::from(".");
+        assert!(config.validate().is_ok());
+
+        config.server.allowed_origins = vec![String::from("*"), String::from("localhost:5173")];
+        config.kernel.req_port = 8080;
+        config.server.client_dir = Some(PathBuf::from("."));
+        config.timeouts.call = 0;
+        config.auth.users = Some(PathBuf::from("missing.json"));
+        config.auth.publish = vec![PathBuf::from("Cargo.toml")];
+        let problems = match config.validate() {
+            Err(NotebookError::Config(problems)) => problems,
+            res => panic!("unexpected {:?}", res),
+        };
+        assert_eq!(problems.lines().count(), 6);
+        assert!(problems.contains("index.html"));
+        assert!(problems.contains("localhost:5173"));
+        assert!(!problems.contains("\"*\""));
+
+        assert!(is_origin("https://example.com:8443"));
+        assert!(!is_origin("https://example.com/"));
+    }
+}
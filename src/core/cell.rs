@@ -1,4 +1,7 @@
-use super::{kernel_client::ExecutionType, notebook::Scope, statement::Statement};
+use super::{
+    input::Input, kernel_client::ExecutionType, notebook::Scope, report::CellRun,
+    statement::Statement, value::ValueSummary,
+};
 use nanoid::nanoid;
 use rustpython_parser::{
     ast::{AliasData, ExprContext, ExprKind, Located, StmtKind},
@@ -6,8 +9,7 @@ use rustpython_parser::{
     parser,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use tracing::warn;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,12 +19,18 @@ pub enum CellType {
     Markdown,
 }
 
+/// a local binding, the value itself stays in the kernel and is referenced by its handle
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LocalValue {
-    pub value: Value,
+    pub handle: String,
+    pub summary: ValueSummary,
     pub local_type: ExecutionType,
 }
 
+fn stale_default() -> bool {
+    true
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Cell {
     pub metadata: CellMetadata,
@@ -32,14 +40,36 @@ pub struct Cell {
     pub statements: Vec<Statement>,
     pub bindings: HashSet<String>,
 
+    /// number of edits applied to the content, clients send edits against a revision
+    #[serde(default)]
+    pub revision: usize,
+
+    /// the cell has not run with its current content or inputs, cells loaded from disk never ran
+    #[serde(skip_deserializing, default = "stale_default")]
+    pub stale: bool,
+
+    /// counts how often the cell became stale, a run only clears `stale` if the cell did not
+    /// become stale again after the run was sent
     #[serde(skip)]
-    pub locals: HashMap<String, LocalValue>,
+    pub stale_mark: u64,
 
     #[serde(skip)]
     pub required: HashSet<String>,
 
+    /// content was edited since the bindings were last set up
+    #[serde(skip)]
+    pub edited: bool,
+
     #[serde(skip)]
     ignore_bindings: HashSet<String>,
+
+    /// set if the cell only assigns a literal, see `Input`
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub input: Option<Input>,
+
+    /// outputs, errors and timing of the last headless run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<CellRun>,
 }
 
 impl Cell {
@@ -49,15 +79,20 @@ impl Cell {
         scope: &mut Scope,
     ) -> Result<Self, ParseError> {
         let mut cell = Self {
-            metadata: CellMetadata { collapsed: false },
+            metadata: CellMetadata::default(),
             uuid: nanoid!(30),
             cell_type,
             content,
-            locals: HashMap::new(),
             bindings: HashSet::new(),
+            revision: 0,
+            stale: true,
+            stale_mark: 0,
+            edited: false,
             ignore_bindings: HashSet::new(),
             required: HashSet::new(),
             statements: Vec::new(),
+            input: None,
+            last_run: None,
         };
 
         cell.setup_local_vars(scope)?;
@@ -69,11 +104,21 @@ impl Cell {
         Self::new(CellType::ReactiveCode, String::from(content), scope)
     }
 
+    /// reactive cells run whenever a cell they depend on runs, the others are only marked stale
+    pub fn is_reactive(&self) -> bool {
+        matches!(self.cell_type, CellType::ReactiveCode)
+    }
+
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+        self.stale_mark += 1;
+    }
+
     fn unbind_all(&mut self) {
+        self.input = None;
         self.bindings.clear();
         self.ignore_bindings.clear();
         self.required.clear();
-        self.locals.clear();
         self.statements.clear();
     }
 
@@ -89,10 +134,14 @@ impl Cell {
                 self.unbind_all();
                 // update content
                 self.content = content.to_string();
+                self.edited = false;
                 // rebind all new local bindings
                 self.setup_local_vars(scope)
             }
-            CellType::Markdown => Ok(warn!("TODO check Markdown cell")),
+            CellType::Markdown => {
+                warn!("TODO check Markdown cell");
+                Ok(())
+            }
         }
     }
 
@@ -102,12 +151,16 @@ impl Cell {
                 let ast = parser::parse_program(&self.content, "<input>")?;
 
                 for statement in ast.iter() {
-                    self.handle_stmt_node(&statement, scope, true);
+                    self.handle_stmt_node(statement, scope, true);
                 }
+                self.input = Input::of(&ast);
 
                 Ok(())
             }
-            CellType::Markdown => Ok(warn!("TODO check Markdown cell")),
+            CellType::Markdown => {
+                warn!("TODO check Markdown cell");
+                Ok(())
+            }
         }
     }
 
@@ -151,7 +204,7 @@ impl Cell {
         // println!("statement: {:#?}", statement);
         match &stmt_kind.node {
             StmtKind::Import { names } | StmtKind::ImportFrom { names, .. } => {
-                self.import_dependencies(&names, scope)
+                self.import_dependencies(names, scope)
             }
 
             StmtKind::Assign { targets, value, .. } => {
@@ -177,10 +230,10 @@ impl Cell {
             StmtKind::If { test, body, orelse } => {
                 self.handle_expr_node(&test.node, scope);
                 for statement in body {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
                 for statement in orelse {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
             }
 
@@ -190,7 +243,7 @@ impl Cell {
                     // self.handle_expr_node(&case.pattern.node, scope);
                     // self.handle_expr_node(&case.guard.node, scope);
                     for statement in &case.body {
-                        self.handle_stmt_node(&statement, scope, false);
+                        self.handle_stmt_node(statement, scope, false);
                     }
                 }
             }
@@ -209,7 +262,7 @@ impl Cell {
 
                 scope.insert(name.to_string(), self.uuid.clone());
                 for statement in body {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
             }
 
@@ -227,13 +280,12 @@ impl Cell {
             }
 
             StmtKind::While { test, body, orelse } => {
-                println!("statement: {:#?}", stmt_kind);
                 self.handle_expr_node(&test.node, scope);
                 for statement in body {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
                 for statement in orelse {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
             }
 
@@ -241,10 +293,10 @@ impl Cell {
                 // self.handle_expr_node(&target.node, scope);
                 // self.handle_expr_node(&iter.node, scope);
                 for statement in body {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
                 for statement in orelse {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
             }
 
@@ -260,7 +312,7 @@ impl Cell {
                     self.handle_expr_node(&base.node, scope);
                 }
                 for statement in body {
-                    self.handle_stmt_node(&statement, scope, false);
+                    self.handle_stmt_node(statement, scope, false);
                 }
                 for decorator in decorator_list {
                     self.handle_expr_node(&decorator.node, scope);
@@ -442,9 +494,17 @@ impl Cell {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct CellMetadata {
     pub collapsed: bool,
+
+    /// disabled cells and every cell depending on them do not run
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// the literal assignments of the cell are parameters, see `Parameter`
+    #[serde(default)]
+    pub parameters: bool,
 }
 
 #[cfg(test)]
@@ -473,6 +533,19 @@ mod tests {
         assert_eq!(scope, expected_scope);
     }
 
+    #[test]
+    fn test_statement_lines() {
+        let mut scope = Scope::new();
+        let cell = Cell::new_reactive("a = 1\n\ndef f(x):\n  return x\nf(a)", &mut scope).unwrap();
+
+        let lines = cell
+            .statements
+            .iter()
+            .map(|statement| (statement.start_line, statement.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(1, 1), (3, 4), (5, 5)]);
+    }
+
     #[test]
     fn test_required_vars() {
         let mut scope = Scope::new();
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// mime type (e.g. `text/plain`, `text/html`, `image/png`, `image/svg+xml`, `application/json`)
+/// -> payload, binary payloads like images are base64 encoded by the kernel
+pub type MimeBundle = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DisplayData {
+    pub data: MimeBundle,
+
+    #[serde(default)]
+    pub metadata: BTreeMap<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deserialize_without_metadata() {
+        let display_data: DisplayData = serde_json::from_value(json!({
+            "data": { "text/plain": "<Figure size 640x480>", "image/png": "iVBORw0KGgo=" }
+        }))
+        .unwrap();
+
+        assert_eq!(display_data.data.len(), 2);
+        assert_eq!(display_data.data["image/png"], json!("iVBORw0KGgo="));
+        assert!(display_data.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_from_pickle() {
+        let mut data = MimeBundle::new();
+        data.insert(String::from("text/html"), json!("<b>1</b>"));
+        let display_data = DisplayData {
+            data,
+            metadata: BTreeMap::new(),
+        };
+
+        let pickled = serde_pickle::to_vec(&display_data, Default::default()).unwrap();
+        let unpickled: DisplayData =
+            serde_pickle::from_slice(&pickled, Default::default()).unwrap();
+        assert_eq!(unpickled, display_data);
+    }
+}
@@ -1,9 +1,39 @@
-use std::{error::Error, fmt};
+use rustpython_parser::error::ParseError;
+use serde::Serialize;
+use std::{error::Error, fmt, path::Path, sync::PoisonError};
+
+/// stable codes clients can match on, they never change once released
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    MalformedMessage,
+    UnknownCommand,
+    HandshakeRequired,
+    UnsupportedVersion,
+    Unauthorized,
+    Forbidden,
+    ParseError,
+    NotebookNotFound,
+    CellNotFound,
+    NotACodeCell,
+    InvalidInput,
+    CycleDetected,
+    InvalidOrder,
+    EditMismatch,
+    UnknownRevision,
+    KernelUnavailable,
+    KernelTimeout,
+    KernelError,
+    ExecutionFailed,
+    Persistence,
+    Internal,
+}
 
 #[derive(Debug)]
 pub enum TopologyErrors {
     CellNotFound,
     CycleDetected,
+    InvalidOrder,
 }
 
 impl fmt::Display for TopologyErrors {
@@ -11,6 +41,9 @@ impl fmt::Display for TopologyErrors {
         match self {
             TopologyErrors::CellNotFound => write!(f, "Cell not found"),
             TopologyErrors::CycleDetected => write!(f, "Cycle detected"),
+            TopologyErrors::InvalidOrder => {
+                write!(f, "New order has to contain every cell exactly once")
+            }
         }
     }
 }
@@ -18,16 +51,229 @@ impl fmt::Display for TopologyErrors {
 impl Error for TopologyErrors {}
 
 #[derive(Debug)]
-pub enum NotebookErrors {
-    KernelError(String),
+pub enum EditErrors {
+    LengthMismatch,
+    UnknownRevision,
 }
 
-impl fmt::Display for NotebookErrors {
+impl fmt::Display for EditErrors {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            NotebookErrors::KernelError(e) => write!(f, "Kernel error: {}", e),
+            EditErrors::LengthMismatch => write!(f, "Edit does not match the cell content"),
+            EditErrors::UnknownRevision => write!(f, "Unknown revision of the cell"),
         }
     }
 }
 
-impl Error for NotebookErrors {}
+impl Error for EditErrors {}
+
+#[derive(Debug)]
+pub enum KernelErrors {
+    NotConnected,
+    Unavailable(String),
+    Timeout,
+    CouldNotParse,
+}
+
+impl fmt::Display for KernelErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KernelErrors::NotConnected => write!(f, "No kernel connected"),
+            KernelErrors::Unavailable(e) => write!(f, "Kernel unavailable: {}", e),
+            KernelErrors::Timeout => write!(f, "Kernel did not respond"),
+            KernelErrors::CouldNotParse => write!(f, "Could not parse message of the kernel"),
+        }
+    }
+}
+
+impl Error for KernelErrors {}
+
+/// every error of the core and api layers
+#[derive(Debug)]
+pub enum NotebookError {
+    /// the client sent something we do not understand
+    Protocol(ErrorCode, String),
+    Parse(ParseError),
+    Topology(TopologyErrors),
+    Edit(EditErrors),
+    Kernel(KernelErrors),
+    Persistence(String),
+    NotebookNotFound,
+    NotACodeCell,
+    /// the cell is no input or the value does not fit it
+    InvalidInput(String),
+    /// a cell failed while the notebook was called, see `Notebook::call`
+    Execution(String),
+    /// the request has no valid token
+    Unauthorized,
+    /// the token does not grant the permission for the notebook
+    Forbidden,
+    /// the configuration of the server is invalid, one problem per line
+    Config(String),
+    /// a lock was poisoned or a thread is gone
+    Internal(String),
+}
+
+impl NotebookError {
+    /// a notebook or another file could not be read or written
+    pub fn persistence(path: &Path, e: std::io::Error) -> Self {
+        NotebookError::Persistence(format!("{}: {}", path.display(), e))
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            NotebookError::Protocol(code, _) => *code,
+            NotebookError::Parse(_) => ErrorCode::ParseError,
+            NotebookError::Topology(TopologyErrors::CellNotFound) => ErrorCode::CellNotFound,
+            NotebookError::Topology(TopologyErrors::CycleDetected) => ErrorCode::CycleDetected,
+            NotebookError::Topology(TopologyErrors::InvalidOrder) => ErrorCode::InvalidOrder,
+            NotebookError::Edit(EditErrors::LengthMismatch) => ErrorCode::EditMismatch,
+            NotebookError::Edit(EditErrors::UnknownRevision) => ErrorCode::UnknownRevision,
+            NotebookError::Kernel(KernelErrors::Timeout) => ErrorCode::KernelTimeout,
+            NotebookError::Kernel(KernelErrors::CouldNotParse) => ErrorCode::KernelError,
+            NotebookError::Kernel(_) => ErrorCode::KernelUnavailable,
+            NotebookError::Persistence(_) => ErrorCode::Persistence,
+            NotebookError::NotebookNotFound => ErrorCode::NotebookNotFound,
+            NotebookError::NotACodeCell => ErrorCode::NotACodeCell,
+            NotebookError::InvalidInput(_) => ErrorCode::InvalidInput,
+            NotebookError::Execution(_) => ErrorCode::ExecutionFailed,
+            NotebookError::Unauthorized => ErrorCode::Unauthorized,
+            NotebookError::Forbidden => ErrorCode::Forbidden,
+            NotebookError::Config(_) | NotebookError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl fmt::Display for NotebookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotebookError::Protocol(_, message) => write!(f, "{}", message),
+            NotebookError::Parse(e) => write!(f, "{}", e),
+            NotebookError::Topology(e) => write!(f, "{}", e),
+            NotebookError::Edit(e) => write!(f, "{}", e),
+            NotebookError::Kernel(e) => write!(f, "{}", e),
+            NotebookError::Persistence(e) => write!(f, "Could not read or write notebook: {}", e),
+            NotebookError::NotebookNotFound => write!(f, "Notebook not found"),
+            NotebookError::NotACodeCell => write!(f, "Cell is not a code cell"),
+            NotebookError::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            NotebookError::Execution(e) => write!(f, "Execution failed: {}", e),
+            NotebookError::Unauthorized => write!(f, "Missing or invalid token"),
+            NotebookError::Forbidden => write!(f, "Not allowed to do this with the notebook"),
+            NotebookError::Config(e) => write!(f, "Invalid configuration:\n{}", e),
+            NotebookError::Internal(e) => write!(f, "Internal error: {}", e),
+        }
+    }
+}
+
+impl Error for NotebookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NotebookError::Parse(e) => Some(e),
+            NotebookError::Topology(e) => Some(e),
+            NotebookError::Edit(e) => Some(e),
+            NotebookError::Kernel(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for NotebookError {
+    fn from(e: ParseError) -> Self {
+        NotebookError::Parse(e)
+    }
+}
+
+impl From<TopologyErrors> for NotebookError {
+    fn from(e: TopologyErrors) -> Self {
+        NotebookError::Topology(e)
+    }
+}
+
+impl From<EditErrors> for NotebookError {
+    fn from(e: EditErrors) -> Self {
+        NotebookError::Edit(e)
+    }
+}
+
+impl From<KernelErrors> for NotebookError {
+    fn from(e: KernelErrors) -> Self {
+        NotebookError::Kernel(e)
+    }
+}
+
+impl From<serde_json::Error> for NotebookError {
+    fn from(e: serde_json::Error) -> Self {
+        NotebookError::Persistence(e.to_string())
+    }
+}
+
+impl From<zmq::Error> for NotebookError {
+    fn from(e: zmq::Error) -> Self {
+        NotebookError::Kernel(KernelErrors::Unavailable(e.to_string()))
+    }
+}
+
+/// decoding a message of the kernel, encoding one for it is an internal error
+impl From<serde_pickle::Error> for NotebookError {
+    fn from(e: serde_pickle::Error) -> Self {
+        match e {
+            serde_pickle::Error::Io(e) => {
+                NotebookError::Kernel(KernelErrors::Unavailable(e.to_string()))
+            }
+            _ => NotebookError::Kernel(KernelErrors::CouldNotParse),
+        }
+    }
+}
+
+impl<T> From<std::sync::mpsc::SendError<T>> for NotebookError {
+    // the receiving thread is gone
+    fn from(_: std::sync::mpsc::SendError<T>) -> Self {
+        NotebookError::Kernel(KernelErrors::Unavailable(String::from(
+            "Kernel client stopped",
+        )))
+    }
+}
+
+impl<T> From<PoisonError<T>> for NotebookError {
+    fn from(_: PoisonError<T>) -> Self {
+        NotebookError::Internal(String::from("Lock is poisoned"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let err = NotebookError::from(TopologyErrors::CycleDetected);
+        assert_eq!(err.code(), ErrorCode::CycleDetected);
+        assert_eq!(err.to_string(), "Cycle detected");
+
+        let err = NotebookError::from(KernelErrors::NotConnected);
+        assert_eq!(
+            serde_json::to_value(err.code()).unwrap(),
+            serde_json::json!("kernel_unavailable")
+        );
+
+        let err = rustpython_parser::parser::parse_program("a = (", "<input>").unwrap_err();
+        assert_eq!(NotebookError::from(err).code(), ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let path = Path::new("missing.json");
+        let io = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let err = NotebookError::persistence(path, io);
+        assert_eq!(err.code(), ErrorCode::Persistence);
+        assert!(err.to_string().contains("missing.json"));
+
+        let garbage = serde_pickle::from_slice::<String>(b"garbage", Default::default());
+        let err = NotebookError::from(garbage.unwrap_err());
+        assert_eq!(err.code(), ErrorCode::KernelError);
+
+        let io = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        let err = NotebookError::from(serde_pickle::Error::Io(io));
+        assert_eq!(err.code(), ErrorCode::KernelUnavailable);
+    }
+}
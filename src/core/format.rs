@@ -0,0 +1,346 @@
+use super::{
+    cell::{Cell, CellMetadata, CellType},
+    errors::NotebookError,
+    report::CellRun,
+};
+use serde_json::{json, Value};
+use std::{path::Path, str::FromStr};
+
+// starts a cell in python scripts, like in jupytext and vscode
+const CELL_MARKER: &str = "# %%";
+const MARKDOWN_MARKER: &str = "# %% [markdown]";
+// marks parameter cells, like in jupytext and papermill
+const PARAMETERS_TAG: &str = "parameters";
+
+/// formats a notebook can be read from and written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// the json the server keeps notebooks in
+    Notebook,
+    /// a python script with `# %%` between the cells
+    Python,
+    /// a jupyter notebook, with the outputs of the last headless run
+    Jupyter,
+}
+
+impl Format {
+    /// the format of a file by its extension
+    pub fn of(path: &Path) -> Result<Self, NotebookError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        extension.parse().map_err(NotebookError::Persistence)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Notebook),
+            "py" => Ok(Format::Python),
+            "ipynb" => Ok(Format::Jupyter),
+            _ => Err(format!("unknown format `{}`, use json, py or ipynb", s)),
+        }
+    }
+}
+
+/// the cells of a python script or a jupyter notebook
+pub fn parse(
+    format: Format,
+    content: &str,
+) -> Result<Vec<(CellType, String, CellMetadata)>, NotebookError> {
+    match format {
+        Format::Notebook => Err(NotebookError::Persistence(String::from(
+            "notebooks are read with serde",
+        ))),
+        Format::Python => Ok(parse_python(content)),
+        Format::Jupyter => parse_jupyter(content),
+    }
+}
+
+/// the cells in the format, in display order
+pub fn render(format: Format, title: &str, cells: &[&Cell]) -> Result<String, NotebookError> {
+    match format {
+        Format::Notebook => Err(NotebookError::Persistence(String::from(
+            "notebooks are written with serde",
+        ))),
+        Format::Python => Ok(render_python(cells)),
+        Format::Jupyter => Ok(serde_json::to_string_pretty(&render_jupyter(title, cells))?),
+    }
+}
+
+fn parse_python(content: &str) -> Vec<(CellType, String, CellMetadata)> {
+    let mut cells = Vec::new();
+    // code before the first marker is a cell as well
+    let mut cell = (CellType::ReactiveCode, Vec::new(), CellMetadata::default());
+    for line in content.lines() {
+        let cell_type = if line.starts_with(MARKDOWN_MARKER) {
+            CellType::Markdown
+        } else if line.starts_with(CELL_MARKER) {
+            CellType::ReactiveCode
+        } else {
+            let line = match cell.0 {
+                CellType::Markdown => line
+                    .strip_prefix("# ")
+                    .unwrap_or(line.trim_start_matches('#')),
+                _ => line,
+            };
+            cell.1.push(line);
+            continue;
+        };
+
+        // e.g. `# %% tags=["parameters"]`
+        let metadata = CellMetadata {
+            parameters: line.contains(&format!("\"{}\"", PARAMETERS_TAG)),
+            ..Default::default()
+        };
+        cells.push(std::mem::replace(
+            &mut cell,
+            (cell_type, Vec::new(), metadata),
+        ));
+    }
+    cells.push(cell);
+
+    cells
+        .into_iter()
+        .map(|(cell_type, lines, metadata)| {
+            (cell_type, lines.join("\n").trim().to_string(), metadata)
+        })
+        .filter(|(_, content, _)| !content.is_empty())
+        .collect()
+}
+
+fn render_python(cells: &[&Cell]) -> String {
+    cells
+        .iter()
+        .map(|cell| match cell.cell_type {
+            CellType::Markdown => {
+                let lines = cell
+                    .content
+                    .lines()
+                    .map(|line| format!("# {}", line).trim_end().to_string())
+                    .collect::<Vec<_>>();
+                format!("{}\n{}\n", MARKDOWN_MARKER, lines.join("\n"))
+            }
+            _ if cell.metadata.parameters => format!(
+                "{} tags=[\"{}\"]\n{}\n",
+                CELL_MARKER, PARAMETERS_TAG, cell.content
+            ),
+            _ => format!("{}\n{}\n", CELL_MARKER, cell.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_jupyter(content: &str) -> Result<Vec<(CellType, String, CellMetadata)>, NotebookError> {
+    let notebook: Value = serde_json::from_str(content)?;
+    let cells = notebook["cells"].as_array().ok_or_else(|| {
+        NotebookError::Persistence(String::from("jupyter notebook without cells"))
+    })?;
+
+    // raw cells are left out
+    Ok(cells
+        .iter()
+        .filter_map(|cell| {
+            let cell_type = match cell["cell_type"].as_str() {
+                Some("code") => CellType::ReactiveCode,
+                Some("markdown") => CellType::Markdown,
+                _ => return None,
+            };
+            // the source is a string or a list of lines
+            let source = match &cell["source"] {
+                Value::String(source) => source.clone(),
+                Value::Array(lines) => lines.iter().filter_map(|line| line.as_str()).collect(),
+                _ => String::new(),
+            };
+
+            let tags = cell["metadata"]["tags"].as_array();
+            let metadata = CellMetadata {
+                parameters: tags.is_some_and(|tags| tags.contains(&json!(PARAMETERS_TAG))),
+                ..Default::default()
+            };
+
+            Some((cell_type, source, metadata))
+        })
+        .collect())
+}
+
+fn render_jupyter(title: &str, cells: &[&Cell]) -> Value {
+    let cells = cells
+        .iter()
+        .map(|cell| {
+            let source = cell
+                .content
+                .split_inclusive('\n')
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let tags = if cell.metadata.parameters {
+                vec![PARAMETERS_TAG]
+            } else {
+                Vec::new()
+            };
+            match cell.cell_type {
+                CellType::Markdown => json!({
+                    "cell_type": "markdown",
+                    "id": cell.uuid,
+                    "metadata": {},
+                    "source": source,
+                }),
+                _ => json!({
+                    "cell_type": "code",
+                    "id": cell.uuid,
+                    "metadata": { "tags": tags },
+                    "source": source,
+                    "outputs": cell.last_run.as_ref().map(jupyter_outputs).unwrap_or_default(),
+                    "execution_count": null,
+                }),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "nbformat": 4,
+        "nbformat_minor": 5,
+        "metadata": {
+            "title": title,
+            "kernelspec": { "name": "python3", "display_name": "Python 3", "language": "python" },
+            "language_info": { "name": "python" },
+        },
+        "cells": cells,
+    })
+}
+
+fn jupyter_outputs(run: &CellRun) -> Vec<Value> {
+    let mut outputs = run
+        .outputs
+        .iter()
+        .map(|output| match output.metadata.get("stream") {
+            Some(name) => json!({
+                "output_type": "stream",
+                "name": name,
+                "text": output.data.get("text/plain").cloned().unwrap_or_default(),
+            }),
+            None => json!({
+                "output_type": "display_data",
+                "data": output.data,
+                "metadata": {},
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    match (&run.traceback, &run.error) {
+        (Some(traceback), _) => {
+            let mut lines = traceback
+                .frames
+                .iter()
+                .map(|frame| {
+                    let source = frame.source.as_deref().unwrap_or_default();
+                    format!(
+                        "{}, line {}, in {}\n    {}",
+                        frame.filename, frame.line, frame.name, source
+                    )
+                })
+                .collect::<Vec<_>>();
+            lines.push(format!("{}: {}", traceback.ename, traceback.evalue));
+            outputs.push(json!({
+                "output_type": "error",
+                "ename": traceback.ename,
+                "evalue": traceback.evalue,
+                "traceback": lines,
+            }));
+        }
+        (None, Some(error)) => outputs.push(json!({
+            "output_type": "error",
+            "ename": "Error",
+            "evalue": error,
+            "traceback": [error],
+        })),
+        (None, None) => {}
+    }
+
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::display::DisplayData;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn test_python_round_trip() {
+        let script = "import math\n\n# %% [markdown]\n# # Circles\n#\n# of radius `r`\n\n# %% tags=[\"parameters\"]\nr = 2\n\n# %%\nmath.pi * r ** 2\n";
+        let cells = parse_python(script);
+        let contents = cells
+            .iter()
+            .map(|(_, content, _)| content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contents,
+            vec![
+                "import math",
+                "# Circles\n\nof radius `r`",
+                "r = 2",
+                "math.pi * r ** 2"
+            ]
+        );
+        assert!(matches!(cells[1].0, CellType::Markdown));
+        assert!(cells[2].2.parameters && !cells[3].2.parameters);
+
+        let mut scope = HashMap::new();
+        let cells = cells
+            .into_iter()
+            .map(|(cell_type, content, metadata)| {
+                let mut cell = Cell::new(cell_type, content, &mut scope).unwrap();
+                cell.metadata = metadata;
+                cell
+            })
+            .collect::<Vec<_>>();
+        let rendered = render_python(&cells.iter().collect::<Vec<_>>());
+        assert_eq!(parse_python(&rendered).len(), 4);
+        assert!(rendered.contains("\n# %% tags=[\"parameters\"]\nr = 2\n"));
+        assert!(rendered.starts_with("# %%\nimport math\n\n# %% [markdown]\n# # Circles\n#\n"));
+    }
+
+    #[test]
+    fn test_jupyter_round_trip() {
+        let mut scope = HashMap::new();
+        let mut cell = Cell::new_reactive("a = 1\nprint(a)", &mut scope).unwrap();
+        cell.metadata.parameters = true;
+        let notebook = render_jupyter("Title", &[&cell]);
+        assert_eq!(
+            notebook["cells"][0]["source"],
+            json!(["a = 1\n", "print(a)"])
+        );
+        assert_eq!(
+            notebook["cells"][0]["metadata"]["tags"],
+            json!(["parameters"])
+        );
+
+        let cells = parse_jupyter(&notebook.to_string()).unwrap();
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].1, "a = 1\nprint(a)");
+        assert!(cells[0].2.parameters);
+
+        assert!(parse_jupyter("{}").is_err());
+
+        cell.last_run = Some(CellRun {
+            outputs: vec![DisplayData {
+                data: BTreeMap::from([(String::from("text/plain"), json!("1\n"))]),
+                metadata: BTreeMap::from([(String::from("stream"), json!("stdout"))]),
+            }],
+            error: Some(String::from("NameError: name 'b' is not defined")),
+            ..Default::default()
+        });
+        let outputs = &render_jupyter("Title", &[&cell])["cells"][0]["outputs"];
+        assert_eq!(outputs[0]["output_type"], json!("stream"));
+        assert_eq!(outputs[0]["text"], json!("1\n"));
+        assert_eq!(outputs[1]["output_type"], json!("error"));
+        assert_eq!(Format::of(Path::new("a/b.ipynb")).unwrap(), Format::Jupyter);
+        assert!(Format::of(Path::new("b.txt")).is_err());
+    }
+}
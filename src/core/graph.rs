@@ -0,0 +1,237 @@
+use super::{notebook::Scope, topology::Topology};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// longer first lines are cut off in node labels
+const LABEL_LENGTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    Dot,
+    Mermaid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub uuid: String,
+
+    /// first non empty line of the cell
+    pub label: String,
+}
+
+/// `from` defines the variables which are used by `to`, edges point in the direction of the data flow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub variables: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl DependencyGraph {
+    pub fn new(topology: &Topology, scope: &Scope) -> Self {
+        let nodes = topology
+            .display_order
+            .iter()
+            .filter_map(|uuid| topology.cells.get(uuid))
+            .map(|cell| GraphNode {
+                uuid: cell.uuid.clone(),
+                label: Self::label(&cell.content),
+            })
+            .collect::<Vec<_>>();
+
+        let mut edges = Vec::new();
+        for node in nodes.iter() {
+            let cell = &topology.cells[&node.uuid];
+            let mut dependencies = topology
+                .dependencies
+                .get(&cell.uuid)
+                .map(|dependencies| dependencies.iter().collect::<Vec<_>>())
+                .unwrap_or_default();
+            dependencies.sort();
+
+            for dependency in dependencies {
+                let mut variables = cell
+                    .required
+                    .iter()
+                    .filter(|name| scope.get(*name) == Some(dependency))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                variables.sort();
+
+                edges.push(GraphEdge {
+                    from: dependency.clone(),
+                    to: cell.uuid.clone(),
+                    variables,
+                });
+            }
+        }
+
+        Self { nodes, edges }
+    }
+
+    fn label(content: &str) -> String {
+        let line = content
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+
+        if line.chars().count() > LABEL_LENGTH {
+            let cut = line.chars().take(LABEL_LENGTH).collect::<String>();
+            format!("{}...", cut)
+        } else {
+            line.to_string()
+        }
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Json => serde_json::to_string(self).unwrap(),
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut dot = String::from("digraph notebook {\n    node [shape=box];\n");
+        for node in self.nodes.iter() {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\"];",
+                escape(&node.uuid),
+                escape(&node.label)
+            );
+        }
+        for edge in self.edges.iter() {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                escape(&edge.from),
+                escape(&edge.to),
+                escape(&edge.variables.join(", "))
+            );
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        // uuids may contain "-", which mermaid would read as part of an arrow
+        let id = |uuid: &str| {
+            let index = self.nodes.iter().position(|node| node.uuid == uuid);
+            format!("cell{}", index.unwrap_or_default())
+        };
+        let escape = |text: &str| text.replace('"', "#quot;");
+
+        let mut mermaid = String::from("flowchart TD\n");
+        for node in self.nodes.iter() {
+            let _ = writeln!(
+                mermaid,
+                "    {}[\"{}\"]",
+                id(&node.uuid),
+                escape(&node.label)
+            );
+        }
+        for edge in self.edges.iter() {
+            let _ = writeln!(
+                mermaid,
+                "    {} -->|{}| {}",
+                id(&edge.from),
+                edge.variables.join(", "),
+                id(&edge.to)
+            );
+        }
+
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cell::Cell;
+
+    fn example() -> (DependencyGraph, Vec<String>) {
+        let mut scope = Scope::new();
+        let cell_1 = Cell::new_reactive("a = 1\nb = \"two\"", &mut scope).unwrap();
+        let cell_2 = Cell::new_reactive("\n\nc = a + len(b)", &mut scope).unwrap();
+        let cell_3 = Cell::new_reactive("print(a, c)", &mut scope).unwrap();
+        let uuids = vec![
+            cell_1.uuid.clone(),
+            cell_2.uuid.clone(),
+            cell_3.uuid.clone(),
+        ];
+
+        let topology = Topology::from_vec(vec![cell_1, cell_2, cell_3], &mut scope).unwrap();
+        (DependencyGraph::new(&topology, &scope), uuids)
+    }
+
+    #[test]
+    fn test_graph_nodes_and_edges() {
+        let (graph, uuids) = example();
+
+        let labels = graph
+            .nodes
+            .iter()
+            .map(|node| node.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["a = 1", "c = a + len(b)", "print(a, c)"]);
+
+        assert_eq!(graph.edges.len(), 3);
+        let edge = graph
+            .edges
+            .iter()
+            .find(|edge| edge.from == uuids[0] && edge.to == uuids[1])
+            .unwrap();
+        assert_eq!(edge.variables, vec!["a", "b"]);
+
+        let edge = graph
+            .edges
+            .iter()
+            .find(|edge| edge.from == uuids[1] && edge.to == uuids[2])
+            .unwrap();
+        assert_eq!(edge.variables, vec!["c"]);
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let (graph, uuids) = example();
+        let dot = graph.to_dot();
+
+        assert!(dot.starts_with("digraph notebook {"));
+        assert!(dot.contains(&format!("\"{}\" [label=\"a = 1\"];", uuids[0])));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [label=\"a, b\"];",
+            uuids[0], uuids[1]
+        )));
+    }
+
+    #[test]
+    fn test_graph_mermaid() {
+        let (graph, _) = example();
+        let mermaid = graph.to_mermaid();
+
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains("    cell0[\"a = 1\"]\n"));
+        assert!(mermaid.contains("    cell0 -->|a, b| cell1\n"));
+        assert!(mermaid.contains("    cell1 -->|c| cell2\n"));
+    }
+
+    #[test]
+    fn test_graph_label_is_truncated() {
+        let label = DependencyGraph::label(&"x".repeat(100));
+        assert_eq!(label, format!("{}...", "x".repeat(LABEL_LENGTH)));
+    }
+}
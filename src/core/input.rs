@@ -0,0 +1,210 @@
+use super::errors::NotebookError;
+use rustpython_parser::ast::{Constant, ExprKind, Located, Stmt, StmtKind, Unaryop};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// a cell which only assigns a literal to a name, e.g. `threshold = 0.5`. Viewers of a published
+/// notebook change its value with a widget instead of editing code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub name: String,
+    pub value: Value,
+}
+
+impl Input {
+    pub fn of(ast: &[Stmt]) -> Option<Self> {
+        match ast {
+            [stmt] => Self::assigned(stmt),
+            _ => None,
+        }
+    }
+
+    /// the name and literal of an assignment like `threshold = 0.5`
+    fn assigned(stmt: &Stmt) -> Option<Self> {
+        let (id, value) = match &stmt.node {
+            StmtKind::Assign { targets, value, .. } => match targets.as_slice() {
+                [Located {
+                    node: ExprKind::Name { id, .. },
+                    ..
+                }] => (id, value),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let value = match &value.node {
+            ExprKind::Constant { value, .. } => literal(value, false)?,
+            ExprKind::UnaryOp {
+                op: Unaryop::USub,
+                operand,
+            } => match &operand.node {
+                ExprKind::Constant { value, .. } => literal(value, true)?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            name: id.to_string(),
+            value,
+        })
+    }
+
+    /// the content of the cell with `value` assigned instead, it has to be of the same kind
+    pub fn assignment(&self, value: &Value) -> Result<String, NotebookError> {
+        let literal = match (&self.value, value) {
+            (Value::Bool(_), Value::Bool(value)) => {
+                String::from(if *value { "True" } else { "False" })
+            }
+            (Value::Number(_), Value::Number(value)) => value.to_string(),
+            // json string literals are python string literals as well
+            (Value::String(_), Value::String(_)) => value.to_string(),
+            _ => {
+                return Err(NotebookError::InvalidInput(format!(
+                    "{} has to be like {}",
+                    self.name, self.value
+                )))
+            }
+        };
+
+        Ok(format!("{} = {}", self.name, literal))
+    }
+}
+
+/// a literal assignment in a cell marked as parameters, e.g. `samples = 100`. Runs of the
+/// notebook may override its value, like with papermill
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Parameter {
+    pub name: String,
+
+    /// the value in the notebook
+    pub value: Value,
+    pub cell_uuid: String,
+
+    /// line and columns of the assignment in the cell content, starting at 0
+    #[serde(skip)]
+    position: (usize, usize, usize),
+}
+
+impl Parameter {
+    /// the literal assignments of the cell which are on a single line, in order
+    pub fn all_of(ast: &[Stmt], cell_uuid: &str) -> Vec<Self> {
+        ast.iter()
+            .filter_map(|stmt| {
+                let end = stmt.end_location?;
+                if end.row() != stmt.location.row() {
+                    return None;
+                }
+
+                let Input { name, value } = Input::assigned(stmt)?;
+                Some(Self {
+                    name,
+                    value,
+                    cell_uuid: cell_uuid.to_string(),
+                    position: (
+                        stmt.location.row() - 1,
+                        stmt.location.column(),
+                        end.column(),
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// the content of the cell with `value` assigned instead, it has to be of the same kind
+    pub fn replace(&self, content: &str, value: &Value) -> Result<String, NotebookError> {
+        let input = Input {
+            name: self.name.clone(),
+            value: self.value.clone(),
+        };
+        let assignment = input.assignment(value)?;
+
+        let (row, start, end) = self.position;
+        let lines = content
+            .split('\n')
+            .enumerate()
+            .map(|(index, line)| {
+                if index != row {
+                    return line.to_string();
+                }
+                let before = line.chars().take(start).collect::<String>();
+                let after = line.chars().skip(end).collect::<String>();
+                format!("{}{}{}", before, assignment, after)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(lines.join("\n"))
+    }
+}
+
+fn literal(constant: &Constant, negative: bool) -> Option<Value> {
+    match constant {
+        Constant::Bool(value) if !negative => Some(Value::Bool(*value)),
+        Constant::Str(value) if !negative => Some(Value::String(value.clone())),
+        Constant::Int(value) => {
+            let value = value.to_string().parse::<i64>().ok()?;
+            Some(Value::from(if negative { -value } else { value }))
+        }
+        Constant::Float(value) => Some(Value::from(if negative { -value } else { *value })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustpython_parser::parser;
+    use serde_json::json;
+
+    fn input(content: &str) -> Option<Input> {
+        Input::of(&parser::parse_program(content, "<input>").unwrap())
+    }
+
+    #[test]
+    fn test_inputs() {
+        assert_eq!(input("a = -2").unwrap().value, json!(-2));
+        assert_eq!(input("title = 'hi'").unwrap().value, json!("hi"));
+        assert_eq!(input("flag = True").unwrap().name, "flag");
+
+        assert!(input("a = b").is_none());
+        assert!(input("a = 1\nb = 2").is_none());
+        assert!(input("a, b = 1, 2").is_none());
+        assert!(input("print(1)").is_none());
+    }
+
+    #[test]
+    fn test_parameters() {
+        let content = "import math\nsamples = 100  # per run\nname, seed = 'a', 1\nrate = -0.5; label = 'x'\nmean = math.pi\ntext = \"\"\"\nlong\"\"\"";
+        let ast = parser::parse_program(content, "<input>").unwrap();
+        let parameters = Parameter::all_of(&ast, "a");
+        let names = parameters
+            .iter()
+            .map(|parameter| parameter.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["samples", "rate", "label"]);
+        assert_eq!(parameters[1].value, json!(-0.5));
+
+        let content = parameters[2].replace(content, &json!("y")).unwrap();
+        let content = parameters[1].replace(&content, &json!(2)).unwrap();
+        let content = parameters[0].replace(&content, &json!(10)).unwrap();
+        assert!(content.contains("\nsamples = 10  # per run\n"));
+        assert!(content.contains("\nrate = 2; label = \"y\"\n"));
+        assert!(parameters[0].replace(&content, &json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_assignment() {
+        let threshold = input("threshold = 0.5").unwrap();
+        assert_eq!(
+            threshold.assignment(&json!(0.75)).unwrap(),
+            "threshold = 0.75"
+        );
+        assert!(threshold.assignment(&json!("0.75")).is_err());
+
+        let title = input("title = 'hi'").unwrap();
+        assert_eq!(
+            title.assignment(&json!("it's \"here\"")).unwrap(),
+            r#"title = "it's \"here\"""#
+        );
+    }
+}
@@ -1,49 +1,96 @@
-use super::cell::{Cell, LocalValue};
-use crate::api::ws_client::WsClient;
-use actix::{Addr, Message};
+use super::{
+    cell::{Cell, LocalValue},
+    display::DisplayData,
+    errors::{KernelErrors, NotebookError},
+    traceback::Traceback,
+    value::{InspectRequest, InspectResponse, KernelNamespace, ValuesRequest},
+};
+use crate::api::{sessions::SessionRegistry, state::OpenNotebooks, ws_client::WsClient};
+use actix::{Handler, Message};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error, fmt, process::Command, sync::mpsc, thread};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus},
+    sync::{mpsc, Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tracing::{info, log::warn};
 use zmq::Socket;
 
+// while waiting for results the queue is checked for plans superseding the running one
+const POLL_INTERVAL_MS: i64 = 100;
+
+// pings sent to a started kernel until its answer arrives on the subscription, see `ping`
+const PING_ATTEMPTS: usize = 50;
+
+/// how the kernel is started and reached
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KernelConfig {
+    /// program and arguments starting the kernel
+    pub command: Vec<String>,
+
+    /// directory the kernel is started in
+    pub dir: PathBuf,
+
+    /// ports the kernel publishes its results and answers requests on
+    pub pub_port: u16,
+    pub req_port: u16,
+}
+
+impl Default for KernelConfig {
+    fn default() -> Self {
+        Self {
+            command: vec![String::from("python3"), String::from("main.py")],
+            dir: PathBuf::from("./kernel/src"),
+            pub_port: 8081,
+            // the kernel always bound its request socket on 8082, the former default of 8081
+            // was its pub port and only worked with ZMQ_PORT_REQ set
+            req_port: 8082,
+        }
+    }
+}
+
 pub struct KernelClient {
     sub_socket: Socket,
     req_socket: Socket,
     rx: mpsc::Receiver<KernelClientMsg>,
     pub tx: mpsc::Sender<KernelClientMsg>,
-    ws_mapping: HashMap<String, Addr<WsClient>>, // notebook_uuid, ws sender
+    sessions: Arc<Mutex<SessionRegistry>>,
+
+    /// cells which finished are not stale anymore
+    notebooks: OpenNotebooks,
+
+    /// received messages which were not handled yet
+    pending: VecDeque<KernelClientMsg>,
+
+    /// id of the latest execution plan sent to the kernel
+    run_id: u64,
+    kernel: Child,
+    config: KernelConfig,
 }
 
 impl KernelClient {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let current_dir = std::env::current_dir()?;
-        let kernel_path = current_dir.join("kernel").join("src").join("main.py");
-        info!("kernel path: {:?}", kernel_path);
-
-        let zmq_port_sub = std::env::var("ZMQ_PORT_SUB")
-            .unwrap_or_else(|_| "8081".to_string())
-            .parse::<u16>()?;
-        let zmq_port_req = std::env::var("ZMQ_PORT_REQ")
-            .unwrap_or_else(|_| "8081".to_string())
-            .parse::<u16>()?;
+    pub fn new(
+        sessions: Arc<Mutex<SessionRegistry>>,
+        notebooks: OpenNotebooks,
+        config: &KernelConfig,
+    ) -> Result<Self, NotebookError> {
+        info!("kernel: {:?} in {:?}", config.command, config.dir);
 
         let ctx = zmq::Context::new();
 
         let sub_socket = ctx.socket(zmq::SUB)?;
-        sub_socket.connect(&format!("tcp://localhost:{:?}", zmq_port_sub))?;
+        sub_socket.connect(&format!("tcp://localhost:{:?}", config.pub_port))?;
         sub_socket.set_subscribe(b"")?;
 
         let req_socket = ctx.socket(zmq::REQ)?;
-        req_socket.connect(&format!("tcp://localhost:{:?}", zmq_port_req))?;
-
-        thread::spawn(|| {
-            Command::new("python3")
-                .current_dir("./kernel/src/")
-                .arg("main.py")
-                .spawn()
-                .expect("Failed to start kernel");
-        });
+        req_socket.connect(&format!("tcp://localhost:{:?}", config.req_port))?;
 
+        let kernel = spawn_kernel(config)?;
         let (tx, rx) = mpsc::channel();
 
         Ok(Self {
@@ -51,25 +98,75 @@ impl KernelClient {
             req_socket,
             rx,
             tx,
-            ws_mapping: HashMap::new(),
+            sessions,
+            notebooks,
+            pending: VecDeque::new(),
+            run_id: 0,
+            kernel,
+            config: config.clone(),
         })
     }
 
     pub fn start(&mut self) {
         loop {
             info!("waiting in start for message");
-            match self.rx.recv() {
+            match self.next_msg() {
                 Ok(msg) => {
                     info!("Received message: {:#?}", msg);
+                    if !matches!(msg, KernelClientMsg::Shutdown) {
+                        if let Err(e) = self.ensure_kernel() {
+                            warn!("Could not restart the kernel: {}", e);
+                        }
+                    }
                     match msg {
-                        KernelClientMsg::InitWs(uuid, sender) => {
-                            self.ws_mapping.insert(uuid, sender);
+                        KernelClientMsg::MsgToKernel(msg) if self.skip_superseded(&msg) => {
+                            info!("Skipping superseded plan of {}", msg.notebook_uuid);
+                        }
+                        KernelClientMsg::MsgToKernel(mut msg) => {
+                            self.run_id += 1;
+                            msg.run_id = self.run_id;
+                            let _res = self.send_to_kernel(&KernelRequest::Execute(&msg));
+                            let res = self.receive_from_kernel(&msg);
+                            info!("res: {:?}", res);
+                        }
+                        KernelClientMsg::Inspect(req) => {
+                            let res = self.inspect(&req);
+                            info!("res: {:?}", res);
+                        }
+                        KernelClientMsg::Namespace(notebook_uuid, origin, reply) => {
+                            let res = self.namespace(&notebook_uuid, origin, reply);
+                            info!("res: {:?}", res);
+                        }
+                        KernelClientMsg::Values(req, reply) => {
+                            let res = self.values(&req, reply);
+                            info!("res: {:?}", res);
+                        }
+                        KernelClientMsg::Ping(reply) => {
+                            let res = self.ping(reply);
+                            info!("res: {:?}", res);
+                        }
+                        KernelClientMsg::DropNamespace(namespace) => {
+                            let res = self.send_to_kernel(&KernelRequest::DropNamespace {
+                                namespace: &namespace,
+                            });
+                            info!("res: {:?}", res);
                         }
-                        KernelClientMsg::MsgToKernel(msg) => {
-                            let _res = self.send_to_kernel(&msg);
-                            let res = self.receive_from_kernel();
+                        KernelClientMsg::InvalidateCells(notebook_uuid, cell_uuids) => {
+                            let res = self.send_to_kernel(&KernelRequest::InvalidateCells {
+                                notebook_uuid: &notebook_uuid,
+                                cell_uuids: &cell_uuids,
+                            });
                             info!("res: {:?}", res);
                         }
+                        KernelClientMsg::Shutdown => {
+                            info!("Shutting down the kernel");
+                            if let Err(e) = self.signal(libc::SIGTERM) {
+                                warn!("Could not stop the kernel: {}", e);
+                            }
+                            let status = self.kernel.wait();
+                            info!("Kernel exited: {:?}", status);
+                            return;
+                        }
                     }
                 }
                 Err(_e) => {
@@ -79,48 +176,420 @@ impl KernelClient {
         }
     }
 
-    pub fn receive_from_kernel(&self) -> Result<(), Box<dyn Error>> {
+    /// the oldest message not handled yet, everything queued behind it is taken off the channel
+    /// so superseded plans can be found
+    fn next_msg(&mut self) -> Result<KernelClientMsg, mpsc::RecvError> {
+        if self.pending.is_empty() {
+            let msg = self.rx.recv()?;
+            self.enqueue(msg);
+        }
+        self.receive_pending();
+
+        // nothing runs anymore once the kernel is to be stopped
+        if let Some(position) = self.shutdown_position() {
+            return Ok(self.pending.remove(position).unwrap());
+        }
+
+        Ok(self.pending.pop_front().unwrap())
+    }
+
+    fn shutdown_position(&self) -> Option<usize> {
+        self.pending
+            .iter()
+            .position(|msg| matches!(msg, KernelClientMsg::Shutdown))
+    }
+
+    fn receive_pending(&mut self) {
+        while let Ok(msg) = self.rx.try_recv() {
+            self.enqueue(msg);
+        }
+    }
+
+    /// cells of a plan are queued until the kernel starts running them
+    fn enqueue(&mut self, msg: KernelClientMsg) {
+        if let KernelClientMsg::MsgToKernel(plan) = &msg {
+            let timestamp = now_millis();
+            for cell in plan.execution_cells.iter() {
+                let queued = MsgFromKernel {
+                    notebook_uuid: plan.notebook_uuid.clone(),
+                    cell_uuid: cell.uuid.clone(),
+                    origin: plan.origin.clone(),
+                    status: Some(CellStatus::Queued),
+                    timestamp: Some(timestamp),
+                    ..Default::default()
+                };
+                if let Err(e) = self.deliver(plan, queued) {
+                    warn!("Could not send status: {}", e);
+                }
+            }
+        }
+        self.pending.push_back(msg);
+    }
+
+    fn broadcast_result(&self, msg: MsgFromKernel) -> Result<(), NotebookError> {
+        let mut sessions = self.sessions.lock()?;
+        if sessions.broadcast_result(msg) == 0 {
+            warn!("Could not find ws connection");
+        }
+
+        Ok(())
+    }
+
+    /// results of a plan in a private namespace only go to its listeners, e.g. of a call, or
+    /// else to the session which sent it
+    fn deliver(&self, plan: &MsgToKernel, msg: MsgFromKernel) -> Result<(), NotebookError> {
+        match &plan.namespace {
+            Some(namespace) if self.sessions.lock()?.notify(namespace, &msg) > 0 => Ok(()),
+            Some(_) => self.send_to_session(&plan.notebook_uuid, &plan.origin, msg),
+            None => self.broadcast_result(msg),
+        }
+    }
+
+    /// a queued plan which runs at least the same cells supersedes `msg`, it takes over the
+    /// namespaces `msg` would have dropped
+    fn skip_superseded(&mut self, msg: &MsgToKernel) -> bool {
+        let newer = self.pending.iter_mut().find_map(|pending| match pending {
+            KernelClientMsg::MsgToKernel(newer) if newer.supersedes(msg) => Some(newer),
+            _ => None,
+        });
+
+        match newer {
+            Some(newer) => {
+                for cell_uuid in msg.invalidate.iter() {
+                    if !newer.invalidate.contains(cell_uuid) {
+                        newer.invalidate.push(cell_uuid.clone());
+                    }
+                }
+                if let Err(e) = self.release(msg, &HashSet::new()) {
+                    warn!("Could not send status: {}", e);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// cells of a skipped or interrupted plan are queued again if a pending plan runs them,
+    /// the others are idle unless they finished, failed or were blocked before
+    fn release(&self, plan: &MsgToKernel, settled: &HashSet<String>) -> Result<(), NotebookError> {
+        let timestamp = now_millis();
+        for cell in plan.execution_cells.iter() {
+            let newer = self.pending.iter().find_map(|pending| match pending {
+                KernelClientMsg::MsgToKernel(newer)
+                    if newer.notebook_uuid == plan.notebook_uuid
+                        && newer.namespace == plan.namespace
+                        && newer.execution_cells.iter().any(|c| c.uuid == cell.uuid) =>
+                {
+                    Some(newer)
+                }
+                _ => None,
+            });
+            let (owner, status) = match newer {
+                Some(newer) => (newer, CellStatus::Queued),
+                None if settled.contains(&cell.uuid) => continue,
+                None => (plan, CellStatus::Idle),
+            };
+            let msg = MsgFromKernel {
+                notebook_uuid: plan.notebook_uuid.clone(),
+                cell_uuid: cell.uuid.clone(),
+                origin: owner.origin.clone(),
+                status: Some(status),
+                timestamp: Some(timestamp),
+                ..Default::default()
+            };
+            self.deliver(owner, msg)?;
+        }
+
+        Ok(())
+    }
+
+    /// sends the results of the run to every session of the notebook until the kernel says
+    /// the run ended. The run is interrupted as soon as a plan superseding it is queued, its
+    /// remaining results and the ones of earlier runs are dropped
+    pub fn receive_from_kernel(&mut self, run: &MsgToKernel) -> Result<(), NotebookError> {
+        let mut interrupted = false;
+        // cells of the run which finished, failed or were blocked
+        let mut settled = HashSet::new();
         loop {
             info!("Waiting for response from kernel");
 
+            if self.sub_socket.poll(zmq::POLLIN, POLL_INTERVAL_MS)? == 0 {
+                if let Some(status) = self.exit_status()? {
+                    return self.kernel_exited(run, &settled, status);
+                }
+                self.receive_pending();
+                if self.shutdown_position().is_some() {
+                    info!("Leaving run {} to shut down", run.run_id);
+                    return Ok(());
+                }
+                if !interrupted && self.is_superseded(run) {
+                    info!("Interrupting run {}", run.run_id);
+                    self.interrupt()?;
+                    interrupted = true;
+                }
+                continue;
+            }
+
             let mut msg = zmq::Message::new();
             self.sub_socket.recv(&mut msg, 0)?;
 
             info!("msg: {:?}", msg);
             let res: MsgFromKernel = serde_pickle::from_slice(&msg, Default::default())?;
             info!("Received message from kernel: {:#?}", res);
+            if res.run_id != run.run_id {
+                info!("Dropping result of previous run {}", res.run_id);
+                continue;
+            }
             if res.ended {
                 info!("Kernel ended");
+                // listeners wait for the end of the runs of the notebook or namespace
+                let key = run.namespace.as_ref().unwrap_or(&run.notebook_uuid);
+                self.sessions.lock()?.notify(key, &res);
+                // results of the interrupted run arrived after the newer plan queued its cells
+                if interrupted {
+                    self.release(run, &settled)?;
+                }
                 break;
             }
+            if interrupted {
+                info!("Dropping result of superseded run {}", res.run_id);
+                continue;
+            }
 
-            let ws_conn = match self.ws_mapping.get(&res.notebook_uuid) {
-                Some(ws_conn) => ws_conn,
-                None => {
-                    warn!("Could not find ws connection");
-                    continue;
-                }
-            };
+            // every session of the notebook sees the results
             if let Some(err) = &res.error {
                 warn!("Error from kernel: {}", err);
-                ws_conn.do_send(res);
-                break;
             }
+            if let Some(CellStatus::Finished | CellStatus::Errored | CellStatus::Blocked) =
+                res.status
+            {
+                settled.insert(res.cell_uuid.clone());
+            }
+            if res.status == Some(CellStatus::Finished) && run.namespace.is_none() {
+                self.finished(run, &res.cell_uuid)?;
+            }
+            self.deliver(run, res)?;
+        }
+
+        Ok(())
+    }
 
-            ws_conn.do_send(res);
+    /// the cell is up to date now, sessions get the stale cells without it
+    fn finished(&self, run: &MsgToKernel, cell_uuid: &str) -> Result<(), NotebookError> {
+        let cell = match run
+            .execution_cells
+            .iter()
+            .find(|cell| cell.uuid == cell_uuid)
+        {
+            Some(cell) => cell,
+            None => return Ok(()),
+        };
+        let mut notebooks = self.notebooks.lock()?;
+        let notebook = match notebooks.get_mut(&run.notebook_uuid) {
+            Some(notebook) => notebook,
+            None => return Ok(()),
+        };
+        if notebook.finished(cell) {
+            let stale = notebook.stale_cells();
+            self.sessions
+                .lock()?
+                .broadcast_stale(&run.notebook_uuid, stale);
         }
 
         Ok(())
     }
 
-    pub fn send_to_kernel(&self, msg: &MsgToKernel) -> Result<(), Box<dyn Error>> {
+    fn is_superseded(&self, run: &MsgToKernel) -> bool {
+        self.pending.iter().any(|pending| match pending {
+            KernelClientMsg::MsgToKernel(newer) => newer.supersedes(run),
+            _ => false,
+        })
+    }
+
+    /// the cells of the run which did not finish fail, listeners learn the run ended and a new
+    /// kernel is started for the next plans. What ran before is gone with the old kernel
+    fn kernel_exited(
+        &mut self,
+        run: &MsgToKernel,
+        settled: &HashSet<String>,
+        status: ExitStatus,
+    ) -> Result<(), NotebookError> {
+        let error = format!("Kernel exited with {}", status);
+        warn!("{} during run {}", error, run.run_id);
+
+        for cell in run.execution_cells.iter() {
+            if settled.contains(&cell.uuid) {
+                continue;
+            }
+            let failed = MsgFromKernel {
+                notebook_uuid: run.notebook_uuid.clone(),
+                cell_uuid: cell.uuid.clone(),
+                error: Some(error.clone()),
+                origin: run.origin.clone(),
+                run_id: run.run_id,
+                ..Default::default()
+            };
+            let errored = MsgFromKernel {
+                status: Some(CellStatus::Errored),
+                timestamp: Some(now_millis()),
+                error: None,
+                ..failed.clone()
+            };
+            self.deliver(run, failed)?;
+            self.deliver(run, errored)?;
+        }
+        let ended = MsgFromKernel {
+            notebook_uuid: run.notebook_uuid.clone(),
+            cell_uuid: run.cell_uuid.clone(),
+            error: Some(error.clone()),
+            ended: true,
+            origin: run.origin.clone(),
+            run_id: run.run_id,
+            ..Default::default()
+        };
+        let key = run.namespace.as_ref().unwrap_or(&run.notebook_uuid);
+        self.sessions.lock()?.notify(key, &ended);
+
+        self.restart_kernel()?;
+
+        Err(KernelErrors::Unavailable(error).into())
+    }
+
+    fn exit_status(&mut self) -> Result<Option<ExitStatus>, NotebookError> {
+        let status = self
+            .kernel
+            .try_wait()
+            .map_err(|e| KernelErrors::Unavailable(e.to_string()))?;
+
+        Ok(status)
+    }
+
+    /// a kernel which exited while nothing ran is started again before the next request, which
+    /// would wait for an answer forever otherwise
+    fn ensure_kernel(&mut self) -> Result<(), NotebookError> {
+        if let Some(status) = self.exit_status()? {
+            warn!("Kernel exited with {}, restarting it", status);
+            self.restart_kernel()?;
+        }
+
+        Ok(())
+    }
+
+    fn restart_kernel(&mut self) -> Result<(), NotebookError> {
+        respawn(
+            &mut self.kernel,
+            &self.config,
+            &self.notebooks,
+            &self.sessions,
+        )?;
+        info!("Restarted the kernel");
+
+        Ok(())
+    }
+
+    /// raises a KeyboardInterrupt in the cell the kernel is running
+    fn interrupt(&self) -> Result<(), NotebookError> {
+        self.signal(libc::SIGINT)
+    }
+
+    fn signal(&self, signal: libc::c_int) -> Result<(), NotebookError> {
+        // the pid is the one of our own child, which is not reaped before it was waited for
+        if unsafe { libc::kill(self.kernel.id() as libc::pid_t, signal) } != 0 {
+            let e = std::io::Error::last_os_error();
+            return Err(KernelErrors::Unavailable(e.to_string()).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn send_to_kernel(&self, msg: &KernelRequest) -> Result<Vec<u8>, NotebookError> {
         info!("sending message to kernel: {:#?}", msg);
 
-        let msg = serde_pickle::to_vec(msg, Default::default())?;
+        // the request could not be pickled, the kernel is not at fault
+        let msg = serde_pickle::to_vec(msg, Default::default())
+            .map_err(|e| NotebookError::Internal(format!("Could not encode request: {}", e)))?;
         self.req_socket.send(&msg, 0)?;
         let res = self.req_socket.recv_bytes(0)?;
         info!("Received response from kernel: {:?}", res);
 
+        Ok(res)
+    }
+
+    /// inspections are answered directly on the request socket
+    pub fn inspect(&self, req: &InspectRequest) -> Result<(), NotebookError> {
+        let res = self.send_to_kernel(&KernelRequest::Inspect(req))?;
+        let mut res: InspectResponse = serde_pickle::from_slice(&res, Default::default())?;
+        res.origin = req.origin.clone();
+
+        self.send_to_session(&req.notebook_uuid, &req.origin, res)
+    }
+
+    pub fn namespace(
+        &self,
+        notebook_uuid: &str,
+        origin: Origin,
+        reply: Option<mpsc::Sender<KernelNamespace>>,
+    ) -> Result<(), NotebookError> {
+        let res = self.send_to_kernel(&KernelRequest::Namespace { notebook_uuid })?;
+        let mut res: KernelNamespace = serde_pickle::from_slice(&res, Default::default())?;
+        res.origin = origin.clone();
+
+        match reply {
+            Some(reply) => Ok(reply.send(res)?),
+            None => self.send_to_session(notebook_uuid, &origin, res),
+        }
+    }
+
+    /// what a kernel publishes before the subscription is connected is lost, so it is pinged
+    /// until its answer, the only message without a run id, arrives on the subscription
+    fn ping(&self, reply: mpsc::Sender<()>) -> Result<(), NotebookError> {
+        for _ in 0..PING_ATTEMPTS {
+            self.send_to_kernel(&KernelRequest::Ping)?;
+            while self.sub_socket.poll(zmq::POLLIN, POLL_INTERVAL_MS)? > 0 {
+                let msg = self.sub_socket.recv_bytes(0)?;
+                let res: MsgFromKernel = serde_pickle::from_slice(&msg, Default::default())?;
+                if res.run_id == 0 {
+                    return Ok(reply.send(())?);
+                }
+            }
+        }
+
+        Err(KernelErrors::Timeout.into())
+    }
+
+    /// values of a private namespace as json, answered directly on the request socket
+    pub fn values(
+        &self,
+        req: &ValuesRequest,
+        reply: mpsc::Sender<HashMap<String, Value>>,
+    ) -> Result<(), NotebookError> {
+        let res = self.send_to_kernel(&KernelRequest::Values(req))?;
+        let res = serde_json::from_slice(&res).map_err(|_| KernelErrors::CouldNotParse)?;
+
+        Ok(reply.send(res)?)
+    }
+
+    /// responses only go to the session which asked for them
+    fn send_to_session<M>(
+        &self,
+        notebook_uuid: &str,
+        origin: &Origin,
+        msg: M,
+    ) -> Result<(), NotebookError>
+    where
+        M: Message<Result = ()> + Send + 'static,
+        WsClient: Handler<M>,
+    {
+        let sessions = self.sessions.lock()?;
+        let session = origin
+            .session_id
+            .as_ref()
+            .and_then(|session_id| sessions.get(notebook_uuid, session_id));
+        match session {
+            Some(ws_conn) => ws_conn.do_send(msg),
+            None => warn!("Could not find ws connection"),
+        }
+
         Ok(())
     }
 }
@@ -129,7 +598,7 @@ impl fmt::Debug for KernelClient {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("KernelClient")
             // .field("socket", &self.socket)
-            .field("ws_mapping", &self.ws_mapping)
+            .field("sessions", &self.sessions)
             .finish()
     }
 }
@@ -143,8 +612,39 @@ pub enum ExecutionType {
 
 #[derive(Debug, Clone)]
 pub enum KernelClientMsg {
-    InitWs(String, Addr<WsClient>),
     MsgToKernel(MsgToKernel),
+    Inspect(InspectRequest),
+    Namespace(String, Origin, Option<mpsc::Sender<KernelNamespace>>),
+    /// json values of names in a private namespace, see `Notebook::call`
+    Values(ValuesRequest, mpsc::Sender<HashMap<String, Value>>),
+    /// forgets a private namespace, see `MsgToKernel::namespace`
+    DropNamespace(String),
+    /// forgets the values of cells in the namespace of a notebook, e.g. of a deleted cell
+    InvalidateCells(String, Vec<String>),
+    /// answered once messages of the kernel reach the kernel client, see `KernelClient::ping`
+    Ping(mpsc::Sender<()>),
+    /// stops the kernel and the kernel client
+    Shutdown,
+}
+
+/// everything sent over the request socket, the kernel dispatches on `kind`
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
+pub enum KernelRequest<'a> {
+    Execute(&'a MsgToKernel),
+    Inspect(&'a InspectRequest),
+    Namespace {
+        notebook_uuid: &'a str,
+    },
+    Values(&'a ValuesRequest),
+    DropNamespace {
+        namespace: &'a str,
+    },
+    InvalidateCells {
+        notebook_uuid: &'a str,
+        cell_uuids: &'a [String],
+    },
+    Ping,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,7 +652,62 @@ pub struct MsgToKernel {
     pub notebook_uuid: String,
     pub cell_uuid: String,
     pub execution_cells: Vec<Cell>,
-    pub locals_of_deps: Vec<HashMap<String, LocalValue>>,
+
+    /// per execution cell: name -> uuid of the cell whose binding is exposed to it
+    pub exposed_names: Vec<HashMap<String, String>>,
+
+    /// cells whose namespace in the kernel is dropped before executing
+    pub invalidate: Vec<String>,
+
+    /// per execution cell: every cell transitively depending on it, in or outside of the plan.
+    /// If the cell fails they are blocked, the ones in the plan do not run
+    #[serde(default)]
+    pub dependents: Vec<Vec<String>>,
+
+    /// echoed by the kernel on every message belonging to this execution
+    pub origin: Origin,
+
+    /// assigned by the kernel client when the plan is sent to the kernel
+    #[serde(default)]
+    pub run_id: u64,
+
+    /// runs the cells in this namespace instead of the one of the notebook, for viewers of a
+    /// published notebook. Its results only go to the session of `origin`
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    /// cells which never ran in `namespace` are taken from the namespace of the notebook, so
+    /// calls only run what their inputs change
+    #[serde(default)]
+    pub reuse_shared: bool,
+}
+
+impl MsgToKernel {
+    /// a later plan of the same notebook running at least the same cells makes this one obsolete
+    pub fn supersedes(&self, older: &MsgToKernel) -> bool {
+        let cells = self
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.as_str())
+            .collect::<HashSet<_>>();
+
+        self.notebook_uuid == older.notebook_uuid
+            && self.namespace == older.namespace
+            && older
+                .execution_cells
+                .iter()
+                .all(|cell| cells.contains(cell.uuid.as_str()))
+    }
+}
+
+/// the websocket session and the request within it a kernel message answers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    #[serde(default)]
+    pub session_id: Option<String>,
+
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -162,23 +717,226 @@ pub struct MsgFromKernel {
     pub locals: HashMap<String, LocalValue>,
     pub error: Option<String>,
     pub ended: bool,
+
+    /// the exception behind `error`
+    #[serde(default)]
+    pub traceback: Option<Traceback>,
+
+    #[serde(default)]
+    pub outputs: Vec<DisplayData>,
+
+    #[serde(default)]
+    pub origin: Origin,
+
+    /// numbers the results of a notebook, assigned by the server when sending them out
+    #[serde(default)]
+    pub seq: Option<u64>,
+
+    /// the execution plan the result belongs to
+    #[serde(default)]
+    pub run_id: u64,
+
+    /// set on lifecycle events of a cell, which carry no results
+    #[serde(default)]
+    pub status: Option<CellStatus>,
+
+    /// milliseconds since the unix epoch, of lifecycle events
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+
+    /// milliseconds the cell ran, of finished and errored events
+    #[serde(default)]
+    pub duration: Option<u64>,
+
+    /// the failed cell a blocked cell depends on
+    #[serde(default)]
+    pub blocked_by: Option<String>,
 }
 
-impl Message for MsgFromKernel {
-    type Result = ();
+/// where a cell of an execution plan is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellStatus {
+    Queued,
+    /// dropped from a superseded plan without a newer plan running it
+    Idle,
+    Running,
+    Finished,
+    Errored,
+    /// not run because a cell it depends on failed
+    Blocked,
 }
 
-#[derive(Debug)]
-pub enum KernelClientErrors {
-    CouldNotParse,
+/// starts the kernel with the ports it binds, stdout is left to the results of headless runs
+fn spawn_kernel(config: &KernelConfig) -> Result<Child, NotebookError> {
+    let (program, args) = config
+        .command
+        .split_first()
+        .ok_or_else(|| KernelErrors::Unavailable(String::from("empty kernel command")))?;
+    let kernel = Command::new(program)
+        .args(args)
+        .current_dir(&config.dir)
+        .env("ZMQ_PORT_PUB", config.pub_port.to_string())
+        .env("ZMQ_PORT_REQ", config.req_port.to_string())
+        .stdout(std::io::stderr())
+        .spawn()
+        .map_err(|e| KernelErrors::Unavailable(e.to_string()))?;
+
+    Ok(kernel)
 }
 
-impl fmt::Display for KernelClientErrors {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            KernelClientErrors::CouldNotParse => write!(f, "Could not parse message"),
+/// starts a new kernel in place of one which exited. Every value is gone with the old kernel, so
+/// the cells which ran are stale again and the sessions of each notebook learn which
+fn respawn(
+    kernel: &mut Child,
+    config: &KernelConfig,
+    notebooks: &OpenNotebooks,
+    sessions: &Arc<Mutex<SessionRegistry>>,
+) -> Result<(), NotebookError> {
+    *kernel = spawn_kernel(config)?;
+
+    let mut notebooks = notebooks.lock()?;
+    for (notebook_uuid, notebook) in notebooks.iter_mut() {
+        if notebook.forget_values() {
+            sessions
+                .lock()?
+                .broadcast_stale(notebook_uuid, notebook.stale_cells());
         }
     }
+
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default()
 }
 
-impl Error for KernelClientErrors {}
+impl MsgFromKernel {
+    /// for viewers of a published notebook, who may see that a cell failed but not its code
+    pub fn redact(&mut self) {
+        if let Some(traceback) = self.traceback.as_mut() {
+            traceback.redact();
+        }
+        if self.error.is_some() {
+            self.error = Some(match &self.traceback {
+                Some(traceback) => traceback.ename.clone(),
+                None => String::from("Error"),
+            });
+        }
+    }
+}
+
+impl Message for MsgFromKernel {
+    type Result = ();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{notebook::Notebook, traceback::Frame};
+
+    fn plan(notebook_uuid: &str, cells: &[&Cell]) -> MsgToKernel {
+        MsgToKernel {
+            notebook_uuid: notebook_uuid.to_string(),
+            cell_uuid: String::new(),
+            execution_cells: cells.iter().map(|cell| (*cell).clone()).collect(),
+            exposed_names: Vec::new(),
+            invalidate: Vec::new(),
+            dependents: Vec::new(),
+            origin: Origin::default(),
+            run_id: 0,
+            namespace: None,
+            reuse_shared: false,
+        }
+    }
+
+    #[test]
+    fn test_supersedes() {
+        let mut scope = HashMap::new();
+        let a = Cell::new_reactive("a = 1", &mut scope).unwrap();
+        let b = Cell::new_reactive("b = a", &mut scope).unwrap();
+
+        let older = plan("nb", &[&a]);
+        assert!(plan("nb", &[&a, &b]).supersedes(&older));
+        assert!(plan("nb", &[&a]).supersedes(&older));
+        assert!(!plan("nb", &[&b]).supersedes(&older));
+        assert!(!plan("other", &[&a, &b]).supersedes(&older));
+
+        // viewers of a published notebook do not interrupt each other
+        let private = MsgToKernel {
+            namespace: Some(String::from("nb:viewer")),
+            ..plan("nb", &[&a, &b])
+        };
+        assert!(!private.supersedes(&older));
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut msg = MsgFromKernel {
+            cell_uuid: String::from("abc"),
+            error: Some(String::from("name 'secret_key' is not defined")),
+            traceback: Some(Traceback {
+                ename: String::from("NameError"),
+                evalue: String::from("name 'secret_key' is not defined"),
+                frames: vec![Frame {
+                    source: Some(String::from("connect(secret_key)")),
+                    ..Frame::default()
+                }],
+                start_line: 1,
+                end_line: 1,
+            }),
+            ..MsgFromKernel::default()
+        };
+        msg.redact();
+        assert_eq!(msg.error.as_deref(), Some("NameError"));
+        assert_eq!(
+            msg.traceback,
+            Some(Traceback {
+                ename: String::from("NameError"),
+                ..Traceback::default()
+            })
+        );
+
+        let mut msg = MsgFromKernel::default();
+        msg.redact();
+        assert_eq!(msg.error, None);
+    }
+
+    #[test]
+    fn test_respawn_marks_cells_stale() {
+        // any long running process stands in for the kernel
+        let config = KernelConfig {
+            command: vec![String::from("sleep"), String::from("30")],
+            dir: PathBuf::from("."),
+            ..KernelConfig::default()
+        };
+        let mut kernel = spawn_kernel(&config).unwrap();
+
+        let (tx, _rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cells = notebook.cells().into_iter().cloned().collect::<Vec<_>>();
+        for cell in cells.iter() {
+            notebook.finished(cell);
+        }
+        assert!(notebook.stale_cells().is_empty());
+        let notebook_uuid = notebook.uuid.clone();
+        let notebooks = Arc::new(Mutex::new(HashMap::from([(
+            notebook_uuid.clone(),
+            notebook,
+        )])));
+        let sessions = Arc::new(Mutex::new(SessionRegistry::new()));
+
+        kernel.kill().unwrap();
+        kernel.wait().unwrap();
+        respawn(&mut kernel, &config, &notebooks, &sessions).unwrap();
+        assert!(kernel.try_wait().unwrap().is_none());
+
+        let notebooks = notebooks.lock().unwrap();
+        assert_eq!(notebooks[&notebook_uuid].stale_cells().len(), cells.len());
+
+        kernel.kill().unwrap();
+        kernel.wait().unwrap();
+    }
+}
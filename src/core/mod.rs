@@ -1,6 +1,15 @@
 pub mod cell;
-mod errors;
+pub mod check;
+pub mod display;
+pub mod errors;
+pub mod format;
+pub mod graph;
+pub mod input;
 pub mod kernel_client;
 pub mod notebook;
+pub mod ot;
+pub mod report;
 mod statement;
 mod topology;
+pub mod traceback;
+pub mod value;
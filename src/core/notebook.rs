@@ -1,12 +1,32 @@
 use super::{
-    cell::{CellType, LocalValue},
-    kernel_client::{KernelClient, KernelClientMsg},
+    check::{self, Problem},
+    errors::{EditErrors, KernelErrors, NotebookError, TopologyErrors},
+    format::{self, Format},
+    graph::DependencyGraph,
+    input::{Input, Parameter},
+    kernel_client::{KernelClientMsg, MsgFromKernel, Origin},
+    ot::TextOperation,
+    report::{CellReport, Report},
+    value::{InspectRequest, KernelNamespace, ValuesRequest, Variable},
+};
+use crate::core::{
+    cell::{Cell, CellMetadata, CellType},
+    kernel_client::MsgToKernel,
+    topology::Topology,
 };
-use crate::core::{cell::Cell, kernel_client::MsgToKernel, topology::Topology};
 use nanoid::nanoid;
+use rustpython_parser::parser;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error, sync::mpsc::Sender};
-use tracing::info;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+    sync::mpsc::Sender,
+};
+use tracing::warn;
+
+/// most edits kept per cell, edits against older revisions are rejected
+const HISTORY_LIMIT: usize = 1000;
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 struct LanguageInfo {
@@ -30,6 +50,14 @@ impl Default for NotebookMetadata {
 
 pub type Scope = HashMap<String, String>;
 
+/// whether cells depending on a cell which ran are run as well, or only marked stale
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutionMode {
+    #[default]
+    Reactive,
+    Lazy,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Notebook {
     pub uuid: String,
@@ -38,11 +66,23 @@ pub struct Notebook {
     topology: Topology,
     title: String,
 
+    #[serde(default)]
+    execution_mode: ExecutionMode,
+
     #[serde(skip)]
     pub scope: Scope,
 
     #[serde(skip)]
     pub kernel_sender: Option<Sender<KernelClientMsg>>,
+
+    /// edits applied to each cell since the notebook was opened, with the revision they produced
+    #[serde(skip)]
+    history: HashMap<String, Vec<(usize, TextOperation)>>,
+
+    /// revision of each cell every editing session is known to have, edits up to the oldest
+    /// of them are not needed to transform later edits anymore
+    #[serde(skip)]
+    acknowledged: HashMap<String, HashMap<String, usize>>,
 }
 
 impl Notebook {
@@ -66,6 +106,13 @@ impl Notebook {
         .unwrap();
         topology.build(&mut scope).unwrap();
 
+        Self {
+            kernel_sender: Some(kernel_sender),
+            ..Self::with_topology("Untitled Notebook", topology, scope)
+        }
+    }
+
+    fn with_topology(title: &str, topology: Topology, scope: Scope) -> Self {
         let uuid = nanoid!(30);
         Self {
             uuid,
@@ -77,69 +124,1681 @@ impl Notebook {
                 file_extension: String::from(".py"),
             },
             topology,
-            title: String::from("Untitled Notebook"),
-            kernel_sender: Some(kernel_sender),
+            title: title.to_string(),
+            execution_mode: ExecutionMode::default(),
+            kernel_sender: None,
+            history: HashMap::new(),
+            acknowledged: HashMap::new(),
+        }
+    }
+
+    /// a notebook of the cells, e.g. of a python script. Cells which do not parse are kept
+    pub fn from_cells(
+        title: &str,
+        cells: Vec<(CellType, String, CellMetadata)>,
+    ) -> Result<Self, NotebookError> {
+        let mut scope = Scope::default();
+        let mut topology = Topology::new();
+        for (index, (cell_type, content, metadata)) in cells.into_iter().enumerate() {
+            let mut cell = Cell::new(cell_type, String::new(), &mut scope)?;
+            cell.content = content;
+            cell.metadata = metadata;
+            topology.insert_cell(index, cell, &mut scope)?;
         }
+
+        let mut notebook = Self::with_topology(title, topology, scope);
+        notebook.analyze()?;
+
+        Ok(notebook)
     }
 
-    pub fn eval_cell(&mut self, cell_uuid: &str, next_content: &str) -> Result<(), Box<dyn Error>> {
+    /// reads the notebook in the format of its extension, see `Format`
+    pub fn load(path: &Path) -> Result<Self, NotebookError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| NotebookError::persistence(path, e))?;
+        match Format::of(path)? {
+            Format::Notebook => {
+                let mut notebook: Notebook = serde_json::from_str(&content)?;
+                notebook.analyze()?;
+                Ok(notebook)
+            }
+            format => {
+                let title = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("Untitled Notebook");
+                Self::from_cells(title, format::parse(format, &content)?)
+            }
+        }
+    }
+
+    /// writes the notebook in the format of the extension
+    pub fn save(&self, path: &Path) -> Result<(), NotebookError> {
+        std::fs::write(path, self.render(Format::of(path)?)?)
+            .map_err(|e| NotebookError::persistence(path, e))?;
+
+        Ok(())
+    }
+
+    /// the notebook in the format, other formats than our own only keep the cells
+    pub fn render(&self, format: Format) -> Result<String, NotebookError> {
+        match format {
+            Format::Notebook => Ok(serde_json::to_string_pretty(self)?),
+            format => format::render(format, &self.title, &self.cells()),
+        }
+    }
+
+    /// sets up the bindings of every cell from scratch, as they are not saved with the notebook
+    fn analyze(&mut self) -> Result<(), NotebookError> {
+        self.scope.clear();
+        for cell_uuid in self.topology.display_order.iter() {
+            let cell = self
+                .topology
+                .cells
+                .get_mut(cell_uuid)
+                .ok_or(TopologyErrors::CellNotFound)?;
+            if matches!(cell.cell_type, CellType::Markdown) {
+                continue;
+            }
+
+            let content = cell.content.clone();
+            cell.bindings.clear();
+            if let Err(e) = cell.update_content(&content, &mut self.scope) {
+                warn!("Could not parse cell {}: {}", cell_uuid, e);
+            }
+        }
+
+        self.topology.build(&mut self.scope)
+    }
+
+    /// problems of the notebook found without a kernel: cells which do not parse or depend on
+    /// themselves, and names no cell defines
+    pub fn check(&self) -> Vec<Problem> {
+        let cyclic = self.topology.cyclic_cells();
+        let mut problems = Vec::new();
+        for cell in self.cells() {
+            if matches!(cell.cell_type, CellType::Markdown) {
+                continue;
+            }
+            if let Err(e) = parser::parse_program(&cell.content, "<input>") {
+                problems.push(Problem::error(&cell.uuid, e.to_string()));
+                continue;
+            }
+            if cyclic.contains(&cell.uuid) {
+                problems.push(Problem::error(
+                    &cell.uuid,
+                    String::from("Cell depends on itself through other cells"),
+                ));
+            }
+
+            let mut undefined = cell
+                .required
+                .iter()
+                .filter(|name| !self.scope.contains_key(*name) && !check::is_builtin(name))
+                .collect::<Vec<_>>();
+            undefined.sort();
+            for name in undefined {
+                problems.push(Problem::warning(
+                    &cell.uuid,
+                    format!("`{}` is not defined in any cell", name),
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// forgets the recorded runs, before running the notebook again
+    pub fn clear_runs(&mut self) {
+        for cell in self.topology.cells.values_mut() {
+            cell.last_run = None;
+        }
+    }
+
+    /// keeps the status or results of a cell with it, see `CellRun`
+    pub fn record(&mut self, msg: &MsgFromKernel) {
+        if let Some(cell) = self.topology.get_cell_mut(&msg.cell_uuid) {
+            cell.last_run
+                .get_or_insert_with(Default::default)
+                .update(msg);
+        }
+    }
+
+    /// the recorded runs of the cells, `started` and `duration` are of the whole run
+    pub fn report(&self, started: u64, duration: u64) -> Report {
+        let cells = self
+            .cells()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, cell)| {
+                cell.last_run.as_ref().map(|run| CellReport {
+                    number: index + 1,
+                    cell_uuid: cell.uuid.clone(),
+                    run: run.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Report {
+            title: self.title.clone(),
+            started,
+            duration,
+            failed: cells.iter().filter(|cell| cell.run.failed()).count(),
+            cells,
+        }
+    }
+
+    /// every cell in display order
+    pub fn cells(&self) -> Vec<&Cell> {
+        self.topology
+            .display_order
+            .iter()
+            .filter_map(|uuid| self.topology.cells.get(uuid))
+            .collect()
+    }
+
+    pub fn eval_cell(
+        &mut self,
+        cell_uuid: &str,
+        next_content: &str,
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        let content_changed = self
+            .topology
+            .cells
+            .get(cell_uuid)
+            .is_some_and(|cell| cell.content != next_content || cell.edited);
+
         // update cell content if it has changed
         self.topology
             .update_cell(cell_uuid, next_content, &mut self.scope)?;
 
-        // get an topological order of the cell uuids and execute them in order
-        let execution_seq = self.topology.execution_seq(cell_uuid)?;
+        // get an topological order of the cell uuids and execute them in order, dependents
+        // only run along if they are reactive
+        let cell_uuids = HashSet::from([cell_uuid.to_string()]);
+        let dependents = self.topology.with_dependents(&cell_uuids);
+        let runs_along = self.reactive_dependents(&cell_uuids);
+        let execution_seq = self
+            .topology
+            .execution_seq(cell_uuid)?
+            .into_iter()
+            .filter(|uuid| {
+                uuid == cell_uuid || runs_along.contains(uuid) || !dependents.contains(uuid)
+            })
+            .collect::<Vec<_>>();
+
+        // the kernel must not keep values of the old content around, even if the plan
+        // stops before the cell is reached
+        let invalidate = if content_changed {
+            vec![cell_uuid.to_string()]
+        } else {
+            Vec::new()
+        };
+
+        self.execute(cell_uuid, &execution_seq, invalidate, origin)
+    }
+
+    /// runs every cell of the notebook in topological order, e.g. after loading it from disk
+    pub fn run_all(&mut self, origin: Origin) -> Result<(), NotebookError> {
+        let invalidate = self.analyze_edited()?;
+        let cells = self.topology.cells.keys().cloned().collect();
+        let execution_seq = self.topology.execution_order(&cells)?;
 
-        let execution_cells = execution_seq
+        self.execute("", &execution_seq, invalidate, origin)
+    }
+
+    /// runs the stale cells and every cell depending on them
+    pub fn run_stale(&mut self, origin: Origin) -> Result<(), NotebookError> {
+        let invalidate = self.analyze_edited()?;
+        let stale = self
+            .topology
+            .cells
+            .values()
+            .filter(|cell| cell.stale)
+            .map(|cell| cell.uuid.clone())
+            .collect();
+        let cells = self.topology.with_dependents(&stale);
+        let execution_seq = self.topology.execution_order(&cells)?;
+
+        self.execute("", &execution_seq, invalidate, origin)
+    }
+
+    /// runs the selected cells and every cell they transitively depend on
+    pub fn run_cells(
+        &mut self,
+        cell_uuids: &[String],
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        let invalidate = self.analyze_edited()?;
+        let selected = cell_uuids.iter().cloned().collect();
+        let cells = self.topology.with_dependencies(&selected);
+        let execution_seq = self.topology.execution_order(&cells)?;
+
+        self.execute("", &execution_seq, invalidate, origin)
+    }
+
+    /// every cell depending on the cell, directly or transitively, in display order
+    fn transitive_dependents(&self, cell_uuid: &str) -> Vec<String> {
+        let dependents = self
+            .topology
+            .with_dependents(&HashSet::from([cell_uuid.to_string()]));
+        self.topology
+            .display_order
             .iter()
-            .map(|uuid| self.topology.cells.get(uuid).unwrap().clone())
+            .filter(|uuid| *uuid != cell_uuid && dependents.contains(*uuid))
+            .cloned()
+            .collect()
+    }
+
+    fn runs_reactively(&self, cell_uuid: &str) -> bool {
+        self.execution_mode == ExecutionMode::Reactive
+            && self.cell(cell_uuid).is_some_and(|cell| cell.is_reactive())
+    }
+
+    /// cells transitively depending on the cells which run along with them, a lazy cell stops
+    /// the cells depending on it from running as well
+    fn reactive_dependents(&self, cell_uuids: &HashSet<String>) -> HashSet<String> {
+        let mut reached = HashSet::new();
+        let mut queue = cell_uuids.iter().cloned().collect::<VecDeque<_>>();
+        while let Some(cell_uuid) = queue.pop_front() {
+            for other in self
+                .topology
+                .dependents
+                .get(&cell_uuid)
+                .into_iter()
+                .flatten()
+            {
+                if self.runs_reactively(other) && reached.insert(other.clone()) {
+                    queue.push_back(other.clone());
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// sets the execution mode of the notebook or, if a cell is given, of that cell
+    pub fn set_execution_mode(
+        &mut self,
+        cell_uuid: Option<&str>,
+        mode: ExecutionMode,
+    ) -> Result<(), NotebookError> {
+        let cell_uuid = match cell_uuid {
+            Some(cell_uuid) => cell_uuid,
+            None => {
+                self.execution_mode = mode;
+                return Ok(());
+            }
+        };
+
+        let cell = self
+            .topology
+            .get_cell_mut(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?;
+        cell.cell_type = match (&cell.cell_type, mode) {
+            (CellType::Markdown, _) => return Err(NotebookError::NotACodeCell),
+            (_, ExecutionMode::Reactive) => CellType::ReactiveCode,
+            (_, ExecutionMode::Lazy) => CellType::NonReactiveCode,
+        };
+
+        Ok(())
+    }
+
+    /// disables or enables the cell, enabled cells did not run while they were disabled
+    pub fn set_disabled(&mut self, cell_uuid: &str, disabled: bool) -> Result<(), NotebookError> {
+        let before = self.topology.disabled_cells();
+        let cell = self
+            .topology
+            .get_cell_mut(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?;
+        cell.metadata.disabled = disabled;
+
+        let after = self.topology.disabled_cells();
+        for uuid in before.difference(&after) {
+            if let Some(cell) = self.topology.get_cell_mut(uuid) {
+                cell.mark_stale();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// uuids of the cells which are disabled or depend on a disabled cell, in display order
+    pub fn disabled_cells(&self) -> Vec<String> {
+        let disabled = self.topology.disabled_cells();
+        self.topology
+            .display_order
+            .iter()
+            .filter(|uuid| disabled.contains(*uuid))
+            .cloned()
+            .collect()
+    }
+
+    /// `cell` as it was sent to the kernel finished in the namespace of the notebook, it is up
+    /// to date unless it became stale again since. Returns whether it is not stale anymore
+    pub fn finished(&mut self, cell: &Cell) -> bool {
+        match self.topology.get_cell_mut(&cell.uuid) {
+            Some(current) if current.stale && current.stale_mark == cell.stale_mark => {
+                current.stale = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// the kernel lost every value, e.g. as it was restarted, so the cells which ran have to
+    /// run again. Returns whether a cell became stale
+    pub fn forget_values(&mut self) -> bool {
+        let mut changed = false;
+        for cell in self.topology.cells.values_mut() {
+            if !cell.stale {
+                cell.mark_stale();
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// uuids of the stale cells, in display order
+    pub fn stale_cells(&self) -> Vec<String> {
+        self.topology
+            .display_order
+            .iter()
+            .filter(|uuid| self.cell(uuid).is_some_and(|cell| cell.stale))
+            .cloned()
+            .collect()
+    }
+
+    /// sets up the bindings of every edited cell again, returns the edited cells
+    fn analyze_edited(&mut self) -> Result<Vec<String>, NotebookError> {
+        let mut edited = self
+            .topology
+            .cells
+            .values()
+            .filter(|cell| cell.edited)
+            .map(|cell| (cell.uuid.clone(), cell.content.clone()))
             .collect::<Vec<_>>();
+        edited.sort();
+
+        for (cell_uuid, content) in edited.iter() {
+            self.topology
+                .update_cell(cell_uuid, content, &mut self.scope)?;
+        }
+
+        Ok(edited.into_iter().map(|(cell_uuid, _)| cell_uuid).collect())
+    }
+
+    /// sends the cells to the kernel, `cell_uuid` is the cell the execution was requested for
+    fn execute(
+        &mut self,
+        cell_uuid: &str,
+        execution_seq: &[String],
+        invalidate: Vec<String>,
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        if self.kernel_sender.is_none() {
+            return Err(KernelErrors::NotConnected.into());
+        }
+
+        // cells depending on the executed ones which do not run along are outdated now
+        let executed = execution_seq.iter().cloned().collect::<HashSet<_>>();
+        for uuid in self.topology.with_dependents(&executed) {
+            if let Some(cell) = self.topology.get_cell_mut(&uuid) {
+                cell.mark_stale();
+            }
+        }
+
+        // they stay stale until they finished, see `finished`
+        let mut execution_cells = Vec::with_capacity(execution_seq.len());
+        for uuid in execution_seq {
+            let cell = self.cell(uuid).ok_or(TopologyErrors::CellNotFound)?;
+            execution_cells.push(cell.clone());
+        }
 
-        let locals_of_deps = execution_cells
+        self.send_plan(cell_uuid, execution_cells, invalidate, None, false, origin)
+    }
+
+    /// `reuse_shared` lets a plan in a private namespace use the cells which ran in the
+    /// namespace of the notebook, see `MsgToKernel::reuse_shared`
+    fn send_plan(
+        &self,
+        cell_uuid: &str,
+        execution_cells: Vec<Cell>,
+        invalidate: Vec<String>,
+        namespace: Option<String>,
+        reuse_shared: bool,
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+
+        let exposed_names = execution_cells
             .iter()
-            .map(|cell| {
-                let dependencies = self.topology.get_dependencies(&cell.uuid);
-                Self::locals_from_dependencies(&cell, &dependencies)
-            })
+            .map(|cell| self.exposed_names(cell))
+            .collect::<Vec<_>>();
+        let dependents = execution_cells
+            .iter()
+            .map(|cell| self.transitive_dependents(&cell.uuid))
             .collect::<Vec<_>>();
 
         let msg = KernelClientMsg::MsgToKernel(MsgToKernel {
             notebook_uuid: self.uuid.clone(),
             cell_uuid: cell_uuid.to_string(),
-            locals_of_deps,
+            exposed_names,
+            invalidate,
+            dependents,
             execution_cells,
+            origin,
+            run_id: 0,
+            namespace,
+            reuse_shared,
         });
-        let kernel_sender = self.kernel_sender.as_ref().unwrap();
         kernel_sender.send(msg)?;
 
         Ok(())
     }
 
-    fn locals_from_dependencies(
-        cell: &Cell,
-        dependencies: &[&Cell],
-    ) -> HashMap<String, LocalValue> {
-        let mut locals = HashMap::new();
-        locals.extend(cell.locals.clone());
+    /// runs cells of a published notebook in the private `namespace` of a viewer, with the values
+    /// the viewer chose for the inputs (cell uuid -> value). Without `changed` every cell runs,
+    /// otherwise the changed input and the cells depending on it. The notebook itself stays as it is
+    pub fn run_published(
+        &self,
+        namespace: &str,
+        inputs: &HashMap<String, Value>,
+        changed: Option<&str>,
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        let cells = match changed {
+            Some(cell_uuid) => {
+                self.input(cell_uuid)?;
+                self.topology
+                    .with_dependents(&HashSet::from([cell_uuid.to_string()]))
+            }
+            None => self.topology.cells.keys().cloned().collect(),
+        };
 
-        for dependency in dependencies.iter() {
-            for (key, value) in dependency.locals.clone().iter() {
-                if cell.required.contains(key) {
-                    locals.insert(key.clone(), value.clone());
-                }
+        let mut scope = self.scope.clone();
+        let mut execution_cells = Vec::new();
+        for uuid in self.topology.execution_order(&cells)? {
+            let mut cell = self
+                .topology
+                .cells
+                .get(&uuid)
+                .cloned()
+                .ok_or(TopologyErrors::CellNotFound)?;
+            if let (Some(input), Some(value)) = (&cell.input, inputs.get(&uuid)) {
+                let content = input.assignment(value)?;
+                cell.update_content(&content, &mut scope)?;
+            }
+            execution_cells.push(cell);
+        }
+
+        let cell_uuid = changed.unwrap_or_default();
+        self.send_plan(
+            cell_uuid,
+            execution_cells,
+            Vec::new(),
+            Some(namespace.to_string()),
+            false,
+            origin,
+        )
+    }
+
+    /// runs the cells the `outputs` (variable names) depend on in the private `namespace`, with
+    /// the parameters overridden by `inputs`. Only cells whose parameters changed, which did not
+    /// run with their current content and the cells depending on them run, the others are taken
+    /// from the namespace of the notebook. The notebook itself stays as it is, the cells binding
+    /// the outputs are returned (name -> cell uuid)
+    pub fn call(
+        &self,
+        namespace: &str,
+        inputs: &HashMap<String, Value>,
+        outputs: &[String],
+        origin: Origin,
+    ) -> Result<HashMap<String, String>, NotebookError> {
+        let mut notebook = self.clone();
+        notebook.set_parameters(inputs)?;
+        notebook.analyze_edited()?;
+
+        let mut bound_by = HashMap::new();
+        for name in outputs {
+            let cell_uuid = notebook.scope.get(name).ok_or_else(|| {
+                NotebookError::InvalidInput(format!("{} is not defined in any cell", name))
+            })?;
+            bound_by.insert(name.clone(), cell_uuid.clone());
+        }
+
+        let needed = notebook
+            .topology
+            .with_dependencies(&bound_by.values().cloned().collect());
+        let outdated = notebook
+            .topology
+            .cells
+            .values()
+            .filter(|cell| cell.stale)
+            .map(|cell| cell.uuid.clone())
+            .collect();
+        let cells = notebook
+            .topology
+            .with_dependents(&outdated)
+            .intersection(&needed)
+            .cloned()
+            .collect();
+
+        let mut execution_cells = Vec::new();
+        for uuid in notebook.topology.execution_order(&cells)? {
+            let cell = notebook
+                .topology
+                .cells
+                .get(&uuid)
+                .cloned()
+                .ok_or(TopologyErrors::CellNotFound)?;
+            execution_cells.push(cell);
+        }
+        notebook.send_plan(
+            "",
+            execution_cells,
+            Vec::new(),
+            Some(namespace.to_string()),
+            true,
+            origin,
+        )?;
+
+        Ok(bound_by)
+    }
+
+    /// asks the kernel for the json values of names (name -> cell uuid) in a private namespace
+    pub fn request_values(
+        &self,
+        namespace: &str,
+        names: HashMap<String, String>,
+        reply: Sender<HashMap<String, Value>>,
+    ) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+        let req = ValuesRequest {
+            notebook_uuid: self.uuid.clone(),
+            namespace: namespace.to_string(),
+            names,
+        };
+        kernel_sender.send(KernelClientMsg::Values(req, reply))?;
+
+        Ok(())
+    }
+
+    /// marks the literal assignments of the cell as parameters or not
+    pub fn mark_parameters(
+        &mut self,
+        cell_uuid: &str,
+        parameters: bool,
+    ) -> Result<(), NotebookError> {
+        let cell = self
+            .topology
+            .get_cell_mut(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?;
+        if matches!(cell.cell_type, CellType::Markdown) {
+            return Err(NotebookError::NotACodeCell);
+        }
+        cell.metadata.parameters = parameters;
+
+        Ok(())
+    }
+
+    /// the parameters of every parameter cell, in display order
+    pub fn parameters(&self) -> Vec<Parameter> {
+        self.cells()
+            .into_iter()
+            .filter(|cell| cell.metadata.parameters)
+            .filter_map(|cell| {
+                let ast = parser::parse_program(&cell.content, "<input>").ok()?;
+                Some(Parameter::all_of(&ast, &cell.uuid))
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// overrides the values of parameters (name -> value), which have to keep their kind. The
+    /// assignments in the parameter cells are rewritten, the edits are returned in display order
+    pub fn set_parameters(
+        &mut self,
+        values: &HashMap<String, Value>,
+    ) -> Result<Vec<(String, usize, TextOperation)>, NotebookError> {
+        let parameters = self.parameters();
+        for name in values.keys() {
+            if !parameters.iter().any(|parameter| &parameter.name == name) {
+                return Err(NotebookError::InvalidInput(format!(
+                    "{} is not a parameter",
+                    name
+                )));
+            }
+        }
+
+        // every value is checked before a cell changes, later assignments are replaced first
+        // so the positions of the earlier ones stay the same
+        let mut contents: HashMap<String, String> = HashMap::new();
+        for parameter in parameters.iter().rev() {
+            let value = match values.get(&parameter.name) {
+                Some(value) => value,
+                None => continue,
+            };
+            let content = match contents.get(&parameter.cell_uuid) {
+                Some(content) => content.clone(),
+                None => self
+                    .cell(&parameter.cell_uuid)
+                    .map(|cell| cell.content.clone())
+                    .ok_or(TopologyErrors::CellNotFound)?,
+            };
+            contents.insert(
+                parameter.cell_uuid.clone(),
+                parameter.replace(&content, value)?,
+            );
+        }
+
+        let mut edits = Vec::new();
+        for cell_uuid in self.topology.display_order.clone() {
+            let content = match contents.get(&cell_uuid) {
+                Some(content) => content,
+                None => continue,
+            };
+            if let Some((revision, operation)) = self.replace_content(&cell_uuid, content)? {
+                edits.push((cell_uuid, revision, operation));
+            }
+        }
+
+        Ok(edits)
+    }
+
+    /// runs the cells and the cells depending on them which run reactively, e.g. after their
+    /// parameters changed
+    pub fn run_changed(
+        &mut self,
+        cell_uuids: &[String],
+        origin: Origin,
+    ) -> Result<(), NotebookError> {
+        let invalidate = self.analyze_edited()?;
+        let mut cells = cell_uuids.iter().cloned().collect::<HashSet<_>>();
+        cells.extend(self.reactive_dependents(&cells));
+        let execution_seq = self.topology.execution_order(&cells)?;
+
+        self.execute("", &execution_seq, invalidate, origin)
+    }
+
+    /// the input of a cell, see `Input`
+    pub fn input(&self, cell_uuid: &str) -> Result<&Input, NotebookError> {
+        self.cell(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?
+            .input
+            .as_ref()
+            .ok_or_else(|| NotebookError::InvalidInput(String::from("Cell is not an input")))
+    }
+
+    /// forgets the private namespace of a viewer who left
+    pub fn drop_namespace(&self, namespace: &str) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+        kernel_sender.send(KernelClientMsg::DropNamespace(namespace.to_string()))?;
+
+        Ok(())
+    }
+
+    /// the notebook as viewers of a published notebook see it, only the code of inputs is left
+    pub fn published(&self) -> Notebook {
+        let mut notebook = self.clone();
+        notebook.history.clear();
+        for cell in notebook.topology.cells.values_mut() {
+            if cell.input.is_none() {
+                cell.content.clear();
+                cell.statements.clear();
             }
         }
-        locals
+
+        notebook
     }
 
-    pub fn reorder_cells(&mut self, cell_uuids: &[String]) {
-        self.topology.reorder_cells(cell_uuids);
+    pub fn inspect(&self, req: InspectRequest) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+        kernel_sender.send(KernelClientMsg::Inspect(req))?;
+
+        Ok(())
+    }
+
+    /// requests the namespace of the notebook from the kernel, the kernel client sends it to
+    /// `reply` or, if there is none, to the websocket session of `origin`
+    pub fn request_namespace(
+        &self,
+        origin: Origin,
+        reply: Option<Sender<KernelNamespace>>,
+    ) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+        kernel_sender.send(KernelClientMsg::Namespace(self.uuid.clone(), origin, reply))?;
+
+        Ok(())
+    }
+
+    /// every global binding of the notebook, with its defining cell from the scope, the
+    /// summary of its value in the kernel and the cells depending on it
+    pub fn variables(&self, namespace: &KernelNamespace) -> Vec<Variable> {
+        let mut bindings = self.scope.clone();
+        // bindings the static analysis does not know about, e.g. loop variables
+        for (cell_uuid, locals) in namespace.cells.iter() {
+            for name in locals.keys() {
+                bindings
+                    .entry(name.clone())
+                    .or_insert_with(|| cell_uuid.clone());
+            }
+        }
+
+        let mut variables = bindings
+            .into_iter()
+            .map(|(name, cell_uuid)| {
+                let local = namespace
+                    .cells
+                    .get(&cell_uuid)
+                    .and_then(|locals| locals.get(&name));
+
+                let mut dependents = self
+                    .topology
+                    .get_dependents(&cell_uuid)
+                    .into_iter()
+                    .filter(|cell| cell.required.contains(&name))
+                    .map(|cell| cell.uuid.clone())
+                    .collect::<Vec<_>>();
+                dependents.sort();
+
+                Variable {
+                    handle: local.map(|local| local.handle.clone()),
+                    summary: local.map(|local| local.summary.clone()),
+                    name,
+                    cell_uuid,
+                    dependents,
+                }
+            })
+            .collect::<Vec<_>>();
+        variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+        variables
+    }
+
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::new(&self.topology, &self.scope)
+    }
+
+    /// names the cell requires from other cells, mapped to the uuid of the defining cell.
+    /// The kernel exposes exactly these values of the other cells namespaces to the cell
+    fn exposed_names(&self, cell: &Cell) -> HashMap<String, String> {
+        cell.required
+            .iter()
+            .filter_map(|name| match self.scope.get(name) {
+                Some(uuid) if uuid != &cell.uuid => Some((name.clone(), uuid.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn reorder_cells(&mut self, cell_uuids: &[String]) -> Result<(), NotebookError> {
+        self.topology.reorder_cells(cell_uuids)
+    }
+
+    pub fn cell(&self, cell_uuid: &str) -> Option<&Cell> {
+        self.topology.cells.get(cell_uuid)
+    }
+
+    /// applies an edit made against `revision` of the cell content. Edits applied in the
+    /// meantime are transformed in, the transformed edit and the new revision are returned
+    pub fn edit_cell(
+        &mut self,
+        cell_uuid: &str,
+        revision: usize,
+        mut operation: TextOperation,
+    ) -> Result<(usize, TextOperation), NotebookError> {
+        let cell = self
+            .topology
+            .get_cell_mut(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?;
+        let history = self.history.entry(cell_uuid.to_string()).or_default();
+
+        let missed = history
+            .iter()
+            .filter(|(applied, _)| *applied > revision)
+            .collect::<Vec<_>>();
+        if revision > cell.revision || missed.len() != cell.revision - revision {
+            return Err(EditErrors::UnknownRevision.into());
+        }
+        for (_, applied) in missed {
+            let (_, transformed) = TextOperation::transform(applied, &operation)?;
+            operation = transformed;
+        }
+
+        cell.content = operation.apply(&cell.content)?;
+        cell.edited = true;
+        cell.mark_stale();
+        cell.revision += 1;
+        let revision = cell.revision;
+        history.push((revision, operation.clone()));
+        self.trim_history(cell_uuid);
+
+        Ok((revision, operation))
+    }
+
+    /// the editing session has the current revision of every cell
+    pub fn join(&mut self, session_id: &str) {
+        let revisions = self
+            .topology
+            .cells
+            .values()
+            .map(|cell| (cell.uuid.clone(), cell.revision))
+            .collect();
+        self.acknowledged.insert(session_id.to_string(), revisions);
+    }
+
+    /// without editing sessions there is nothing left to transform
+    pub fn leave(&mut self, session_id: &str) {
+        if self.acknowledged.remove(session_id).is_none() {
+            return;
+        }
+        if self.acknowledged.is_empty() {
+            self.history.clear();
+            return;
+        }
+        let cell_uuids = self.history.keys().cloned().collect::<Vec<_>>();
+        for cell_uuid in cell_uuids {
+            self.trim_history(&cell_uuid);
+        }
+    }
+
+    /// a new connection resumed the session `resumed_id`, which left when its connection
+    /// dropped, the connection joined as `session_id` before
+    pub fn rejoin(&mut self, session_id: &str, resumed_id: &str) {
+        self.leave(session_id);
+        self.join(resumed_id);
+    }
+
+    /// the session sent an edit against `revision`, so it has seen every edit up to it
+    pub fn acknowledge(&mut self, session_id: &str, cell_uuid: &str, revision: usize) {
+        if let Some(revisions) = self.acknowledged.get_mut(session_id) {
+            let known = revisions.entry(cell_uuid.to_string()).or_default();
+            *known = (*known).max(revision);
+        }
+    }
+
+    /// drops the edits every editing session has seen, and the oldest ones beyond the limit
+    fn trim_history(&mut self, cell_uuid: &str) {
+        let oldest = self
+            .acknowledged
+            .values()
+            .map(|revisions| revisions.get(cell_uuid).copied().unwrap_or_default())
+            .min();
+        let history = match self.history.get_mut(cell_uuid) {
+            Some(history) => history,
+            None => return,
+        };
+        if let Some(oldest) = oldest {
+            history.retain(|(applied, _)| *applied > oldest);
+        }
+        let excess = history.len().saturating_sub(HISTORY_LIMIT);
+        history.drain(..excess);
+    }
+
+    /// evaluates the current content of the cell
+    pub fn run_cell(&mut self, cell_uuid: &str, origin: Origin) -> Result<(), NotebookError> {
+        let content = self
+            .cell(cell_uuid)
+            .map(|cell| cell.content.clone())
+            .ok_or(TopologyErrors::CellNotFound)?;
+
+        self.eval_cell(cell_uuid, &content, origin)
+    }
+
+    /// replaces the whole content of a cell, returns the edit if the content changed
+    pub fn replace_content(
+        &mut self,
+        cell_uuid: &str,
+        content: &str,
+    ) -> Result<Option<(usize, TextOperation)>, NotebookError> {
+        let cell = self.cell(cell_uuid).ok_or(TopologyErrors::CellNotFound)?;
+        if cell.content == content {
+            return Ok(None);
+        }
+
+        let operation = TextOperation::replace(&cell.content, content);
+        let revision = cell.revision;
+        self.edit_cell(cell_uuid, revision, operation).map(Some)
+    }
+
+    pub fn insert_cell(
+        &mut self,
+        index: usize,
+        content: &str,
+    ) -> Result<(usize, Cell), NotebookError> {
+        let cell = Cell::new_reactive(content, &mut self.scope)?;
+        let index = self
+            .topology
+            .insert_cell(index, cell.clone(), &mut self.scope)?;
+
+        Ok((index, cell))
+    }
+
+    /// removes the cell and drops its namespace in the kernel
+    pub fn delete_cell(&mut self, cell_uuid: &str) -> Result<(), NotebookError> {
+        let kernel_sender = self
+            .kernel_sender
+            .as_ref()
+            .ok_or(KernelErrors::NotConnected)?;
+
+        // cells using its bindings have to run again
+        let dependents = self
+            .topology
+            .get_dependents(cell_uuid)
+            .into_iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        self.topology.remove_cell(cell_uuid, &mut self.scope)?;
+        self.history.remove(cell_uuid);
+        for uuid in dependents {
+            if let Some(cell) = self.topology.get_cell_mut(&uuid) {
+                cell.mark_stale();
+            }
+        }
+
+        // not a plan, a running one goes on
+        kernel_sender.send(KernelClientMsg::InvalidateCells(
+            self.uuid.clone(),
+            vec![cell_uuid.to_string()],
+        ))?;
+
+        Ok(())
     }
 }
 
-impl From<&str> for Notebook {
-    fn from(path: &str) -> Self {
-        let json = std::fs::read_to_string(path).unwrap();
-        serde_json::from_str(&json).unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        cell::LocalValue,
+        check::Severity,
+        kernel_client::{CellStatus, ExecutionType},
+        value::ValueSummary,
+    };
+    use serde_json::json;
+    use std::sync::mpsc;
+
+    fn cell_uuid_by_content(notebook: &Notebook, content: &str) -> String {
+        notebook
+            .topology
+            .cells
+            .values()
+            .find(|cell| cell.content == content)
+            .map(|cell| cell.uuid.clone())
+            .unwrap()
+    }
+
+    fn received_msg(rx: &mpsc::Receiver<KernelClientMsg>) -> MsgToKernel {
+        match rx.try_recv().unwrap() {
+            KernelClientMsg::MsgToKernel(msg) => msg,
+            msg => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    /// what the kernel client does when every cell of the plan finished
+    fn finish(notebook: &mut Notebook, msg: &MsgToKernel) {
+        for cell in msg.execution_cells.iter() {
+            notebook.finished(cell);
+        }
+    }
+
+    #[test]
+    fn test_eval_cell_exposes_required_names() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let add_def_uuid = cell_uuid_by_content(&notebook, "def add(a, b):\n  return a + b");
+        let add_call_uuid = cell_uuid_by_content(&notebook, "add(5, 2)");
+
+        notebook
+            .eval_cell(&add_call_uuid, "add(5, 2)", Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+
+        assert!(msg.invalidate.is_empty());
+        let position = msg
+            .execution_cells
+            .iter()
+            .position(|cell| cell.uuid == add_call_uuid)
+            .unwrap();
+        let expected = vec![(String::from("add"), add_def_uuid)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(msg.exposed_names[position], expected);
+    }
+
+    #[test]
+    fn test_variables() {
+        let (tx, _rx) = mpsc::channel();
+        let notebook = Notebook::new(tx);
+        let add_def_uuid = cell_uuid_by_content(&notebook, "def add(a, b):\n  return a + b");
+        let add_call_uuid = cell_uuid_by_content(&notebook, "add(5, 2)");
+        let loop_uuid = cell_uuid_by_content(&notebook, "sum = 0\nfor i in range(10):\n  sum += 1");
+
+        let local = |repr: &str| LocalValue {
+            handle: format!("handle-{}", repr),
+            summary: ValueSummary {
+                type_name: String::from("int"),
+                repr: repr.to_string(),
+                ..Default::default()
+            },
+            local_type: ExecutionType::Exec,
+        };
+        let mut namespace = KernelNamespace {
+            notebook_uuid: notebook.uuid.clone(),
+            ..Default::default()
+        };
+        namespace.cells.insert(
+            loop_uuid.clone(),
+            vec![
+                (String::from("sum"), local("10")),
+                (String::from("i"), local("9")),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let variables = notebook.variables(&namespace);
+
+        let add = variables.iter().find(|v| v.name == "add").unwrap();
+        assert_eq!(add.cell_uuid, add_def_uuid);
+        assert_eq!(add.dependents, vec![add_call_uuid]);
+        assert_eq!(add.summary, None);
+
+        // loop variables are not in the scope, but bound in the kernel
+        let i = variables.iter().find(|v| v.name == "i").unwrap();
+        assert_eq!(i.cell_uuid, loop_uuid);
+        assert_eq!(i.handle, Some(String::from("handle-9")));
+
+        let sum = variables.iter().find(|v| v.name == "sum").unwrap();
+        assert_eq!(sum.summary.as_ref().unwrap().repr, "10");
+        assert!(sum.dependents.is_empty());
+    }
+
+    #[test]
+    fn test_eval_cell_invalidates_changed_content() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cell_uuid = cell_uuid_by_content(&notebook, "print(123)");
+
+        notebook
+            .eval_cell(&cell_uuid, "print(1234)", Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+
+        assert_eq!(msg.invalidate, vec![cell_uuid]);
+        assert!(msg.exposed_names.iter().all(|names| names.is_empty()));
+    }
+
+    #[test]
+    fn test_history_trimmed() {
+        let (tx, _rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cell_uuid = cell_uuid_by_content(&notebook, "print(123)");
+        notebook.join("first");
+        notebook.join("second");
+
+        // content of every revision, edits append a space to the revision they are made on
+        let mut contents = vec![String::from("print(123)")];
+        let mut edit = |notebook: &mut Notebook, session_id: &str, revision: usize| {
+            let content = &contents[revision];
+            let operation = TextOperation::replace(content, &format!("{} ", content));
+            notebook.acknowledge(session_id, &cell_uuid, revision);
+            let res = notebook.edit_cell(&cell_uuid, revision, operation);
+            contents.push(notebook.cell(&cell_uuid).unwrap().content.clone());
+            res
+        };
+        edit(&mut notebook, "first", 0).unwrap();
+        edit(&mut notebook, "first", 1).unwrap();
+        // the second session has not seen any edit yet
+        assert_eq!(notebook.history[&cell_uuid].len(), 2);
+
+        edit(&mut notebook, "second", 1).unwrap();
+        assert_eq!(notebook.history[&cell_uuid].len(), 2);
+        // the edits up to revision 1 are seen by both sessions
+        assert!(edit(&mut notebook, "first", 0).is_err());
+
+        notebook.leave("first");
+        assert_eq!(notebook.history[&cell_uuid].len(), 2);
+        notebook.leave("second");
+        assert!(notebook.history.is_empty());
+    }
+
+    #[test]
+    fn test_history_after_resume() {
+        let (tx, _rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cell_uuid = cell_uuid_by_content(&notebook, "print(123)");
+
+        // the connection of "old" dropped, a new one joined and resumed it
+        notebook.join("old");
+        notebook.leave("old");
+        notebook.join("fresh");
+        notebook.rejoin("fresh", "old");
+        assert!(!notebook.acknowledged.contains_key("fresh"));
+
+        let operation = TextOperation::replace("print(123)", "print(1234)");
+        notebook.acknowledge("old", &cell_uuid, 0);
+        notebook.edit_cell(&cell_uuid, 0, operation).unwrap();
+        assert_eq!(notebook.history[&cell_uuid].len(), 1);
+
+        notebook.leave("old");
+        assert!(notebook.history.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_edits() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cell_uuid = cell_uuid_by_content(&notebook, "print(123)");
+
+        // two sessions edit revision 0 at the same time
+        let first = TextOperation::replace("print(123)", "print(123, a)");
+        let second = TextOperation::replace("print(123)", "print(b, 123)");
+        let (revision, _) = notebook.edit_cell(&cell_uuid, 0, first).unwrap();
+        assert_eq!(revision, 1);
+        let (revision, transformed) = notebook.edit_cell(&cell_uuid, 0, second).unwrap();
+        assert_eq!(revision, 2);
+        assert_eq!(
+            transformed.apply("print(123, a)").unwrap(),
+            "print(b, 123, a)"
+        );
+        assert_eq!(
+            notebook.cell(&cell_uuid).unwrap().content,
+            "print(b, 123, a)"
+        );
+
+        assert!(notebook
+            .edit_cell(&cell_uuid, 3, TextOperation::new())
+            .is_err());
+
+        // the edited content is analyzed and its old values dropped when it is run
+        notebook
+            .eval_cell(&cell_uuid, "print(b, 123, a)", Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let position = msg
+            .execution_cells
+            .iter()
+            .position(|cell| cell.uuid == cell_uuid)
+            .unwrap();
+        assert!(msg.exposed_names[position].contains_key("a"));
+        assert!(msg.exposed_names[position].contains_key("b"));
+        assert_eq!(msg.invalidate, vec![cell_uuid]);
+    }
+
+    #[test]
+    fn test_insert_and_delete_cell() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+
+        let (index, cell) = notebook.insert_cell(0, "d = a * 2").unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(notebook.topology.display_order[0], cell.uuid);
+        assert_eq!(notebook.scope.get("d"), Some(&cell.uuid));
+        assert_eq!(notebook.topology.get_dependencies(&cell.uuid).len(), 1);
+
+        notebook.delete_cell(&cell.uuid).unwrap();
+        assert!(notebook.cell(&cell.uuid).is_none());
+        assert!(!notebook.topology.display_order.contains(&cell.uuid));
+        assert_eq!(notebook.scope.get("d"), None);
+
+        match rx.try_recv().unwrap() {
+            KernelClientMsg::InvalidateCells(notebook_uuid, cell_uuids) => {
+                assert_eq!(notebook_uuid, notebook.uuid);
+                assert_eq!(cell_uuids, vec![cell.uuid]);
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_run_cells_with_dependencies() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let abc_uuid = cell_uuid_by_content(&notebook, "a = 1 + 2\nb = 5\nc = 12");
+        let (_, e) = notebook.insert_cell(0, "e = d + 1").unwrap();
+        let (_, d) = notebook.insert_cell(0, "d = a * 2").unwrap();
+
+        notebook
+            .run_cells(std::slice::from_ref(&e.uuid), Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let order = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![abc_uuid, d.uuid, e.uuid]);
+
+        assert!(notebook
+            .run_cells(&[String::from("unknown")], Origin::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_parameters() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let abc_uuid = cell_uuid_by_content(&notebook, "a = 1 + 2\nb = 5\nc = 12");
+        let (_, d) = notebook.insert_cell(1, "d = b * 2").unwrap();
+        assert!(notebook.parameters().is_empty());
+
+        notebook.mark_parameters(&abc_uuid, true).unwrap();
+        let names = notebook
+            .parameters()
+            .into_iter()
+            .map(|parameter| parameter.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["b", "c"]);
+
+        let values = HashMap::from([(String::from("b"), json!(7)), (String::from("a"), json!(1))]);
+        assert!(notebook.set_parameters(&values).is_err());
+        let values = HashMap::from([
+            (String::from("b"), json!(7)),
+            (String::from("c"), json!("x")),
+        ]);
+        assert!(notebook.set_parameters(&values).is_err());
+        assert_eq!(
+            notebook.cell(&abc_uuid).unwrap().content,
+            "a = 1 + 2\nb = 5\nc = 12"
+        );
+
+        let values = HashMap::from([(String::from("b"), json!(7))]);
+        let edits = notebook.set_parameters(&values).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            notebook.cell(&abc_uuid).unwrap().content,
+            "a = 1 + 2\nb = 7\nc = 12"
+        );
+        assert!(notebook.set_parameters(&values).unwrap().is_empty());
+
+        notebook
+            .run_changed(std::slice::from_ref(&abc_uuid), Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let order = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![abc_uuid.clone(), d.uuid]);
+        assert_eq!(msg.invalidate, vec![abc_uuid]);
+    }
+
+    #[test]
+    fn test_call() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let (_, n) = notebook.insert_cell(1, "n = 2").unwrap();
+        let (_, m) = notebook.insert_cell(2, "m = n * 10").unwrap();
+        notebook.insert_cell(3, "k = 5").unwrap();
+        let (_, out) = notebook.insert_cell(4, "out = k + m").unwrap();
+        notebook.mark_parameters(&n.uuid, true).unwrap();
+        notebook.run_all(Origin::default()).unwrap();
+        finish(&mut notebook, &received_msg(&rx));
+
+        let inputs = HashMap::from([(String::from("n"), json!(3))]);
+        let outputs = vec![String::from("out")];
+        let bound_by = notebook
+            .call("nb/call", &inputs, &outputs, Origin::default())
+            .unwrap();
+        assert_eq!(bound_by["out"], out.uuid);
+
+        // k ran in the notebook already and is taken from there
+        let msg = received_msg(&rx);
+        let order = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![n.uuid.clone(), m.uuid, out.uuid]);
+        assert_eq!(msg.execution_cells[0].content, "n = 3");
+        assert_eq!(msg.namespace.as_deref(), Some("nb/call"));
+        assert!(msg.reuse_shared);
+        assert_eq!(notebook.cell(&n.uuid).unwrap().content, "n = 2");
+
+        let outputs = vec![String::from("missing")];
+        assert!(notebook
+            .call("nb/call", &inputs, &outputs, Origin::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_plan_blocks_transitive_dependents() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let abc_content = "a = 1 + 2\nb = 5\nc = 12";
+        let abc_uuid = cell_uuid_by_content(&notebook, abc_content);
+        let (_, e) = notebook.insert_cell(0, "e = d + 1").unwrap();
+        let (_, d) = notebook.insert_cell(0, "d = a * 2").unwrap();
+
+        notebook
+            .eval_cell(&abc_uuid, abc_content, Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let position = |uuid: &str| {
+            msg.execution_cells
+                .iter()
+                .position(|cell| cell.uuid == uuid)
+                .unwrap()
+        };
+
+        // e runs along after d, and is blocked as well if the cell fails
+        assert!(position(&abc_uuid) < position(&d.uuid));
+        assert!(position(&d.uuid) < position(&e.uuid));
+        assert_eq!(
+            msg.dependents[position(&abc_uuid)],
+            vec![d.uuid.clone(), e.uuid.clone()]
+        );
+        assert_eq!(msg.dependents[position(&d.uuid)], vec![e.uuid]);
+    }
+
+    #[test]
+    fn test_eval_cell_runs_transitive_dependents() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let (_, a) = notebook.insert_cell(0, "x = 1").unwrap();
+        let (_, b) = notebook.insert_cell(1, "y = x + 1").unwrap();
+        let (_, c) = notebook.insert_cell(2, "z = y + 1").unwrap();
+        let order = |msg: MsgToKernel| {
+            msg.execution_cells
+                .into_iter()
+                .map(|cell| cell.uuid)
+                .collect::<Vec<_>>()
+        };
+
+        notebook
+            .eval_cell(&a.uuid, "x = 2", Origin::default())
+            .unwrap();
+        assert_eq!(
+            order(received_msg(&rx)),
+            vec![a.uuid.clone(), b.uuid.clone(), c.uuid.clone()]
+        );
+
+        // a lazy cell in between stops the ones behind it
+        notebook
+            .set_execution_mode(Some(&b.uuid), ExecutionMode::Lazy)
+            .unwrap();
+        notebook
+            .eval_cell(&a.uuid, "x = 3", Origin::default())
+            .unwrap();
+        assert_eq!(order(received_msg(&rx)), vec![a.uuid]);
+        let stale = notebook.stale_cells();
+        assert!(stale.contains(&b.uuid) && stale.contains(&c.uuid));
+    }
+
+    #[test]
+    fn test_eval_cell_skips_dependencies_which_ran() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let (_, a) = notebook.insert_cell(0, "x = 1").unwrap();
+        let (_, b) = notebook.insert_cell(1, "y = x + 1").unwrap();
+        let (_, c) = notebook.insert_cell(2, "z = x * 2").unwrap();
+        notebook.run_all(Origin::default()).unwrap();
+        finish(&mut notebook, &received_msg(&rx));
+
+        // x keeps its value in the kernel, so neither its cell nor its other dependent run
+        notebook
+            .eval_cell(&b.uuid, "y = x + 2", Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let order = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![b.uuid.clone()]);
+        let stale = notebook.stale_cells();
+        assert!(!stale.contains(&a.uuid) && !stale.contains(&c.uuid));
+    }
+
+    #[test]
+    fn test_run_published_with_inputs() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let (_, n) = notebook.insert_cell(0, "n = 3").unwrap();
+        let (_, m) = notebook.insert_cell(1, "m = n * 2").unwrap();
+
+        let inputs = HashMap::from([(n.uuid.clone(), serde_json::json!(5))]);
+        notebook
+            .run_published("nb/viewer", &inputs, Some(&n.uuid), Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        assert_eq!(msg.namespace.as_deref(), Some("nb/viewer"));
+        let cells = msg
+            .execution_cells
+            .iter()
+            .map(|cell| (cell.uuid.clone(), cell.content.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            vec![(n.uuid.clone(), String::from("n = 5")), (m.uuid, m.content)]
+        );
+
+        // the notebook is not touched, and only inputs can be set
+        assert_eq!(notebook.cell(&n.uuid).unwrap().content, "n = 3");
+        assert!(notebook
+            .run_published("nb/viewer", &inputs, Some(&cells[1].0), Origin::default())
+            .is_err());
+        assert!(notebook
+            .published()
+            .cell(&cells[1].0)
+            .unwrap()
+            .content
+            .is_empty());
+    }
+
+    #[test]
+    fn test_run_stale() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let abc_uuid = cell_uuid_by_content(&notebook, "a = 1 + 2\nb = 5\nc = 12");
+        let (_, d) = notebook.insert_cell(0, "d = a * 2").unwrap();
+
+        // nothing ran yet
+        notebook.run_stale(Origin::default()).unwrap();
+        let msg = received_msg(&rx);
+        assert_eq!(msg.execution_cells.len(), notebook.topology.cells.len());
+        // cells are stale until they finished, failed or interrupted ones stay stale
+        assert!(notebook.topology.cells.values().all(|cell| cell.stale));
+        finish(&mut notebook, &msg);
+        assert!(notebook.topology.cells.values().all(|cell| !cell.stale));
+
+        // the edited cell is analyzed again and runs with its dependents
+        let revision = notebook.cell(&abc_uuid).unwrap().revision;
+        let operation = TextOperation::replace("a = 1 + 2\nb = 5\nc = 12", "a = 4");
+        notebook.edit_cell(&abc_uuid, revision, operation).unwrap();
+        notebook.run_stale(Origin::default()).unwrap();
+        let msg = received_msg(&rx);
+        let order = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![abc_uuid.clone(), d.uuid.clone()]);
+        assert_eq!(msg.invalidate, vec![abc_uuid.clone()]);
+        assert_eq!(notebook.scope.get("b"), None);
+
+        // edited while it ran, the result is outdated
+        let revision = notebook.cell(&abc_uuid).unwrap().revision;
+        let operation = TextOperation::replace("a = 4", "a = 5");
+        notebook.edit_cell(&abc_uuid, revision, operation).unwrap();
+        finish(&mut notebook, &msg);
+        assert_eq!(notebook.stale_cells(), vec![abc_uuid]);
+    }
+
+    #[test]
+    fn test_lazy_dependents_become_stale() {
+        let (tx, rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let abc_content = "a = 1 + 2\nb = 5\nc = 12";
+        let abc_uuid = cell_uuid_by_content(&notebook, abc_content);
+        let (_, d) = notebook.insert_cell(0, "d = a * 2").unwrap();
+        let (_, e) = notebook.insert_cell(0, "e = b * 2").unwrap();
+        notebook.run_all(Origin::default()).unwrap();
+        finish(&mut notebook, &received_msg(&rx));
+
+        // a lazy cell does not run along, but becomes stale
+        notebook
+            .set_execution_mode(Some(&d.uuid), ExecutionMode::Lazy)
+            .unwrap();
+        notebook
+            .eval_cell(&abc_uuid, abc_content, Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        let executed = msg
+            .execution_cells
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(executed, vec![abc_uuid.clone(), e.uuid.clone()]);
+        finish(&mut notebook, &msg);
+        assert_eq!(notebook.stale_cells(), vec![d.uuid.clone()]);
+
+        // in a lazy notebook no dependent runs along
+        notebook
+            .set_execution_mode(None, ExecutionMode::Lazy)
+            .unwrap();
+        notebook
+            .eval_cell(&abc_uuid, abc_content, Origin::default())
+            .unwrap();
+        let msg = received_msg(&rx);
+        assert_eq!(msg.execution_cells.len(), 1);
+        finish(&mut notebook, &msg);
+        assert_eq!(notebook.stale_cells(), vec![e.uuid, d.uuid]);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let (tx, _rx) = mpsc::channel();
+        let notebook = Notebook::new(tx);
+        let contents = |notebook: &Notebook| {
+            notebook
+                .cells()
+                .iter()
+                .map(|cell| cell.content.clone())
+                .collect::<Vec<_>>()
+        };
+        let dir = std::env::temp_dir().join(nanoid!(10));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for file in ["notebook.json", "notebook.py", "notebook.ipynb"] {
+            let path = dir.join(file);
+            notebook.save(&path).unwrap();
+            let loaded = Notebook::load(&path).unwrap();
+
+            assert_eq!(contents(&loaded), contents(&notebook));
+            let add_call_uuid = cell_uuid_by_content(&loaded, "add(5, 2)");
+            assert_eq!(loaded.topology.get_dependencies(&add_call_uuid).len(), 1);
+            assert!(loaded.kernel_sender.is_none());
+        }
+        assert_eq!(
+            Notebook::load(&dir.join("notebook.json")).unwrap().uuid,
+            notebook.uuid
+        );
+        assert!(notebook.save(&dir.join("notebook.txt")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_runs() {
+        let (tx, _rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+        let cell_uuid = cell_uuid_by_content(&notebook, "print(123)");
+        let status = |status| MsgFromKernel {
+            cell_uuid: cell_uuid.clone(),
+            status: Some(status),
+            duration: Some(3),
+            ..Default::default()
+        };
+
+        notebook.record(&status(CellStatus::Queued));
+        notebook.record(&status(CellStatus::Errored));
+        let report = notebook.report(0, 10);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.cells.len(), 1);
+        assert_eq!(report.cells[0].number, 5);
+        assert_eq!(report.cells[0].run.duration, Some(3));
+
+        // runs are saved with the notebook
+        let path = std::env::temp_dir().join(format!("{}.json", nanoid!(10)));
+        notebook.save(&path).unwrap();
+        let mut loaded = Notebook::load(&path).unwrap();
+        assert!(loaded.cell(&cell_uuid).unwrap().last_run.is_some());
+        loaded.clear_runs();
+        assert!(loaded.report(0, 0).cells.is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let cells = ["a = b + 1", "b = a", "print(missing)", "c = (", "# Title"];
+        let cells = cells
+            .iter()
+            .enumerate()
+            .map(|(index, content)| match index {
+                4 => (CellType::Markdown, content.to_string(), Default::default()),
+                _ => (
+                    CellType::ReactiveCode,
+                    content.to_string(),
+                    Default::default(),
+                ),
+            })
+            .collect();
+        let notebook = Notebook::from_cells("Problems", cells).unwrap();
+        let uuids = notebook
+            .cells()
+            .iter()
+            .map(|cell| cell.uuid.clone())
+            .collect::<Vec<_>>();
+
+        let problems = notebook
+            .check()
+            .into_iter()
+            .map(|problem| (problem.severity, problem.cell_uuid))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                (Severity::Error, uuids[0].clone()),
+                (Severity::Error, uuids[1].clone()),
+                (Severity::Warning, uuids[2].clone()),
+                (Severity::Error, uuids[3].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reorder_cells_rejects_unknown_cells() {
+        let (tx, _rx) = mpsc::channel();
+        let mut notebook = Notebook::new(tx);
+
+        let mut order = notebook.topology.display_order.clone();
+        order.reverse();
+        assert!(notebook.reorder_cells(&order).is_ok());
+        assert_eq!(notebook.topology.display_order, order);
+
+        order.pop();
+        assert!(notebook.reorder_cells(&order).is_err());
+        order.push(String::from("unknown"));
+        assert!(notebook.reorder_cells(&order).is_err());
     }
 }
@@ -0,0 +1,311 @@
+use super::errors::EditErrors;
+use serde::{Deserialize, Serialize};
+
+/// one component of an edit, lengths are counted in characters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+/// an edit of a cells content which walks over the whole old content,
+/// e.g. `[{ "retain": 4 }, { "insert": "b" }, { "delete": 1 }]` turns `a = 1` into `a = b`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Op>", into = "Vec<Op>")]
+pub struct TextOperation {
+    ops: Vec<Op>,
+}
+
+impl From<Vec<Op>> for TextOperation {
+    // merges adjacent components and drops empty ones
+    fn from(ops: Vec<Op>) -> Self {
+        let mut operation = Self::new();
+        for op in ops {
+            match op {
+                Op::Retain(n) => operation.retain(n),
+                Op::Insert(text) => operation.insert(&text),
+                Op::Delete(n) => operation.delete(n),
+            };
+        }
+
+        operation
+    }
+}
+
+impl From<TextOperation> for Vec<Op> {
+    fn from(operation: TextOperation) -> Self {
+        operation.ops
+    }
+}
+
+impl TextOperation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the smallest operation turning `old` into `new`
+    pub fn replace(old: &str, new: &str) -> Self {
+        let old = old.chars().collect::<Vec<_>>();
+        let new = new.chars().collect::<Vec<_>>();
+
+        let prefix = old
+            .iter()
+            .zip(new.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut operation = Self::new();
+        operation.retain(prefix);
+        operation.delete(old.len() - prefix - suffix);
+        operation.insert(&new[prefix..new.len() - suffix].iter().collect::<String>());
+        operation.retain(suffix);
+
+        operation
+    }
+
+    pub fn retain(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        match self.ops.last_mut() {
+            Some(Op::Retain(last)) => *last += n,
+            _ => self.ops.push(Op::Retain(n)),
+        }
+        self
+    }
+
+    pub fn insert(&mut self, text: &str) -> &mut Self {
+        if text.is_empty() {
+            return self;
+        }
+
+        // inserts always go before deletes, so equal operations look the same
+        let len = self.ops.len();
+        match self.ops.as_mut_slice() {
+            [.., Op::Insert(last)] => last.push_str(text),
+            [.., Op::Insert(before), Op::Delete(_)] => before.push_str(text),
+            [.., Op::Delete(_)] => self.ops.insert(len - 1, Op::Insert(text.to_string())),
+            _ => self.ops.push(Op::Insert(text.to_string())),
+        }
+        self
+    }
+
+    pub fn delete(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        match self.ops.last_mut() {
+            Some(Op::Delete(last)) => *last += n,
+            _ => self.ops.push(Op::Delete(n)),
+        }
+        self
+    }
+
+    /// length of the content the operation can be applied to
+    pub fn base_len(&self) -> usize {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                Op::Retain(n) | Op::Delete(n) => *n,
+                Op::Insert(_) => 0,
+            })
+            .sum()
+    }
+
+    pub fn apply(&self, content: &str) -> Result<String, EditErrors> {
+        let mut chars = content.chars();
+        let mut result = String::with_capacity(content.len());
+
+        for op in self.ops.iter() {
+            match op {
+                Op::Retain(n) => {
+                    for _ in 0..*n {
+                        result.push(chars.next().ok_or(EditErrors::LengthMismatch)?);
+                    }
+                }
+                Op::Insert(text) => result.push_str(text),
+                Op::Delete(n) => {
+                    for _ in 0..*n {
+                        chars.next().ok_or(EditErrors::LengthMismatch)?;
+                    }
+                }
+            }
+        }
+
+        if chars.next().is_some() {
+            return Err(EditErrors::LengthMismatch);
+        }
+
+        Ok(result)
+    }
+
+    /// transforms two concurrent operations on the same content into `(a', b')`, such that
+    /// applying `a` then `b'` gives the same content as applying `b` then `a'`.
+    /// If both insert at the same position, the insert of `a` comes first
+    pub fn transform(a: &Self, b: &Self) -> Result<(Self, Self), EditErrors> {
+        if a.base_len() != b.base_len() {
+            return Err(EditErrors::LengthMismatch);
+        }
+
+        let mut a_prime = Self::new();
+        let mut b_prime = Self::new();
+
+        let mut ops_a = a.ops.iter().cloned();
+        let mut ops_b = b.ops.iter().cloned();
+        let mut op_a = ops_a.next();
+        let mut op_b = ops_b.next();
+
+        loop {
+            match (op_a.take(), op_b.take()) {
+                (None, None) => break,
+                (Some(Op::Insert(text)), other) => {
+                    a_prime.insert(&text);
+                    b_prime.retain(text.chars().count());
+                    op_a = ops_a.next();
+                    op_b = other;
+                }
+                (other, Some(Op::Insert(text))) => {
+                    a_prime.retain(text.chars().count());
+                    b_prime.insert(&text);
+                    op_a = other;
+                    op_b = ops_b.next();
+                }
+                (None, _) | (_, None) => return Err(EditErrors::LengthMismatch),
+                (Some(first), Some(second)) => {
+                    let (len_a, len_b) = (first.len(), second.len());
+                    let len = len_a.min(len_b);
+
+                    match (&first, &second) {
+                        (Op::Retain(_), Op::Retain(_)) => {
+                            a_prime.retain(len);
+                            b_prime.retain(len);
+                        }
+                        // both deleted the same characters
+                        (Op::Delete(_), Op::Delete(_)) => {}
+                        (Op::Delete(_), Op::Retain(_)) => {
+                            a_prime.delete(len);
+                        }
+                        (Op::Retain(_), Op::Delete(_)) => {
+                            b_prime.delete(len);
+                        }
+                        _ => unreachable!("inserts are handled above"),
+                    }
+
+                    op_a = first.shorten(len).or_else(|| ops_a.next());
+                    op_b = second.shorten(len).or_else(|| ops_b.next());
+                }
+            }
+        }
+
+        Ok((a_prime, b_prime))
+    }
+}
+
+impl Op {
+    fn len(&self) -> usize {
+        match self {
+            Op::Retain(n) | Op::Delete(n) => *n,
+            Op::Insert(text) => text.chars().count(),
+        }
+    }
+
+    /// what is left of a retain or delete after consuming `n` characters
+    fn shorten(self, n: usize) -> Option<Self> {
+        match self {
+            Op::Retain(len) if len > n => Some(Op::Retain(len - n)),
+            Op::Delete(len) if len > n => Some(Op::Delete(len - n)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converges(content: &str, a: &TextOperation, b: &TextOperation) -> String {
+        let (a_prime, b_prime) = TextOperation::transform(a, b).unwrap();
+        let ab = b_prime.apply(&a.apply(content).unwrap()).unwrap();
+        let ba = a_prime.apply(&b.apply(content).unwrap()).unwrap();
+        assert_eq!(ab, ba);
+
+        ab
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut operation = TextOperation::new();
+        operation.retain(4).insert("b").delete(1);
+
+        assert_eq!(operation.apply("a = 1").unwrap(), "a = b");
+        assert!(operation.apply("a = 12").is_err());
+        assert!(operation.apply("a =").is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let operation = TextOperation::replace("print(a)", "print(a, b)");
+        assert_eq!(
+            Vec::<Op>::from(operation.clone()),
+            vec![
+                Op::Retain(7),
+                Op::Insert(String::from(", b")),
+                Op::Retain(1)
+            ]
+        );
+        assert_eq!(operation.apply("print(a)").unwrap(), "print(a, b)");
+
+        let operation = TextOperation::replace("äöü", "äü");
+        assert_eq!(operation.apply("äöü").unwrap(), "äü");
+    }
+
+    #[test]
+    fn test_transform_concurrent_inserts() {
+        let content = "a = 1";
+        let a = TextOperation::replace(content, "a = 12");
+        let b = TextOperation::replace(content, "a = 13");
+
+        // a was applied first, so its insert comes first
+        assert_eq!(converges(content, &a, &b), "a = 123");
+    }
+
+    #[test]
+    fn test_transform_insert_and_delete() {
+        let content = "x = 1\ny = 2\n";
+        let a = TextOperation::replace(content, "x = 1\n");
+        let b = TextOperation::replace(content, "z = 0\nx = 1\ny = 2\n");
+        assert_eq!(converges(content, &a, &b), "z = 0\nx = 1\n");
+
+        // overlapping deletes
+        let a = TextOperation::replace(content, "x\ny = 2\n");
+        let b = TextOperation::replace(content, "x = 2\n");
+        assert_eq!(converges(content, &a, &b), "x2\n");
+    }
+
+    #[test]
+    fn test_transform_length_mismatch() {
+        let a = TextOperation::replace("abc", "ab");
+        let b = TextOperation::replace("abcd", "abc");
+        assert!(TextOperation::transform(&a, &b).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_normalizes() {
+        let operation: TextOperation = serde_json::from_str(
+            r#"[{ "retain": 1 }, { "retain": 2 }, { "delete": 1 }, { "insert": "x" }, { "insert": "" }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Vec::<Op>::from(operation),
+            vec![Op::Retain(3), Op::Insert(String::from("x")), Op::Delete(1)]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use super::{
+    display::DisplayData,
+    kernel_client::{CellStatus, MsgFromKernel},
+    traceback::Traceback,
+};
+use serde::{Deserialize, Serialize};
+
+/// what happened to a cell in the last headless run, saved with the notebook
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CellRun {
+    /// the last status of the cell, unset if it was never queued
+    pub status: Option<CellStatus>,
+
+    #[serde(default)]
+    pub outputs: Vec<DisplayData>,
+
+    #[serde(default)]
+    pub error: Option<String>,
+
+    #[serde(default)]
+    pub traceback: Option<Traceback>,
+
+    /// milliseconds the cell ran
+    #[serde(default)]
+    pub duration: Option<u64>,
+
+    /// the failed cell a blocked cell depends on
+    #[serde(default)]
+    pub blocked_by: Option<String>,
+}
+
+impl CellRun {
+    /// takes in a status or the results of the cell, queuing it starts a new run
+    pub fn update(&mut self, msg: &MsgFromKernel) {
+        match msg.status {
+            Some(CellStatus::Queued) => {
+                *self = Self {
+                    status: msg.status,
+                    ..Default::default()
+                }
+            }
+            Some(status) => {
+                self.status = Some(status);
+                self.duration = msg.duration.or(self.duration);
+                self.blocked_by = msg.blocked_by.clone();
+            }
+            // the kernel sends the outputs of every statement of the cell so far
+            None => {
+                self.outputs = msg.outputs.clone();
+                self.error = msg.error.clone();
+                self.traceback = msg.traceback.clone();
+            }
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.status == Some(CellStatus::Errored)
+    }
+}
+
+/// the result of a headless run of a notebook
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub title: String,
+
+    /// milliseconds since the unix epoch
+    pub started: u64,
+
+    /// milliseconds the whole run took
+    pub duration: u64,
+
+    /// number of cells which failed
+    pub failed: usize,
+
+    /// every cell which was part of the run, in display order
+    pub cells: Vec<CellReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CellReport {
+    /// position of the cell, starting at 1
+    pub number: usize,
+    pub cell_uuid: String,
+
+    #[serde(flatten)]
+    pub run: CellRun,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn status(status: CellStatus, duration: Option<u64>) -> MsgFromKernel {
+        MsgFromKernel {
+            status: Some(status),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_update_with_statements() {
+        let output = |text: &str| {
+            serde_json::from_value::<DisplayData>(json!({ "data": { "text/plain": text } }))
+                .unwrap()
+        };
+        let mut run = CellRun::default();
+        run.update(&status(CellStatus::Queued, None));
+        run.update(&status(CellStatus::Running, None));
+        run.update(&MsgFromKernel {
+            outputs: vec![output("1")],
+            ..Default::default()
+        });
+        run.update(&MsgFromKernel {
+            outputs: vec![output("1"), output("2")],
+            ..Default::default()
+        });
+        run.update(&status(CellStatus::Finished, Some(3)));
+        assert_eq!(run.outputs, vec![output("1"), output("2")]);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut run = CellRun::default();
+        run.update(&status(CellStatus::Queued, None));
+        run.update(&status(CellStatus::Running, None));
+        run.update(&MsgFromKernel {
+            outputs: vec![DisplayData::default()],
+            error: Some(String::from("ZeroDivisionError: division by zero")),
+            ..Default::default()
+        });
+        run.update(&status(CellStatus::Errored, Some(12)));
+        assert!(run.failed());
+        assert_eq!(run.duration, Some(12));
+        assert_eq!(run.outputs.len(), 1);
+
+        // the next run starts from scratch
+        run.update(&status(CellStatus::Queued, None));
+        assert_eq!(run.status, Some(CellStatus::Queued));
+        assert!(run.outputs.is_empty() && run.error.is_none() && run.duration.is_none());
+
+        let report = CellReport {
+            number: 1,
+            cell_uuid: String::from("a"),
+            run,
+        };
+        assert_eq!(
+            serde_json::to_value(report).unwrap()["status"],
+            json!("Queued")
+        );
+    }
+}
@@ -6,30 +6,35 @@ use serde::{Deserialize, Serialize};
 pub struct Statement {
     pub execution_type: ExecutionType,
     pub content: String,
+
+    /// lines of the cell the statement spans, 1-based and inclusive. The kernel maps line
+    /// numbers of tracebacks back to the cell with them
+    #[serde(default)]
+    pub start_line: usize,
+
+    #[serde(default)]
+    pub end_line: usize,
 }
 
 impl Statement {
     pub fn new_exec(start: &Location, end: &Location, content: &str) -> Self {
-        let content = Self::extract_content(start, end, content);
-        Self {
-            execution_type: ExecutionType::Exec,
-            content,
-        }
+        Self::new(ExecutionType::Exec, start, end, content)
     }
 
     pub fn new_eval(start: &Location, end: &Location, content: &str) -> Self {
-        let content = Self::extract_content(start, end, content);
-        Self {
-            execution_type: ExecutionType::Eval,
-            content,
-        }
+        Self::new(ExecutionType::Eval, start, end, content)
     }
 
     pub fn new_definition(start: &Location, end: &Location, content: &str) -> Self {
-        let content = Self::extract_content(start, end, content);
+        Self::new(ExecutionType::Definition, start, end, content)
+    }
+
+    fn new(execution_type: ExecutionType, start: &Location, end: &Location, content: &str) -> Self {
         Self {
-            execution_type: ExecutionType::Definition,
-            content,
+            execution_type,
+            content: Self::extract_content(start, end, content),
+            start_line: start.row(),
+            end_line: end.row(),
         }
     }
 
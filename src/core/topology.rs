@@ -1,10 +1,10 @@
 use super::notebook::Scope;
-use crate::core::{cell::Cell, errors::TopologyErrors};
-use serde::{Deserialize, Serialize};
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    error::Error,
+use crate::core::{
+    cell::Cell,
+    errors::{NotebookError, TopologyErrors},
 };
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Topology {
@@ -24,7 +24,7 @@ impl Topology {
         }
     }
 
-    pub fn from_vec(cells: Vec<Cell>, scope: &mut Scope) -> Result<Self, Box<dyn Error>> {
+    pub fn from_vec(cells: Vec<Cell>, scope: &mut Scope) -> Result<Self, NotebookError> {
         let mut topology = Topology::new();
         for cell in cells {
             topology.display_order.push(cell.uuid.clone());
@@ -46,9 +46,9 @@ impl Topology {
         cell_uuid: &str,
         next_content: &str,
         scope: &mut Scope,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), NotebookError> {
         if let Some(cell) = self.get_cell_mut(cell_uuid) {
-            if next_content == cell.content {
+            if next_content == cell.content && !cell.edited {
                 return Ok(());
             }
             cell.update_content(next_content, scope)?;
@@ -76,7 +76,7 @@ impl Topology {
             .collect()
     }
 
-    pub fn build(&mut self, scope: &mut Scope) -> Result<(), Box<dyn Error>> {
+    pub fn build(&mut self, scope: &mut Scope) -> Result<(), NotebookError> {
         self.dependencies.clear();
         self.dependents.clear();
 
@@ -89,12 +89,12 @@ impl Topology {
 
                     self.dependents
                         .entry(other_uuid.clone())
-                        .or_insert_with(HashSet::new)
+                        .or_default()
                         .insert(cell.uuid.clone());
 
                     self.dependencies
                         .entry(cell.uuid.clone())
-                        .or_insert_with(HashSet::new)
+                        .or_default()
                         .insert(other_uuid.clone());
                 }
             }
@@ -103,35 +103,112 @@ impl Topology {
         Ok(())
     }
 
-    pub fn execution_seq(&self, cell_uuid: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        let cell = match self.cells.get(cell_uuid) {
-            Some(cell) => cell,
-            None => return Err(Box::new(TopologyErrors::CellNotFound)),
-        };
+    /// the cell, the stale cells it directly depends on and every cell transitively depending
+    /// on it, in topological order without the disabled ones. dependencies which already ran
+    /// keep their values in the kernel and are not run again
+    pub fn execution_seq(&self, cell_uuid: &str) -> Result<Vec<String>, NotebookError> {
+        if !self.cells.contains_key(cell_uuid) {
+            return Err(TopologyErrors::CellNotFound.into());
+        }
+
+        let mut cells = self.with_dependents(&HashSet::from([cell_uuid.to_string()]));
+        cells.extend(
+            self.dependencies
+                .get(cell_uuid)
+                .into_iter()
+                .flatten()
+                .filter(|uuid| self.cells.get(*uuid).is_some_and(|cell| cell.stale))
+                .cloned(),
+        );
+
+        self.execution_order(&cells)
+    }
+
+    /// cells which are disabled or depend on a disabled cell
+    pub fn disabled_cells(&self) -> HashSet<String> {
+        let disabled = self
+            .cells
+            .values()
+            .filter(|cell| cell.metadata.disabled)
+            .map(|cell| cell.uuid.clone())
+            .collect();
 
-        let mut dependencies = self.get_dependencies(cell_uuid);
-        let mut dependents = self.get_dependents(cell_uuid);
+        self.with_dependents(&disabled)
+    }
 
-        let mut nodes = Vec::with_capacity(dependencies.len() + dependents.len() + 1);
-        nodes.push(cell);
-        nodes.append(&mut dependencies);
-        nodes.append(&mut dependents);
+    fn without_disabled(&self, cell_uuids: Vec<String>) -> Vec<String> {
+        let disabled = self.disabled_cells();
+        cell_uuids
+            .into_iter()
+            .filter(|uuid| !disabled.contains(uuid))
+            .collect()
+    }
 
+    /// the cells and every cell they transitively depend on
+    pub fn with_dependencies(&self, cell_uuids: &HashSet<String>) -> HashSet<String> {
+        Self::closure(cell_uuids, &self.dependencies)
+    }
+
+    /// the cells and every cell transitively depending on them
+    pub fn with_dependents(&self, cell_uuids: &HashSet<String>) -> HashSet<String> {
+        Self::closure(cell_uuids, &self.dependents)
+    }
+
+    /// cells which depend on themselves through other cells
+    pub fn cyclic_cells(&self) -> HashSet<String> {
+        self.dependencies
+            .iter()
+            .filter(|(cell_uuid, dependencies)| {
+                Self::closure(dependencies, &self.dependencies).contains(*cell_uuid)
+            })
+            .map(|(cell_uuid, _)| cell_uuid.clone())
+            .collect()
+    }
+
+    fn closure(
+        cell_uuids: &HashSet<String>,
+        edges: &HashMap<String, HashSet<String>>,
+    ) -> HashSet<String> {
+        let mut reached = cell_uuids.clone();
+        let mut queue = cell_uuids.iter().cloned().collect::<VecDeque<_>>();
+        while let Some(cell_uuid) = queue.pop_front() {
+            for other in edges.get(&cell_uuid).into_iter().flatten() {
+                if reached.insert(other.clone()) {
+                    queue.push_back(other.clone());
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// a topological order of the given cells without the disabled ones, cells without an
+    /// order between them keep their display order
+    pub fn execution_order(
+        &self,
+        cell_uuids: &HashSet<String>,
+    ) -> Result<Vec<String>, NotebookError> {
         let mut update_topology = Self::new();
         update_topology.dependencies = self.dependencies.clone();
         update_topology.dependents = self.dependents.clone();
-        for node in nodes {
+        for cell_uuid in self.display_order.iter() {
+            if !cell_uuids.contains(cell_uuid) {
+                continue;
+            }
             update_topology
                 .cells
-                .insert(node.uuid.clone(), node.clone());
-            update_topology.display_order.push(node.uuid.clone());
+                .insert(cell_uuid.clone(), self.cells[cell_uuid].clone());
+            update_topology.display_order.push(cell_uuid.clone());
+        }
+        if update_topology.cells.len() != cell_uuids.len() {
+            return Err(TopologyErrors::CellNotFound.into());
         }
 
         let sorted = update_topology.topological_sort()?;
-        Ok(sorted)
+        Ok(self.without_disabled(sorted))
     }
 
-    fn topological_sort(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    fn topological_sort(&self) -> Result<Vec<String>, NotebookError> {
         let mut sorted = vec![];
 
         let cells = self.cells.clone();
@@ -142,17 +219,17 @@ impl Topology {
         for cell_uuid in self.display_order.iter() {
             let cell = match cells.get(cell_uuid) {
                 Some(cell) => cell,
-                None => return Err(Box::new(TopologyErrors::CellNotFound)),
+                None => return Err(TopologyErrors::CellNotFound.into()),
             };
 
-            let dependencies = self.get_dependencies(&cell_uuid);
+            let dependencies = self.get_dependencies(cell_uuid);
             degree.insert(cell_uuid.clone(), (dependencies.len(), cell));
         }
 
-        // queue of cells with no dependencies
+        // queue of cells with no dependencies, in display order
         let mut queue = VecDeque::new();
-        for (_cell_uuid, (degree, cell)) in degree.iter() {
-            if *degree == 0 {
+        for cell_uuid in self.display_order.iter() {
+            if let Some((0, cell)) = degree.get(cell_uuid) {
                 queue.push_back(*cell);
             }
         }
@@ -178,20 +255,68 @@ impl Topology {
         }
 
         if count != nodes.len() {
-            return Err(Box::new(TopologyErrors::CycleDetected));
+            return Err(TopologyErrors::CycleDetected.into());
         }
 
         Ok(sorted)
     }
 
-    pub fn reorder_cells(&mut self, cell_uuids: &[String]) {
+    pub fn reorder_cells(&mut self, cell_uuids: &[String]) -> Result<(), NotebookError> {
+        let unique = cell_uuids.iter().collect::<HashSet<_>>();
+        if unique.len() != cell_uuids.len()
+            || cell_uuids.len() != self.cells.len()
+            || !cell_uuids.iter().all(|uuid| self.cells.contains_key(uuid))
+        {
+            return Err(TopologyErrors::InvalidOrder.into());
+        }
+
         self.display_order = cell_uuids.to_vec();
+        Ok(())
+    }
+
+    /// inserts the cell at `index` of the display order, or at the end if `index` is too large.
+    /// Returns the index the cell ended up at, a cell closing a cycle is not inserted
+    pub fn insert_cell(
+        &mut self,
+        index: usize,
+        cell: Cell,
+        scope: &mut Scope,
+    ) -> Result<usize, NotebookError> {
+        let index = index.min(self.display_order.len());
+        let cell_uuid = cell.uuid.clone();
+        self.display_order.insert(index, cell_uuid.clone());
+        self.cells.insert(cell_uuid.clone(), cell);
+        self.build(scope)?;
+
+        if let Err(e) = self.topological_sort() {
+            self.remove_cell(&cell_uuid, scope)?;
+            return Err(e);
+        }
+
+        Ok(index)
+    }
+
+    pub fn remove_cell(
+        &mut self,
+        cell_uuid: &str,
+        scope: &mut Scope,
+    ) -> Result<Cell, NotebookError> {
+        let cell = self
+            .cells
+            .remove(cell_uuid)
+            .ok_or(TopologyErrors::CellNotFound)?;
+        self.display_order.retain(|uuid| uuid != cell_uuid);
+        scope.retain(|_, uuid| uuid != cell_uuid);
+        self.build(scope)?;
+
+        Ok(cell)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::error::Error;
 
     #[test]
     fn test_trivial_deps() {
@@ -264,7 +389,7 @@ mod tests {
 
         let cell_uuid_2 = code_cell_2.uuid.clone();
         let code_cell_1_clone = code_cell_1.clone();
-        let expected_deps = vec![&code_cell_1_clone];
+        let expected_deps = [&code_cell_1_clone];
 
         let topology = Topology::from_vec(vec![code_cell_1, code_cell_2], &mut scope).unwrap();
 
@@ -280,7 +405,7 @@ mod tests {
 
         let cell_uuid_1 = code_cell_1.uuid.clone();
         let code_cell_2_clone = code_cell_2.clone();
-        let expected_deps = vec![&code_cell_2_clone];
+        let expected_deps = [&code_cell_2_clone];
 
         let topology = Topology::from_vec(vec![code_cell_1, code_cell_2], &mut scope).unwrap();
 
@@ -368,7 +493,95 @@ mod tests {
             &mut scope,
         );
         assert!(topology.is_err());
-        assert!(topology.err().unwrap().is::<TopologyErrors>());
+        assert!(matches!(
+            topology.err().unwrap(),
+            NotebookError::Topology(TopologyErrors::CycleDetected)
+        ));
+    }
+
+    #[test]
+    fn test_cyclic_cells() {
+        let mut scope = HashMap::new();
+        let code_cell_1 = Cell::new_reactive("a = 1", &mut scope).unwrap();
+        let code_cell_2 = Cell::new_reactive("b = a + c", &mut scope).unwrap();
+        let code_cell_3 = Cell::new_reactive("c = b", &mut scope).unwrap();
+        let code_cell_4 = Cell::new_reactive("d = c", &mut scope).unwrap();
+        let expected = HashSet::from([code_cell_2.uuid.clone(), code_cell_3.uuid.clone()]);
+
+        // inserting cells checks for cycles, loaded notebooks may have them anyway
+        let mut topology = Topology::new();
+        for cell in [code_cell_1, code_cell_2, code_cell_3, code_cell_4] {
+            topology.display_order.push(cell.uuid.clone());
+            topology.cells.insert(cell.uuid.clone(), cell);
+        }
+        topology.build(&mut scope).unwrap();
+
+        assert_eq!(topology.cyclic_cells(), expected);
+    }
+
+    #[test]
+    fn test_execution_order_of_closures() {
+        let mut scope = HashMap::new();
+        let code_cell_1 = Cell::new_reactive("a = 1", &mut scope).unwrap();
+        let code_cell_2 = Cell::new_reactive("c = b + 1", &mut scope).unwrap();
+        let code_cell_3 = Cell::new_reactive("b = a", &mut scope).unwrap();
+        let code_cell_4 = Cell::new_reactive("d = 4", &mut scope).unwrap();
+        let uuids =
+            [&code_cell_1, &code_cell_2, &code_cell_3, &code_cell_4].map(|cell| cell.uuid.clone());
+
+        let topology = Topology::from_vec(
+            vec![code_cell_1, code_cell_2, code_cell_3, code_cell_4],
+            &mut scope,
+        )
+        .unwrap();
+
+        let needed = topology.with_dependencies(&HashSet::from([uuids[1].clone()]));
+        assert_eq!(
+            topology.execution_order(&needed).unwrap(),
+            vec![uuids[0].clone(), uuids[2].clone(), uuids[1].clone()]
+        );
+
+        let affected = topology.with_dependents(&HashSet::from([uuids[2].clone()]));
+        assert_eq!(
+            topology.execution_order(&affected).unwrap(),
+            vec![uuids[2].clone(), uuids[1].clone()]
+        );
+
+        let unknown = HashSet::from([String::from("unknown")]);
+        assert!(topology.execution_order(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_disabled_cells_are_skipped() {
+        let mut scope = HashMap::new();
+        let code_cell_1 = Cell::new_reactive("a = 1", &mut scope).unwrap();
+        let mut code_cell_2 = Cell::new_reactive("b = a", &mut scope).unwrap();
+        let code_cell_3 = Cell::new_reactive("c = b", &mut scope).unwrap();
+        let code_cell_4 = Cell::new_reactive("d = a", &mut scope).unwrap();
+        code_cell_2.metadata.disabled = true;
+        let uuids =
+            [&code_cell_1, &code_cell_2, &code_cell_3, &code_cell_4].map(|cell| cell.uuid.clone());
+
+        let topology = Topology::from_vec(
+            vec![code_cell_1, code_cell_2, code_cell_3, code_cell_4],
+            &mut scope,
+        )
+        .unwrap();
+
+        // the dependent of the disabled cell is disabled as well
+        assert_eq!(
+            topology.disabled_cells(),
+            HashSet::from([uuids[1].clone(), uuids[2].clone()])
+        );
+        assert_eq!(
+            topology.execution_seq(&uuids[0]).unwrap(),
+            vec![uuids[0].clone(), uuids[3].clone()]
+        );
+        let all = uuids.iter().cloned().collect();
+        assert_eq!(
+            topology.execution_order(&all).unwrap(),
+            vec![uuids[0].clone(), uuids[3].clone()]
+        );
     }
 
     #[test]
@@ -384,7 +597,31 @@ mod tests {
             &mut scope,
         );
         assert!(topology.is_err());
-        assert!(topology.err().unwrap().is::<TopologyErrors>());
+        assert!(matches!(
+            topology.err().unwrap(),
+            NotebookError::Topology(TopologyErrors::CycleDetected)
+        ));
+    }
+
+    #[test]
+    fn test_insert_cell_cycle_detected() {
+        let mut scope = HashMap::new();
+        let code_cell_1 = Cell::new_reactive("a = b", &mut scope).unwrap();
+        let code_cell_2 = Cell::new_reactive("c = 1", &mut scope).unwrap();
+        let mut topology = Topology::from_vec(vec![code_cell_1, code_cell_2], &mut scope).unwrap();
+
+        let code_cell_3 = Cell::new_reactive("b = a", &mut scope).unwrap();
+        let res = topology.insert_cell(1, code_cell_3, &mut scope);
+        assert!(matches!(
+            res,
+            Err(NotebookError::Topology(TopologyErrors::CycleDetected))
+        ));
+        assert_eq!(topology.display_order.len(), 2);
+        assert!(!scope.contains_key("b"));
+        assert!(topology.topological_sort().is_ok());
+
+        let code_cell_4 = Cell::new_reactive("d = c", &mut scope).unwrap();
+        assert_eq!(topology.insert_cell(5, code_cell_4, &mut scope).unwrap(), 2);
     }
 
     #[test]
@@ -409,6 +646,28 @@ mod tests {
         assert_eq!(execution_seq, expected_seq);
     }
 
+    #[test]
+    fn test_execution_seq_reaches_transitive_dependents() {
+        let mut scope = HashMap::new();
+        let code_cell_1 = Cell::new_reactive("c = b * 2", &mut scope).unwrap();
+        let code_cell_2 = Cell::new_reactive("b = a + 1", &mut scope).unwrap();
+        let code_cell_3 = Cell::new_reactive("a = 1", &mut scope).unwrap();
+        let uuids = [&code_cell_3, &code_cell_2, &code_cell_1].map(|cell| cell.uuid.clone());
+
+        let mut topology =
+            Topology::from_vec(vec![code_cell_1, code_cell_2, code_cell_3], &mut scope).unwrap();
+
+        assert_eq!(topology.execution_seq(&uuids[0]).unwrap(), uuids.to_vec());
+        assert_eq!(topology.execution_seq(&uuids[1]).unwrap(), uuids.to_vec());
+
+        // a dependency which already ran is not run again
+        topology.cells.get_mut(&uuids[0]).unwrap().stale = false;
+        assert_eq!(
+            topology.execution_seq(&uuids[1]).unwrap(),
+            uuids[1..].to_vec()
+        );
+    }
+
     #[test]
     fn test_execution_seq_2() {
         let mut scope = HashMap::new();
@@ -438,9 +697,10 @@ mod tests {
 
         let topology = Topology::from_vec(vec![cell_1, cell_2], &mut scope)?;
 
-        let expected_dependencies = vec![cell_1_uuid.clone()];
+        let expected_dependencies = [cell_1_uuid.clone()];
         let dependencies = topology.get_dependencies(&cell_2_uuid);
-        Ok(assert_eq!(dependencies[0].uuid, expected_dependencies[0]))
+        assert_eq!(dependencies[0].uuid, expected_dependencies[0]);
+        Ok(())
     }
 
     #[test]
@@ -457,9 +717,10 @@ mod tests {
         assert_eq!(scope.get("a").unwrap(), &cell_1_uuid);
         assert_eq!(scope.get("b").unwrap(), &cell_2_uuid);
 
-        let expected_dependencies = vec![cell_1_uuid.clone()];
+        let expected_dependencies = [cell_1_uuid.clone()];
         let dependencies = topology.get_dependencies(&cell_2_uuid);
-        Ok(assert_eq!(dependencies[0].uuid, expected_dependencies[0]))
+        assert_eq!(dependencies[0].uuid, expected_dependencies[0]);
+        Ok(())
     }
 
     // #[test]
@@ -1030,7 +1291,7 @@ mod tests {
 
         let topology = Topology::from_vec(vec![cell_1, cell_2], &mut scope).unwrap();
 
-        let expected_dependencies = vec![cell_1_uuid.clone()];
+        let expected_dependencies = [cell_1_uuid.clone()];
         let dependencies = topology.get_dependencies(&cell_2_uuid);
         assert_eq!(dependencies[0].uuid, expected_dependencies[0]);
     }
@@ -1046,7 +1307,7 @@ mod tests {
 
         let topology = Topology::from_vec(vec![cell_1, cell_2], &mut scope).unwrap();
 
-        let expected_dependents = vec![cell_1_uuid.clone()];
+        let expected_dependents = [cell_1_uuid.clone()];
         let dependencies = topology.get_dependents(&cell_2_uuid);
         assert_eq!(dependencies[0].uuid, expected_dependents[0]);
     }
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// an exception raised while running a cell, line numbers are lines of the cells
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Traceback {
+    /// type of the exception, e.g. `ZeroDivisionError`
+    pub ename: String,
+    pub evalue: String,
+
+    /// from the statement of the cell down to where the exception was raised
+    pub frames: Vec<Frame>,
+
+    /// lines of the statement which raised the exception, 1-based and inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl Traceback {
+    /// only the type of the exception, the message, frames and lines may quote the code
+    pub fn redact(&mut self) {
+        self.evalue.clear();
+        self.frames.clear();
+        self.start_line = 0;
+        self.end_line = 0;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    /// set if the code of the frame is part of a cell, e.g. a function defined in another cell
+    #[serde(default)]
+    pub cell_uuid: Option<String>,
+
+    /// file of frames outside of the notebook
+    pub filename: String,
+    pub name: String,
+    pub line: usize,
+
+    /// source of the line, if it is known
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::kernel_client::MsgFromKernel;
+
+    #[test]
+    fn test_deserialize_from_pickle() {
+        let traceback = Traceback {
+            ename: String::from("ZeroDivisionError"),
+            evalue: String::from("division by zero"),
+            frames: vec![Frame {
+                cell_uuid: Some(String::from("abc")),
+                filename: String::from("<cell abc line 2>"),
+                name: String::from("<module>"),
+                line: 3,
+                source: Some(String::from("1 / 0")),
+            }],
+            start_line: 2,
+            end_line: 3,
+        };
+
+        let pickled = serde_pickle::to_vec(&traceback, Default::default()).unwrap();
+        let unpickled: Traceback = serde_pickle::from_slice(&pickled, Default::default()).unwrap();
+        assert_eq!(unpickled, traceback);
+    }
+
+    #[test]
+    fn test_deserialize_from_kernel() {
+        // an error message of the kernel, pickled by it like dill does: a cell calls a function
+        // of another cell which divides by zero
+        let pickled = include_bytes!("fixtures/error.pickle");
+        let msg: MsgFromKernel = serde_pickle::from_slice(pickled, Default::default()).unwrap();
+        assert_eq!(msg.error.as_deref(), Some("division by zero"));
+        assert_eq!(msg.run_id, 4);
+        assert_eq!(msg.origin.request_id.as_deref(), Some("7"));
+
+        let traceback = msg.traceback.unwrap();
+        assert_eq!(traceback.ename, "ZeroDivisionError");
+        assert_eq!((traceback.start_line, traceback.end_line), (3, 3));
+        assert_eq!(
+            traceback.frames,
+            vec![
+                Frame {
+                    cell_uuid: Some(String::from("use-cell")),
+                    filename: String::from("<cell use-cell line 3>"),
+                    name: String::from("<module>"),
+                    line: 3,
+                    source: Some(String::from("ratio(1, 0)")),
+                },
+                Frame {
+                    cell_uuid: Some(String::from("def-cell")),
+                    filename: String::from("<cell def-cell line 1>"),
+                    name: String::from("ratio"),
+                    line: 2,
+                    source: Some(String::from("return a / b")),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use super::{cell::LocalValue, kernel_client::Origin};
+use actix::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// what the client gets to see of a value living in the kernel
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValueSummary {
+    pub type_name: String,
+    pub repr: String,
+
+    /// repr was cut off, the full value can be inspected via its handle
+    #[serde(default)]
+    pub truncated: bool,
+
+    #[serde(default)]
+    pub length: Option<usize>,
+
+    /// e.g. shape of numpy arrays or pandas dataframes
+    #[serde(default)]
+    pub shape: Option<Vec<usize>>,
+
+    /// the value has children (items, elements, attributes) which can be inspected
+    #[serde(default)]
+    pub expandable: bool,
+}
+
+/// child of an inspected value, key is a dict key, list index or attribute name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueEntry {
+    pub key: Value,
+    pub summary: ValueSummary,
+}
+
+/// lazily fetch the children `start..end` of the value behind `handle`,
+/// `path` walks into nested structures (keys, indices or attribute names)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InspectRequest {
+    #[serde(default)]
+    pub notebook_uuid: String,
+    pub handle: String,
+
+    #[serde(default)]
+    pub path: Vec<Value>,
+
+    #[serde(default)]
+    pub start: usize,
+
+    #[serde(default)]
+    pub end: Option<usize>,
+
+    #[serde(default)]
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InspectResponse {
+    pub notebook_uuid: String,
+    pub handle: String,
+
+    #[serde(default)]
+    pub path: Vec<Value>,
+
+    #[serde(default)]
+    pub summary: Option<ValueSummary>,
+
+    #[serde(default)]
+    pub children: Vec<ValueEntry>,
+
+    #[serde(default)]
+    pub error: Option<String>,
+
+    #[serde(default)]
+    pub origin: Origin,
+}
+
+impl Message for InspectResponse {
+    type Result = ();
+}
+
+/// everything the kernel holds for a notebook, cell_uuid -> name -> local
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KernelNamespace {
+    pub notebook_uuid: String,
+    pub cells: HashMap<String, HashMap<String, LocalValue>>,
+
+    #[serde(default)]
+    pub origin: Origin,
+}
+
+impl Message for KernelNamespace {
+    type Result = ();
+}
+
+/// the values of names (name -> uuid of the defining cell) in a private namespace, sent back
+/// as json
+#[derive(Debug, Clone, Serialize)]
+pub struct ValuesRequest {
+    pub notebook_uuid: String,
+    pub namespace: String,
+    pub names: HashMap<String, String>,
+}
+
+/// a global binding of a notebook as shown in the variable explorer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+
+    /// uuid of the cell binding the variable
+    pub cell_uuid: String,
+
+    /// `None` if the defining cell has not been executed yet
+    pub handle: Option<String>,
+    pub summary: Option<ValueSummary>,
+
+    /// uuids of the cells using the variable
+    pub dependents: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_inspect_response_from_pickle() {
+        let response = InspectResponse {
+            notebook_uuid: String::from("notebook"),
+            handle: String::from("handle"),
+            path: vec![json!("rows"), json!(0)],
+            summary: Some(ValueSummary {
+                type_name: String::from("numpy.ndarray"),
+                repr: String::from("array([1, 2, 3, ...])"),
+                truncated: true,
+                length: Some(1000),
+                shape: Some(vec![1000]),
+                expandable: true,
+            }),
+            children: vec![ValueEntry {
+                key: json!(0),
+                summary: ValueSummary {
+                    type_name: String::from("numpy.int64"),
+                    repr: String::from("1"),
+                    ..Default::default()
+                },
+            }],
+            error: None,
+            origin: Default::default(),
+        };
+
+        let pickled = serde_pickle::to_vec(&response, Default::default()).unwrap();
+        let unpickled: InspectResponse =
+            serde_pickle::from_slice(&pickled, Default::default()).unwrap();
+        assert_eq!(unpickled.path, response.path);
+        assert_eq!(unpickled.summary, response.summary);
+        assert_eq!(unpickled.children, response.children);
+    }
+
+    #[test]
+    fn test_summary_missing_fields() {
+        let summary: ValueSummary =
+            serde_json::from_value(json!({ "type_name": "builtins.int", "repr": "1" })).unwrap();
+        assert_eq!(summary.length, None);
+        assert!(!summary.expandable);
+    }
+}
@@ -1,4 +1,6 @@
 mod api;
+mod cli;
+mod config;
 mod core;
 
 use actix_cors::Cors;
@@ -8,41 +10,199 @@ use actix_web::{
     App, HttpServer,
 };
 use api::{
-    routes::{notebook_routes, ws_routes},
+    auth::{self, Auth},
+    routes::{client_files, notebook_routes, ws_routes},
     state::State,
 };
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
+use core::{errors::NotebookError, format::Format, notebook::Notebook};
 use dotenv::dotenv;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tracing::{error, info, warn, Level};
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
     dotenv().ok();
 
-    let api_port = std::env::var("API_PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a number");
-    let client_url = std::env::var("CLIENT_URL").expect("CLIENT_URL must be set");
-
-    let data = Data::new(State::new());
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin(&client_url)
-            .allowed_methods(vec!["GET", "POST"])
-            .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
-            .allowed_header(http::header::CONTENT_TYPE)
-            .max_age(3600);
-
-        App::new()
-            .app_data(Data::clone(&data))
-            .wrap(cors)
-            .wrap(actix_web::middleware::Logger::new(
-                "Router: %r, Status: %s, Time: %Dms",
-            ))
-            .service(web::scope("/api").configure(notebook_routes))
-            .service(web::scope("/ws").configure(ws_routes))
+    let cli = Cli::parse();
+    let config_path = cli.config.clone();
+    let res = match cli.command() {
+        Command::Serve(args) => {
+            tracing_subscriber::fmt::init();
+            let res = Config::load(config_path.as_deref()).and_then(|mut config| {
+                config.apply(args);
+                config.validate()?;
+                Ok(config)
+            });
+            let res = match res {
+                Ok(config) => serve(config).await,
+                Err(e) => Err(e),
+            };
+            return match res {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    error!("{}", e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        // logs would get in between the results
+        command => {
+            tracing_subscriber::fmt()
+                .with_max_level(Level::ERROR)
+                .with_writer(std::io::stderr)
+                .init();
+            match command {
+                Command::Run(args) => Config::load(config_path.as_deref()).and_then(|config| {
+                    config.validate()?;
+                    cli::run(&args, &config)
+                }),
+                Command::Export(args) => cli::export(&args),
+                Command::Check(args) => cli::check(&args),
+                Command::Serve(_) => unreachable!(),
+            }
+        }
+    };
+
+    res.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ExitCode::FAILURE
+    })
+}
+
+async fn serve(config: Config) -> Result<(), NotebookError> {
+    let auth = Auth::load(config.auth.token.clone(), config.auth.users.as_deref())?;
+    let server = config.server.clone();
+    let url = server
+        .client_url
+        .clone()
+        .unwrap_or_else(|| format!("http://{}:{}", server.host, server.port));
+    info!("Open the notebook at {}/?token={}", url, auth.token);
+
+    let mut state = State::new(auth, &config)?;
+    let mut opened = match &server.notebook_dir {
+        Some(notebook_dir) => open_dir(&state, notebook_dir)?,
+        None => HashMap::new(),
+    };
+    for path in config.auth.publish.iter() {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| NotebookError::persistence(path, e))?;
+        let notebook_uuid = match opened.get(&canonical) {
+            Some(notebook_uuid) => notebook_uuid.clone(),
+            None => open(&state, path, &mut opened)?,
+        };
+        info!("Published {:?}", path);
+        state.auth.published.insert(notebook_uuid);
+    }
+    let data = Data::new(state);
+
+    let client_dir = server.client_dir();
+    if let Some(dir) = &client_dir {
+        info!("Serving the client from {:?}", dir);
+    }
+
+    // the client url may always call the server
+    let origins = server
+        .client_url
+        .iter()
+        .chain(server.allowed_origins.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+    HttpServer::new({
+        let data = Data::clone(&data);
+        move || {
+            let mut cors = Cors::default()
+                .allowed_methods(vec!["GET", "POST"])
+                .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
+                .allowed_header(http::header::CONTENT_TYPE)
+                .max_age(3600);
+            for origin in origins.iter() {
+                cors = match origin.as_str() {
+                    "*" => cors.allow_any_origin(),
+                    origin => cors.allowed_origin(origin),
+                };
+            }
+
+            let app = App::new()
+                .app_data(Data::clone(&data))
+                .wrap(cors)
+                // the path without the query, tokens may be passed in it
+                .wrap(
+                    actix_web::middleware::Logger::new(
+                        "Router: %{METHOD}xi %U, Status: %s, Time: %Dms",
+                    )
+                    .custom_request_replace("METHOD", |req| req.method().to_string()),
+                )
+                .service(
+                    web::scope("/api")
+                        .wrap_fn(auth::authenticate)
+                        .configure(notebook_routes),
+                )
+                .service(
+                    web::scope("/ws")
+                        .wrap_fn(auth::authenticate)
+                        .configure(ws_routes),
+                );
+            // after the api, the client would take every path otherwise
+            match &client_dir {
+                Some(dir) => app.service(client_files(dir)),
+                None => app,
+            }
+        }
     })
-    .bind(("127.0.0.1", api_port))?
+    .bind((server.host.as_str(), server.port))
+    .map_err(|e| {
+        NotebookError::Internal(format!(
+            "Could not listen on {}:{}: {}",
+            server.host, server.port, e
+        ))
+    })?
     .run()
     .await
+    .map_err(|e| NotebookError::Internal(e.to_string()))?;
+
+    data.shutdown()
+}
+
+/// opens every notebook of the directory, files which are no notebooks are skipped. Returns
+/// the uuids of the opened notebooks by their canonical path
+fn open_dir(state: &State, dir: &Path) -> Result<HashMap<PathBuf, String>, NotebookError> {
+    let mut opened = HashMap::new();
+    let entries = std::fs::read_dir(dir).map_err(|e| NotebookError::persistence(dir, e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| NotebookError::persistence(dir, e))?
+            .path();
+        if Format::of(&path).is_err() {
+            continue;
+        }
+
+        if let Err(e) = open(state, &path, &mut opened) {
+            warn!("Could not open {:?}: {}", path, e);
+        }
+    }
+
+    Ok(opened)
+}
+
+fn open(
+    state: &State,
+    path: &Path,
+    opened: &mut HashMap<PathBuf, String>,
+) -> Result<String, NotebookError> {
+    let notebook_uuid = state.open(Notebook::load(path)?)?;
+    info!("Opened {:?} as {}", path, notebook_uuid);
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| NotebookError::persistence(path, e))?;
+    opened.insert(canonical, notebook_uuid.clone());
+
+    Ok(notebook_uuid)
 }